@@ -0,0 +1,36 @@
+//! Draws every lexer error of a file with ariadne, colored when printing to a terminal.
+//!
+//! Run with `cargo run --example ariadne_report --features ariadne`.
+use std::io::IsTerminal;
+use zigzin::diagnostic::ariadne::report_errors;
+use zigzin::lexer::{read_accept_states_from_json, read_transitions_from_csv, DFA};
+use zigzin::scanner::next_token;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let dfa = DFA::try_new(
+        read_transitions_from_csv("automato/DFA-transitions.csv")?,
+        0,
+        read_accept_states_from_json("automato/DFA-final-states.json")?,
+    )?;
+
+    let source = "pub fn main() void {\n    var pi = 3.14 @ 2;\n    var tag = #x;\n}\n";
+    let chars: Vec<char> = source.chars().collect();
+    // Scan token by token, skipping the offending character after an error to find the next one.
+    let mut errors = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        if chars[index].is_whitespace() {
+            index += 1;
+            continue;
+        }
+        match next_token(&dfa, &chars, index) {
+            Ok((_, next)) => index = next,
+            Err(err) => {
+                errors.push(err);
+                index += 1;
+            }
+        }
+    }
+    print!("{}", report_errors("example.zig", source, &errors, std::io::stdout().is_terminal()));
+    Ok(())
+}
@@ -0,0 +1,18 @@
+//! Renders a lexer error as a codespan-style diagnostic.
+//!
+//! Run with `cargo run --example diagnostic --features diagnostic`.
+use zigzin::lexer::{read_accept_states_from_json, read_transitions_from_csv, try_process_input, DFA};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let dfa = DFA::try_new(
+        read_transitions_from_csv("automato/DFA-transitions.csv")?,
+        0,
+        read_accept_states_from_json("automato/DFA-final-states.json")?,
+    )?;
+
+    let source = "pub fn main() void {\n    var pi = 3.14 @ 2;\n}\n";
+    if let Err(err) = try_process_input(&dfa, source) {
+        print!("{}", err.to_diagnostic().render("example.zig", source));
+    }
+    Ok(())
+}
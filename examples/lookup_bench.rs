@@ -0,0 +1,70 @@
+//! Compares transition lookup strategies on the ZigZin automaton.
+//!
+//! Run with `cargo run --release --example lookup_bench`.
+use std::time::Instant;
+use zigzin::lexer::{read_accept_states_from_json, read_transitions_from_csv, try_process_input, DFA};
+use zigzin::scanner::{scan, Transitions};
+
+/// A dense `state x ASCII` table: the fastest lookup, but its size grows with the alphabet.
+struct DenseDfa {
+    start: usize,
+    table: Vec<[Option<usize>; 128]>,
+    accept: Vec<Option<String>>,
+}
+
+impl DenseDfa {
+    fn new(dfa: &DFA) -> Self {
+        let states = dfa.transitions.iter().map(|(&(from, _), &to)| from.max(to)).max().unwrap_or(0) + 1;
+        let mut table = vec![[None; 128]; states];
+        for (&(from, ch), &to) in &dfa.transitions {
+            if ch.is_ascii() {
+                table[from][ch as usize] = Some(to);
+            }
+        }
+        let mut accept = vec![None; states];
+        for (&state, label) in &dfa.accept {
+            accept[state] = Some(label.clone());
+        }
+        DenseDfa { start: dfa.start, table, accept }
+    }
+}
+
+impl Transitions for DenseDfa {
+    fn start(&self) -> usize {
+        self.start
+    }
+
+    fn next_state(&self, state: usize, ch: char) -> Option<usize> {
+        if ch.is_ascii() { self.table[state][ch as usize] } else { None }
+    }
+
+    fn accept_label(&self, state: usize) -> Option<&str> {
+        self.accept[state].as_deref()
+    }
+}
+
+fn time<T>(name: &str, runs: u32, mut f: impl FnMut() -> T) {
+    let start = Instant::now();
+    for _ in 0..runs {
+        std::hint::black_box(f());
+    }
+    println!("{:<10} {:>8.2?} per run", name, start.elapsed() / runs);
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let dfa = DFA::try_new(
+        read_transitions_from_csv("automato/DFA-transitions.csv")?,
+        0,
+        read_accept_states_from_json("automato/DFA-final-states.json")?,
+    )?;
+    let compiled = dfa.compile_phf();
+    let dense = DenseDfa::new(&dfa);
+    let source = std::fs::read_to_string("tests/lexer_input_test.zig")?.repeat(2000);
+    let chars: Vec<char> = source.chars().collect();
+    println!("{} characters", chars.len());
+
+    time("HashMap", 20, || try_process_input(&dfa, &source).unwrap());
+    time("Compiled", 20, || scan(&compiled, &chars, false).unwrap());
+    time("Dense", 20, || scan(&dense, &chars, false).unwrap());
+    Ok(())
+}
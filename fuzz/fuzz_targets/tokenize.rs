@@ -0,0 +1,55 @@
+//! Feeds arbitrary bytes to the tokenizer, both with the bundled ZigZin automaton and with a
+//! small automaton decoded from the input itself, and checks that scanning always returns.
+//!
+//! Run with `cargo +nightly fuzz run tokenize` from the repository root.
+#![no_main]
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use libfuzzer_sys::fuzz_target;
+use zigzin::lexer::{process_bytes, read_accept_states_from_json, read_transitions_from_csv, DFA};
+
+fn zigzin_dfa() -> &'static DFA {
+    static DFA_CELL: OnceLock<DFA> = OnceLock::new();
+    DFA_CELL.get_or_init(|| {
+        DFA::try_new(
+            read_transitions_from_csv("automato/DFA-transitions.csv").unwrap(),
+            0,
+            read_accept_states_from_json("automato/DFA-final-states.json").unwrap(),
+        )
+        .unwrap()
+    })
+}
+
+/// Decodes a DFA with up to 8 states over a 4-letter alphabet from the first bytes of `data`,
+/// returning it with the remaining bytes. Automata with an accepting start state are rejected
+/// by `check_start`, as `DFA::try_new` would.
+fn small_dfa(data: &[u8]) -> Option<(DFA, &[u8])> {
+    let (&header, rest) = data.split_first()?;
+    let edge_count = (header & 0x0f) as usize;
+    let accept_mask = rest.first().copied().unwrap_or(0);
+    let rest = rest.get(1..).unwrap_or_default();
+    let edges = rest.get(..edge_count * 2)?;
+    let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+    for edge in edges.chunks(2) {
+        let from = (edge[0] & 0x07) as usize;
+        let ch = (b'a' + ((edge[0] >> 3) & 0x03)) as char;
+        let to = (edge[1] & 0x07) as usize;
+        dfa.add_transition(from, ch, to);
+    }
+    for state in 0..8 {
+        if accept_mask & (1 << state) != 0 {
+            dfa.add_accept(state, "token");
+        }
+    }
+    dfa.check_start().ok()?;
+    Some((dfa, &rest[edge_count * 2..]))
+}
+
+fuzz_target!(|data: &[u8]| {
+    let _ = process_bytes(zigzin_dfa(), data, true);
+    if let Some((dfa, input)) = small_dfa(data) {
+        let _ = process_bytes(&dfa, input, false);
+        let _ = process_bytes(&dfa, input, true);
+    }
+});
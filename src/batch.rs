@@ -0,0 +1,322 @@
+//! Tokenizing many files at once, collecting errors instead of stopping at the first one.
+#[cfg(all(feature = "csv", feature = "json"))]
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::error::LexError;
+use crate::lexer::{process_file_input, DFA};
+use crate::tokens::Token;
+
+/// An error tied to the file (or directory) it happened in.
+#[derive(Debug)]
+pub struct FileError {
+    pub path: PathBuf,
+    pub error: Box<dyn Error>,
+}
+
+impl FileError {
+    /// Formats the error as a report line. Lexer errors go through `template` (see
+    /// `LexError::render`); other errors, such as unreadable files, are shown as `path: error: ...`.
+    pub fn render(&self, template: &str) -> String {
+        let file = self.path.display().to_string();
+        match self.error.downcast_ref::<LexError>() {
+            Some(lex_error) => lex_error.render(template, &file),
+            None => format!("{}: error: {}", file, self.error),
+        }
+    }
+}
+
+/// Outcome of tokenizing every file of a directory tree.
+#[derive(Debug, Default)]
+pub struct LintReport {
+    /// Number of files that were tokenized, whether or not they succeeded.
+    pub files: usize,
+    /// One entry per failed file or unreadable directory, in path order.
+    pub errors: Vec<FileError>,
+}
+
+impl LintReport {
+    /// Number of distinct files that failed.
+    pub fn failed_files(&self) -> usize {
+        let mut paths: Vec<&Path> = self.errors.iter().map(|e| e.path.as_path()).collect();
+        paths.dedup();
+        paths.len()
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// Paths of the files tokens come from, indexed by `Token::file_id`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileTable {
+    paths: Vec<PathBuf>,
+}
+
+impl FileTable {
+    pub fn new() -> Self {
+        FileTable::default()
+    }
+
+    /// Registers a file and returns its id. Adding the same path again returns the existing id.
+    pub fn add(&mut self, path: impl Into<PathBuf>) -> u32 {
+        let path = path.into();
+        match self.paths.iter().position(|known| *known == path) {
+            Some(index) => index as u32,
+            None => {
+                self.paths.push(path);
+                (self.paths.len() - 1) as u32
+            }
+        }
+    }
+
+    /// The path registered under `id`.
+    pub fn path(&self, id: u32) -> Option<&Path> {
+        self.paths.get(id as usize).map(PathBuf::as_path)
+    }
+
+    /// The path of the file `token` comes from.
+    pub fn path_of(&self, token: &Token) -> Option<&Path> {
+        self.path(token.file_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+}
+
+/// Tokenizes several files into one stream, registering each in `files` and tagging its tokens
+/// with the file's id. Files that fail are skipped and reported, like in `lint_directory`.
+pub fn process_files(dfa: &DFA, files: &mut FileTable, paths: &[PathBuf]) -> (Vec<Token>, Vec<FileError>) {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    for path in paths {
+        let file_id = files.add(path.clone());
+        match process_file_input(dfa, &path.to_string_lossy()) {
+            Ok(file_tokens) => tokens.extend(file_tokens.into_iter().map(|token| Token { file_id, ..token })),
+            Err(error) => errors.push(FileError { path: path.clone(), error }),
+        }
+    }
+    (tokens, errors)
+}
+
+/// Recursively collects the files under `dir` whose extension is `extension`, sorted by path.
+/// Directories that cannot be read are reported in `errors` and skipped.
+pub fn find_files(dir: &Path, extension: &str, errors: &mut Vec<FileError>) -> Vec<PathBuf> {
+    collect_files(dir, &|ext| ext == extension, errors)
+}
+
+/// Like `find_files`, keeping the files whose extension passes `keep`.
+fn collect_files(dir: &Path, keep: &dyn Fn(&str) -> bool, errors: &mut Vec<FileError>) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            errors.push(FileError { path: dir.to_path_buf(), error: err.into() });
+            return files;
+        }
+    };
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            files.extend(collect_files(&path, keep, errors));
+        } else if path.extension().is_some_and(|ext| keep(&ext.to_string_lossy())) {
+            files.push(path);
+        }
+    }
+    files
+}
+
+/// Tokenizes every `extension` file under `dir`. A file that fails to read or to lex is
+/// recorded in the report and the run continues with the next one.
+pub fn lint_directory(dfa: &DFA, dir: &Path, extension: &str) -> LintReport {
+    let mut report = LintReport::default();
+    let files = find_files(dir, extension, &mut report.errors);
+    for path in files {
+        report.files += 1;
+        if let Err(error) = process_file_input(dfa, &path.to_string_lossy()) {
+            report.errors.push(FileError { path, error });
+        }
+    }
+    report
+}
+
+/// Automata chosen by file extension, for projects whose file kinds are lexed differently. Each
+/// extension names the transitions CSV and final-states JSON files of its automaton (see
+/// `DFA::from_files`); an automaton is loaded the first time a file needs it and then cached, so
+/// extensions sharing the same files also share one automaton.
+#[cfg(all(feature = "csv", feature = "json"))]
+#[derive(Debug, Default)]
+pub struct DfaRegistry {
+    /// Definition files by extension, without the leading dot.
+    definitions: HashMap<String, (String, String)>,
+    loaded: HashMap<(String, String), DFA>,
+}
+
+#[cfg(all(feature = "csv", feature = "json"))]
+impl DfaRegistry {
+    pub fn new() -> Self {
+        DfaRegistry::default()
+    }
+
+    /// Lexes files ending in `extension` (with or without the dot) with the automaton defined by
+    /// `transitions_path` and `accept_path`, replacing any earlier definition. Nothing is read yet.
+    pub fn register(&mut self, extension: &str, transitions_path: &str, accept_path: &str) -> &mut Self {
+        let extension = extension.trim_start_matches('.').to_string();
+        self.definitions.insert(extension, (transitions_path.to_string(), accept_path.to_string()));
+        self
+    }
+
+    /// The registered extensions, sorted.
+    pub fn extensions(&self) -> Vec<&str> {
+        let mut extensions: Vec<&str> = self.definitions.keys().map(String::as_str).collect();
+        extensions.sort_unstable();
+        extensions
+    }
+
+    /// The automaton for `path`, loading it on first use. Fails if the extension of `path` is
+    /// not registered or the definition files cannot be loaded.
+    pub fn dfa_for(&mut self, path: &Path) -> Result<&DFA, Box<dyn Error>> {
+        let extension = path.extension().map(|ext| ext.to_string_lossy()).unwrap_or_default();
+        let Some(definition) = self.definitions.get(extension.as_ref()) else {
+            let known = self.extensions().iter().map(|ext| format!(".{}", ext)).collect::<Vec<_>>().join(", ");
+            return Err(match extension.is_empty() {
+                true => format!("{} has no extension to select a DFA by (known: {})", path.display(), known),
+                false => format!("No DFA registered for .{} files like {} (known: {})", extension, path.display(), known),
+            }
+            .into());
+        };
+        if !self.loaded.contains_key(definition) {
+            let dfa = DFA::from_files(&definition.0, &definition.1)
+                .map_err(|err| format!("Cannot load the DFA for .{} files: {}", extension, err))?;
+            self.loaded.insert(definition.clone(), dfa);
+        }
+        Ok(&self.loaded[definition])
+    }
+
+    /// Tokenizes a file with the automaton of its extension.
+    pub fn process_file(&mut self, path: &Path) -> Result<Vec<Token>, Box<dyn Error>> {
+        let dfa = self.dfa_for(path)?;
+        process_file_input(dfa, &path.to_string_lossy())
+    }
+
+    /// Like `lint_directory`, over the files of every registered extension, each tokenized with
+    /// its own automaton.
+    pub fn lint_directory(&mut self, dir: &Path) -> LintReport {
+        let mut report = LintReport::default();
+        let files = collect_files(dir, &|ext| self.definitions.contains_key(ext), &mut report.errors);
+        for path in files {
+            report.files += 1;
+            if let Err(error) = self.process_file(&path) {
+                report.errors.push(FileError { path, error });
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+    use crate::error::GNU_TEMPLATE;
+
+    #[test]
+    fn test_lint_directory_collects_errors() {
+        let dir = std::env::temp_dir().join(format!("zigzin-lint-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("good.zig"), "aa a").unwrap();
+        fs::write(dir.join("nested/bad.zig"), "a\nab").unwrap();
+        fs::write(dir.join("nested/invalid.zig"), b"a\xff").unwrap();
+        fs::write(dir.join("ignored.txt"), "b").unwrap();
+
+        let mut transitions = HashMap::new();
+        transitions.insert((0, 'a'), 1);
+        transitions.insert((1, 'a'), 1);
+        let mut accept = HashMap::new();
+        accept.insert(1, "a".to_string());
+        let report = lint_directory(&DFA::new(transitions, 0, accept), &dir, "zig");
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(report.files, 3);
+        assert_eq!(report.failed_files(), 2);
+        let bad = dir.join("nested/bad.zig").display().to_string();
+        assert_eq!(report.errors[0].render(GNU_TEMPLATE), format!("{}:2:2: error: Unexpected token 'b'", bad));
+        assert!(report.errors[1].path.ends_with("nested/invalid.zig"));
+    }
+
+    #[test]
+    fn test_process_files_tags_tokens_with_file_ids() {
+        let mut transitions = HashMap::new();
+        transitions.insert((0, 'a'), 1);
+        let mut accept = HashMap::new();
+        accept.insert(1, "a".to_string());
+        let dfa = DFA::new(transitions, 0, accept);
+
+        let dir = std::env::temp_dir().join(format!("zigzin-files-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let paths: Vec<PathBuf> = ["one.zig", "two.zig", "missing.zig"].iter().map(|name| dir.join(name)).collect();
+        fs::write(&paths[0], "a a").unwrap();
+        fs::write(&paths[1], "a").unwrap();
+        let mut files = FileTable::new();
+        files.add(dir.join("earlier.zig"));
+        let (tokens, errors) = process_files(&dfa, &mut files, &paths);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let ids: Vec<u32> = tokens.iter().map(|token| token.file_id).collect();
+        assert_eq!(ids, [1, 1, 2]);
+        assert_eq!(files.path_of(&tokens[2]), Some(paths[1].as_path()));
+        assert_eq!(files.len(), 4);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].path, paths[2]);
+    }
+
+    #[cfg(all(feature = "csv", feature = "json"))]
+    #[test]
+    fn test_dfa_registry_selects_dfa_by_extension() {
+        let dir = std::env::temp_dir().join(format!("zigzin-registry-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        fs::write(path("a.csv"), "From,Input,To\n0,a,1\n").unwrap();
+        fs::write(path("b.csv"), "From,Input,To\n0,b,1\n").unwrap();
+        fs::write(path("accept.json"), "[[1, \"letter\"]]").unwrap();
+        fs::write(path("main.zz"), "a a").unwrap();
+        fs::write(path("main.zzi"), "b").unwrap();
+        fs::write(path("other.zzh"), "a").unwrap();
+        fs::write(path("notes.txt"), "?").unwrap();
+
+        let mut registry = DfaRegistry::new();
+        registry
+            .register("zz", &path("a.csv"), &path("accept.json"))
+            .register(".zzi", &path("b.csv"), &path("accept.json"))
+            .register("zzh", &path("a.csv"), &path("accept.json"));
+        assert_eq!(registry.extensions(), ["zz", "zzh", "zzi"]);
+        assert_eq!(registry.process_file(&dir.join("main.zz")).unwrap().len(), 2);
+        assert_eq!(registry.process_file(&dir.join("main.zzi")).unwrap()[0].lexeme, "b");
+        let unknown = registry.process_file(&dir.join("notes.txt")).unwrap_err().to_string();
+        assert!(unknown.starts_with("No DFA registered for .txt files"), "{}", unknown);
+        assert!(unknown.ends_with("(known: .zz, .zzh, .zzi)"), "{}", unknown);
+
+        let report = registry.lint_directory(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(report.files, 3);
+        assert!(report.is_success());
+        // `.zz` and `.zzh` files share one automaton.
+        assert_eq!(registry.loaded.len(), 2);
+    }
+
+    #[test]
+    fn test_unreadable_directory_is_reported() {
+        let mut errors = Vec::new();
+        let files = find_files(Path::new("tests/does-not-exist"), "zig", &mut errors);
+        assert!(files.is_empty());
+        assert_eq!(errors.len(), 1);
+    }
+}
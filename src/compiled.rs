@@ -0,0 +1,200 @@
+//! A read-only transition table laid out for fast lookups.
+//!
+//! `CompiledDfa` stores the edges of every state contiguously, sorted by character, with a
+//! per-state offset table (a compressed sparse row layout). A lookup is an index into the
+//! offsets followed by a binary search over that state's few edges, which avoids hashing a
+//! `(usize, char)` key for every input character. Build one with `DFA::compile_phf`.
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::error::LexError;
+use crate::scanner::{self, Transitions};
+use crate::tokens::Token;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledDfa {
+    start: usize,
+    /// `edges[offsets[s]..offsets[s + 1]]` are the transitions of state `s`.
+    offsets: Vec<usize>,
+    edges: Vec<(char, usize)>,
+    /// `intervals[interval_offsets[s]..interval_offsets[s + 1]]` are the range transitions of state `s`.
+    interval_offsets: Vec<usize>,
+    intervals: Vec<(char, char, usize)>,
+    /// Accept label of each state, indexed by state id.
+    accept: Vec<Option<String>>,
+    /// Negated class of each state: sorted excluded characters and the target.
+    negated: Vec<Option<(Vec<char>, usize)>>,
+}
+
+impl CompiledDfa {
+    /// Builds the table from `(from, input, to)` transitions and `(state, label)` accept states.
+    pub fn from_parts(
+        start: usize,
+        transitions: impl IntoIterator<Item = (usize, char, usize)>,
+        accept: impl IntoIterator<Item = (usize, String)>,
+    ) -> Self {
+        Self::from_parts_with_intervals(start, transitions, core::iter::empty(), accept)
+    }
+
+    /// Like `from_parts`, also taking `(from, char_start, char_end, to)` range transitions, which
+    /// must not overlap within a state. Single-character transitions take precedence.
+    pub fn from_parts_with_intervals(
+        start: usize,
+        transitions: impl IntoIterator<Item = (usize, char, usize)>,
+        intervals: impl IntoIterator<Item = (usize, char, char, usize)>,
+        accept: impl IntoIterator<Item = (usize, String)>,
+    ) -> Self {
+        let mut transitions: Vec<(usize, char, usize)> = transitions.into_iter().collect();
+        transitions.sort_unstable();
+        let mut intervals: Vec<(usize, char, char, usize)> = intervals.into_iter().collect();
+        intervals.sort_unstable();
+        let accept: Vec<(usize, String)> = accept.into_iter().collect();
+        let state_count = transitions
+            .iter()
+            .flat_map(|&(from, _, to)| [from, to])
+            .chain(intervals.iter().flat_map(|&(from, _, _, to)| [from, to]))
+            .chain(accept.iter().map(|(state, _)| *state))
+            .chain(core::iter::once(start))
+            .max()
+            .unwrap_or(0)
+            + 1;
+
+        let (offsets, edges) = group_by_state(state_count, &transitions, |&(from, ch, to)| (from, (ch, to)));
+        let (interval_offsets, intervals) =
+            group_by_state(state_count, &intervals, |&(from, low, high, to)| (from, (low, high, to)));
+
+        let mut accept_labels = alloc::vec![None; state_count];
+        for (state, label) in accept {
+            accept_labels[state] = Some(label);
+        }
+        CompiledDfa {
+            start,
+            offsets,
+            edges,
+            interval_offsets,
+            intervals,
+            accept: accept_labels,
+            negated: alloc::vec![None; state_count],
+        }
+    }
+
+    /// Adds `(from, excluded, to)` negated-class transitions, consulted after exact and range
+    /// transitions miss.
+    pub fn with_negated(mut self, negated: impl IntoIterator<Item = (usize, Vec<char>, usize)>) -> Self {
+        for (from, mut excluded, to) in negated {
+            self.ensure_state(from.max(to));
+            excluded.sort_unstable();
+            self.negated[from] = Some((excluded, to));
+        }
+        self
+    }
+
+    /// Grows the per-state tables so that `state` has (empty) entries.
+    fn ensure_state(&mut self, state: usize) {
+        while self.accept.len() <= state {
+            self.offsets.push(self.edges.len());
+            self.interval_offsets.push(self.intervals.len());
+            self.accept.push(None);
+            self.negated.push(None);
+        }
+    }
+
+    /// Scans the input like `lexer::try_process_input`, using the compiled table.
+    pub fn process_input(&self, input: &str) -> Result<Vec<Token>, LexError> {
+        let input_chars: Vec<char> = input.chars().collect();
+        scanner::scan(self, &input_chars, false)
+    }
+}
+
+/// Lays out items sorted by state into a per-state offset table and a flat item array.
+fn group_by_state<T, U>(state_count: usize, items: &[T], split: impl Fn(&T) -> (usize, U)) -> (Vec<usize>, Vec<U>) {
+    let mut offsets = Vec::with_capacity(state_count + 1);
+    let mut grouped = Vec::with_capacity(items.len());
+    let mut items = items.iter().map(split).peekable();
+    for state in 0..state_count {
+        offsets.push(grouped.len());
+        while let Some((_, item)) = items.next_if(|(from, _)| *from == state) {
+            grouped.push(item);
+        }
+    }
+    offsets.push(grouped.len());
+    (offsets, grouped)
+}
+
+impl Transitions for CompiledDfa {
+    fn start(&self) -> usize {
+        self.start
+    }
+
+    fn next_state(&self, state: usize, ch: char) -> Option<usize> {
+        let edges = &self.edges[*self.offsets.get(state)?..*self.offsets.get(state + 1)?];
+        if let Ok(index) = edges.binary_search_by(|&(input, _)| input.cmp(&ch)) {
+            return Some(edges[index].1);
+        }
+        let intervals = &self.intervals[self.interval_offsets[state]..self.interval_offsets[state + 1]];
+        if let Some(index) = intervals.partition_point(|&(low, _, _)| low <= ch).checked_sub(1) {
+            let (_, high, to) = intervals[index];
+            if ch <= high {
+                return Some(to);
+            }
+        }
+        let (excluded, to) = self.negated[state].as_ref()?;
+        excluded.binary_search(&ch).is_err().then_some(*to)
+    }
+
+    fn accept_label(&self, state: usize) -> Option<&str> {
+        self.accept.get(state)?.as_deref()
+    }
+}
+
+#[cfg(feature = "std")]
+impl crate::lexer::DFA {
+    /// Compiles the automaton into a `CompiledDfa`, trading the flexibility of the hash maps for
+    /// lookups without per-character hashing. The table is fixed once built.
+    pub fn compile_phf(&self) -> CompiledDfa {
+        CompiledDfa::from_parts_with_intervals(
+            self.start,
+            self.transitions.iter().map(|(&(from, ch), &to)| (from, ch, to)),
+            self.intervals
+                .iter()
+                .flat_map(|(&from, intervals)| intervals.iter().map(move |&(low, high, to)| (from, low, high, to))),
+            self.accept.iter().map(|(&state, label)| (state, label.clone())),
+        )
+        .with_negated(self.negated.iter().map(|(&from, (excluded, to))| (from, excluded.clone(), *to)))
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::collections::HashMap;
+    use crate::lexer::{try_process_input, DFA};
+
+    #[test]
+    fn test_compiled_dfa_keeps_intervals_and_negated_classes() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_interval(0, 'a', 'z', 1).unwrap();
+        dfa.add_interval(1, '0', '9', 1).unwrap();
+        dfa.add_interval(1, 'a', 'z', 1).unwrap();
+        dfa.add_transition(1, '_', 1).add_accept(1, "id");
+        dfa.add_negated(0, &[' ', 'a'], 5).unwrap().add_accept(5, "other");
+        let compiled = dfa.compile_phf();
+        for input in ["ab_9 z", "a1 B", "a1 #"] {
+            assert_eq!(compiled.process_input(input), try_process_input(&dfa, input));
+        }
+    }
+
+    #[cfg(all(feature = "csv", feature = "json"))]
+
+    #[test]
+    fn test_compiled_dfa_matches_hash_map_dfa() {
+        use crate::lexer::{read_accept_states_from_json, read_transitions_from_csv};
+        let dfa = DFA::new(
+            read_transitions_from_csv("automato/DFA-transitions.csv").unwrap(),
+            0,
+            read_accept_states_from_json("automato/DFA-final-states.json").unwrap(),
+        );
+        let compiled = dfa.compile_phf();
+        let source = std::fs::read_to_string("tests/lexer_input_test.zig").unwrap();
+        assert_eq!(compiled.process_input(&source), try_process_input(&dfa, &source));
+        assert_eq!(compiled.process_input("var @"), try_process_input(&dfa, "var @"));
+    }
+}
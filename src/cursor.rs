@@ -0,0 +1,336 @@
+//! A cursor over the input characters that keeps the line, column and byte offset of the
+//! current character up to date as it moves, so positions never have to be recomputed.
+use alloc::borrow::Cow;
+use alloc::vec::Vec;
+use core::ops::Range;
+use crate::tokens::{Span, Token};
+
+/// Line, column (both 1-based) and byte offset of a character in the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+    pub offset: usize,
+}
+
+impl Position {
+    /// The position of the first character.
+    pub fn start() -> Self {
+        Position { line: 1, column: 1, offset: 0 }
+    }
+
+    /// Moves the position past `ch`.
+    pub fn advance(&mut self, ch: char) {
+        self.advance_with_tab_width(ch, 1);
+    }
+
+    /// Moves the position past `ch`, with a tab moving the column to the next tab stop, every
+    /// `tab_width` columns. A width of 1 counts a tab like any other character.
+    pub fn advance_with_tab_width(&mut self, ch: char, tab_width: usize) {
+        match ch {
+            '\n' => {
+                self.line += 1;
+                self.column = 1;
+            }
+            '\t' if tab_width > 1 => self.column = (self.column - 1) / tab_width * tab_width + tab_width + 1,
+            _ => self.column += 1,
+        }
+        self.offset += ch.len_utf8();
+    }
+}
+
+/// Reads a character buffer front to back, borrowed or owned.
+#[derive(Debug, Clone)]
+pub struct SourceCursor<'a> {
+    chars: Cow<'a, [char]>,
+    index: usize,
+    position: Position,
+    tab_width: usize,
+}
+
+impl<'a> SourceCursor<'a> {
+    pub fn new(chars: impl Into<Cow<'a, [char]>>) -> Self {
+        SourceCursor { chars: chars.into(), index: 0, position: Position::start(), tab_width: 1 }
+    }
+
+    /// Counts columns with tab stops every `tab_width` columns, see `Position::advance_with_tab_width`.
+    pub fn with_tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width;
+        self
+    }
+
+    /// Treats the first character as being at `position`, for a buffer holding the rest of a
+    /// larger input, so positions continue from where an earlier pass stopped.
+    pub fn starting_at(mut self, position: Position) -> Self {
+        self.position = position;
+        self
+    }
+
+    /// A cursor owning the characters of `input`.
+    pub fn from_text(input: &str) -> SourceCursor<'static> {
+        SourceCursor::new(input.chars().collect::<Vec<char>>())
+    }
+
+    /// The whole buffer, including the characters already passed.
+    pub fn chars(&self) -> &[char] {
+        &self.chars
+    }
+
+    /// Index of the current character in `chars`.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Position of the current character, or just past the end once the input is exhausted.
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.index >= self.chars.len()
+    }
+
+    /// The current character.
+    pub fn current(&self) -> Option<char> {
+        self.peek(0)
+    }
+
+    /// The character `distance` places after the current one, without moving.
+    pub fn peek(&self, distance: usize) -> Option<char> {
+        self.chars.get(self.index + distance).copied()
+    }
+
+    /// Moves past the current character and returns it.
+    pub fn advance(&mut self) -> Option<char> {
+        let ch = self.current()?;
+        self.position.advance_with_tab_width(ch, self.tab_width);
+        self.index += 1;
+        Some(ch)
+    }
+
+    /// Moves past up to `count` characters and returns them.
+    pub fn advance_by(&mut self, count: usize) -> &[char] {
+        let start = self.index;
+        let end = (start + count).min(self.chars.len());
+        for &ch in &self.chars[start..end] {
+            self.position.advance_with_tab_width(ch, self.tab_width);
+        }
+        self.index = end;
+        &self.chars[start..end]
+    }
+}
+
+/// The location of a piece of the source as lines and columns (1-based) besides its byte range.
+/// The end is exclusive like the byte range: `end_col` is the column just past the last character.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SourceSpan {
+    pub start_line: usize,
+    pub start_col: usize,
+    pub end_line: usize,
+    pub end_col: usize,
+    pub byte_range: Range<usize>,
+}
+
+/// Start offsets of the lines of a text, built once so that byte offsets can be turned into
+/// lines and columns with a binary search instead of a walk from the start of the text.
+#[derive(Debug, Clone)]
+pub struct LineIndex<'a> {
+    source: &'a str,
+    /// Byte offset of the first character of every line; the first entry is 0.
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let mut line_starts = Vec::from([0]);
+        line_starts.extend(source.match_indices('\n').map(|(offset, _)| offset + 1));
+        LineIndex { source, line_starts }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Line and column (both 1-based) of the byte `offset`, which must lie on a character
+    /// boundary; an offset past the end maps to the end of the text. Finding the line takes
+    /// O(log lines); the column counts the characters before `offset` on its line.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let mut offset = offset.min(self.source.len());
+        while !self.source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let column = self.source[self.line_starts[line - 1]..offset].chars().count() + 1;
+        (line, column)
+    }
+
+    /// The `Position` of the byte `offset`, see `line_col`.
+    pub fn position(&self, offset: usize) -> Position {
+        let (line, column) = self.line_col(offset);
+        Position { line, column, offset: offset.min(self.source.len()) }
+    }
+
+    /// Lines and columns of both ends of `span`, see `SourceSpan`.
+    pub fn source_span(&self, span: Span) -> SourceSpan {
+        let (start_line, start_col) = self.line_col(span.start);
+        let (end_line, end_col) = self.line_col(span.end);
+        SourceSpan { start_line, start_col, end_line, end_col, byte_range: span.start..span.end }
+    }
+
+    /// Text of the 1-based `line`, without its line break.
+    pub fn line_text(&self, line: usize) -> &'a str {
+        let start = self.line_starts[line - 1];
+        let end = self.line_starts.get(line).map_or(self.source.len(), |&next| next - 1);
+        &self.source[start..end]
+    }
+}
+
+/// A piece of an output text copied from the source: the bytes `output` of the output came from
+/// the bytes `source`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub output: Range<usize>,
+    pub source: Range<usize>,
+}
+
+/// Translates byte offsets of a text assembled from tokens, e.g. with whitespace or some tokens
+/// dropped, back to offsets of the source they were scanned from. It is a list of `Segment`s
+/// sorted by output offset, looked up with a binary search; `LineIndex::line_col` over the
+/// source then gives the original line and column.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    segments: Vec<Segment>,
+    output_len: usize,
+}
+
+impl SourceMap {
+    /// The map of the text `passes::reconstruct(tokens)` builds: every token's leading trivia,
+    /// lexeme and trailing trivia, in order. As recorded by the scanner, leading trivia is taken
+    /// to end where the token's span starts and trailing trivia to start where it ends.
+    pub fn from_tokens(tokens: &[Token]) -> Self {
+        let mut map = SourceMap::default();
+        for token in tokens {
+            let (start, end) = (token.span.start, token.span.end);
+            let leading = token.leading_trivia.len();
+            map.push(leading, start.saturating_sub(leading)..start);
+            map.push(token.lexeme.len(), start..end);
+            map.push(token.trailing_trivia.len(), end..end + token.trailing_trivia.len());
+        }
+        map
+    }
+
+    /// Appends `len` output bytes copied from `source`, merging them into the previous segment
+    /// when both continue it.
+    fn push(&mut self, len: usize, source: Range<usize>) {
+        if len == 0 {
+            return;
+        }
+        let output = self.output_len..self.output_len + len;
+        self.output_len = output.end;
+        if let Some(last) = self.segments.last_mut() {
+            let exact = |segment: &Segment| segment.output.len() == segment.source.len();
+            if last.source.end == source.start && exact(last) && len == source.len() {
+                last.output.end = output.end;
+                last.source.end = source.end;
+                return;
+            }
+        }
+        self.segments.push(Segment { output, source });
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Length in bytes of the text the map covers.
+    pub fn output_len(&self) -> usize {
+        self.output_len
+    }
+
+    /// The source offset byte `offset` of the output came from, or `None` past the end of the
+    /// output. The end of the output maps to the end of the last segment. Where a segment's
+    /// output is longer than its source, e.g. a lexeme rewritten by an action, the offsets past the
+    /// source's length map to its end.
+    pub fn original_offset(&self, offset: usize) -> Option<usize> {
+        if offset == self.output_len {
+            return Some(self.segments.last().map_or(0, |segment| segment.source.end));
+        }
+        let segment = self.segments.get(self.segments.partition_point(|segment| segment.output.end <= offset))?;
+        Some((segment.source.start + (offset - segment.output.start)).min(segment.source.end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_index_matches_linear_walk() {
+        let source = "pub fn main() {\n\tvar é = 1;\n\n  return €;\n}";
+        let index = LineIndex::new(source);
+        let mut position = Position::start();
+        for (offset, ch) in source.char_indices() {
+            assert_eq!(index.position(offset), position, "offset {}", offset);
+            position.advance(ch);
+        }
+        assert_eq!(index.position(source.len()), position);
+
+        // Pseudo-random offsets over a larger text, snapped to character boundaries.
+        let large = source.repeat(50);
+        let index = LineIndex::new(&large);
+        let mut seed = 0x2545_f491_u32;
+        for _ in 0..500 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            let mut offset = seed as usize % large.len();
+            while !large.is_char_boundary(offset) {
+                offset -= 1;
+            }
+            let mut expected = Position::start();
+            large[..offset].chars().for_each(|ch| expected.advance(ch));
+            assert_eq!(index.position(offset), expected);
+        }
+        assert_eq!(index.line_count(), 4 * 50 + 1);
+        assert_eq!(index.line_text(2), "\tvar é = 1;");
+        assert_eq!(index.line_text(3), "");
+    }
+
+    #[test]
+    fn test_source_map_follows_trivia_and_rewritten_lexemes() {
+        use alloc::string::ToString;
+
+        let token = |lexeme: &str, start: usize, leading: &str| {
+            let mut token = Token::new("word".to_string(), lexeme.to_string()).with_position(Span::new(start, start + lexeme.len()), 1, 1);
+            token.leading_trivia = leading.to_string();
+            token
+        };
+        // "ab  cd" with its trivia maps onto itself in one segment.
+        let kept = [token("ab", 0, ""), token("cd", 4, "  ")];
+        let identity = SourceMap::from_tokens(&kept);
+        assert_eq!(identity.segments(), [Segment { output: 0..6, source: 0..6 }]);
+        assert_eq!((0..=6).map(|offset| identity.original_offset(offset).unwrap()).collect::<Vec<_>>(), [0, 1, 2, 3, 4, 5, 6]);
+        assert_eq!(identity.original_offset(7), None);
+
+        // Without the trivia, and with `cd` rewritten as `cdef`.
+        let rewritten = [token("ab", 0, ""), Token { lexeme: "cdef".to_string(), ..token("cd", 4, "") }];
+        let map = SourceMap::from_tokens(&rewritten);
+        assert_eq!(map.output_len(), 6);
+        let offsets: Vec<usize> = (0..=6).map(|offset| map.original_offset(offset).unwrap()).collect();
+        assert_eq!(offsets, [0, 1, 4, 5, 6, 6, 6]);
+    }
+
+    #[test]
+    fn test_cursor_tracks_positions() {
+        let mut cursor = SourceCursor::from_text("a€\nbc");
+        assert_eq!(cursor.advance(), Some('a'));
+        assert_eq!(cursor.peek(1), Some('\n'));
+        assert_eq!(cursor.advance_by(2), ['€', '\n']);
+        assert_eq!(cursor.position(), Position { line: 2, column: 1, offset: 5 });
+        assert_eq!((cursor.index(), cursor.current()), (3, Some('b')));
+        assert_eq!(cursor.advance_by(10), ['b', 'c']);
+        assert!(cursor.is_at_end());
+        assert_eq!(cursor.advance(), None);
+        assert_eq!(cursor.position(), Position { line: 2, column: 3, offset: 7 });
+    }
+}
@@ -0,0 +1,356 @@
+//! Structured diagnostics for lexer errors.
+//!
+//! The types here mirror the shape of `codespan_reporting::diagnostic` (a severity, a message,
+//! an optional code, byte-range labels and notes), so a `Diagnostic` can be converted field by
+//! field into the structure codespan-reporting or ariadne consume without ZigZin depending on them.
+//! With the `ariadne` feature, `to_report` and `ariadne::report_errors` do that conversion.
+use std::fmt::Write;
+use std::ops::Range;
+use crate::cursor::LineIndex;
+use crate::error::LexError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Bug,
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelStyle {
+    Primary,
+    Secondary,
+}
+
+/// A message attached to a byte range of the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub style: LabelStyle,
+    pub range: Range<usize>,
+    pub message: String,
+}
+
+impl Label {
+    pub fn primary(range: Range<usize>) -> Self {
+        Label { style: LabelStyle::Primary, range, message: String::new() }
+    }
+
+    pub fn secondary(range: Range<usize>) -> Self {
+        Label { style: LabelStyle::Secondary, range, message: String::new() }
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<String>,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity) -> Self {
+        Diagnostic { severity, code: None, message: String::new(), labels: Vec::new(), notes: Vec::new() }
+    }
+
+    pub fn error() -> Self {
+        Diagnostic::new(Severity::Error)
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+
+    pub fn with_labels(mut self, mut labels: Vec<Label>) -> Self {
+        self.labels.append(&mut labels);
+        self
+    }
+
+    pub fn with_notes(mut self, mut notes: Vec<String>) -> Self {
+        self.notes.append(&mut notes);
+        self
+    }
+
+    /// Renders the diagnostic in the style of codespan-reporting: a header, the source line of
+    /// every label with a caret underline, and the notes.
+    pub fn render(&self, file_name: &str, source: &str) -> String {
+        self.render_with(file_name, source, false)
+    }
+
+    /// Like `render`, with ANSI colors: the severity and primary underlines in its color,
+    /// secondary underlines and the gutter in blue.
+    pub fn render_colored(&self, file_name: &str, source: &str) -> String {
+        self.render_with(file_name, source, true)
+    }
+
+    fn render_with(&self, file_name: &str, source: &str, color: bool) -> String {
+        let paint = |text: &str, code: &str| match color {
+            true => format!("\x1b[{}m{}\x1b[0m", code, text),
+            false => text.to_string(),
+        };
+        let mut out = String::new();
+        let (severity, severity_color) = match self.severity {
+            Severity::Bug => ("bug", "1;31"),
+            Severity::Error => ("error", "1;31"),
+            Severity::Warning => ("warning", "1;33"),
+            Severity::Note => ("note", "1;32"),
+            Severity::Help => ("help", "1;36"),
+        };
+        let header = match &self.code {
+            Some(code) => format!("{}[{}]", severity, code),
+            None => severity.to_string(),
+        };
+        writeln!(out, "{}: {}", paint(&header, severity_color), self.message).unwrap();
+        let lines = LineIndex::new(source);
+        for label in &self.labels {
+            let (line, column) = lines.line_col(label.range.start);
+            let line_text = lines.line_text(line);
+            let gutter = " ".repeat(line.to_string().len());
+            // Ranges past the end of the source or inside a character are underlined with one caret.
+            let width = source.get(label.range.clone()).map_or(1, |text| text.chars().count().max(1));
+            let (marker, marker_color) = match label.style {
+                LabelStyle::Primary => ("^", severity_color),
+                LabelStyle::Secondary => ("-", "34"),
+            };
+            writeln!(out, "{} {} {}:{}:{}", gutter, paint("┌─", "34"), file_name, line, column).unwrap();
+            writeln!(out, "{} {}", gutter, paint("│", "34")).unwrap();
+            writeln!(out, "{} {}", paint(&format!("{} │", line), "34"), line_text).unwrap();
+            let underline = format!("{} {}", marker.repeat(width), label.message);
+            writeln!(out, "{} {} {}{}", gutter, paint("│", "34"), " ".repeat(column - 1), paint(&underline, marker_color))
+                .unwrap();
+        }
+        for note in &self.notes {
+            writeln!(out, "  = {}", note).unwrap();
+        }
+        out
+    }
+}
+
+/// Renders every error of `source` as a diagnostic, in order and separated by blank lines, e.g. the
+/// errors collected over a whole file. With `color`, ANSI colors are used as in `render_colored`.
+pub fn report_errors(file_name: &str, source: &str, errors: &[LexError], color: bool) -> String {
+    let reports: Vec<String> = errors
+        .iter()
+        .map(|error| error.to_diagnostic().render_with(file_name, source, color))
+        .collect();
+    reports.join("\n")
+}
+
+#[cfg(feature = "ariadne")]
+pub mod ariadne {
+    //! Conversion of diagnostics into `ariadne::Report`s, which draw several labels of one line
+    //! together and show multi-line spans.
+    use std::ops::Range;
+    use ::ariadne::{Color, Config, IndexType, Label, Report, ReportKind, Source};
+    use super::{Diagnostic, LabelStyle, Severity};
+    use crate::error::LexError;
+
+    impl Diagnostic {
+        /// Converts the diagnostic into a `Report` over byte ranges of the file `file_name`, e.g. to
+        /// print it with `Report::eprint((file_name, Source::from(source)))`.
+        pub fn to_report<'a>(&self, file_name: &'a str, color: bool) -> Report<'static, (&'a str, Range<usize>)> {
+            let (kind, label_color) = match self.severity {
+                Severity::Bug | Severity::Error => (ReportKind::Error, Color::Red),
+                Severity::Warning => (ReportKind::Warning, Color::Yellow),
+                Severity::Note | Severity::Help => (ReportKind::Advice, Color::Cyan),
+            };
+            let primary = self.labels.iter().find(|label| label.style == LabelStyle::Primary).or(self.labels.first());
+            let span = primary.map_or(0..0, |label| label.range.clone());
+            let mut builder = Report::build(kind, (file_name, span))
+                .with_config(Config::default().with_color(color).with_index_type(IndexType::Byte))
+                .with_message(&self.message);
+            if let Some(code) = &self.code {
+                builder = builder.with_code(code);
+            }
+            for label in &self.labels {
+                let mut report_label = Label::new((file_name, label.range.clone()));
+                if !label.message.is_empty() {
+                    report_label = report_label.with_message(&label.message);
+                }
+                if color {
+                    report_label = report_label.with_color(match label.style {
+                        LabelStyle::Primary => label_color,
+                        LabelStyle::Secondary => Color::Blue,
+                    });
+                }
+                builder.add_label(report_label);
+            }
+            for note in &self.notes {
+                builder.add_note(note);
+            }
+            builder.finish()
+        }
+    }
+
+    impl LexError {
+        /// The `Report` of `to_diagnostic`, see `Diagnostic::to_report`.
+        pub fn to_report<'a>(&self, file_name: &'a str, color: bool) -> Report<'static, (&'a str, Range<usize>)> {
+            self.to_diagnostic().to_report(file_name, color)
+        }
+    }
+
+    /// Renders every error of `source` with ariadne, in order, like `diagnostic::report_errors`.
+    /// Labels outside the source are moved to its end rather than making ariadne fail.
+    pub fn report_errors(file_name: &str, source: &str, errors: &[LexError], color: bool) -> String {
+        let cache = Source::from(source);
+        let mut out = Vec::new();
+        for error in errors {
+            let mut diagnostic = error.to_diagnostic();
+            for label in &mut diagnostic.labels {
+                label.range = label.range.start.min(source.len())..label.range.end.min(source.len());
+            }
+            diagnostic.to_report(file_name, color).write((file_name, &cache), &mut out).unwrap();
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::tokens::Span;
+
+        #[test]
+        fn test_report_errors_draws_each_error_with_ariadne() {
+            let source = "a @\nb #\n";
+            let errors = [
+                LexError::UnexpectedChar { ch: '@', line: 1, column: 3, span: Span::new(2, 3) },
+                LexError::UnexpectedChar { ch: '#', line: 2, column: 3, span: Span::new(6, 7) },
+                LexError::AcceptingStart { state: 0 },
+            ];
+            let plain = report_errors("input.zig", source, &errors, false);
+            assert_eq!(plain.matches("Error: unexpected character").count(), 2);
+            assert!(plain.contains("input.zig:1:3") && plain.contains("input.zig:2:3"));
+            assert!(plain.contains("no token starts with this character"));
+            assert!(plain.contains("Error: the start state 0 is accepting"));
+            assert!(!plain.contains('\x1b'));
+            assert!(report_errors("input.zig", source, &errors, true).contains('\x1b'));
+        }
+
+        #[test]
+        fn test_report_errors_clamps_ranges_past_the_source() {
+            let error = LexError::UnexpectedChar { ch: '@', line: 1, column: 9, span: Span::new(8, 40) };
+            assert!(report_errors("input.zig", "a @", &[error], false).contains("Error: unexpected character '@'"));
+        }
+    }
+}
+
+impl LexError {
+    /// Converts the error into a `Diagnostic` with a primary label over the offending bytes.
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let span = self.span();
+        match self {
+            LexError::UnexpectedChar { ch, .. } => Diagnostic::error()
+                .with_message(format!("unexpected character '{}'", ch))
+                .with_labels(vec![Label::primary(span.start..span.end)
+                    .with_message("no token starts with this character")]),
+            LexError::CharacterNotInAlphabet { ch, .. } => Diagnostic::error()
+                .with_message(format!("character '{}' is not in the alphabet", ch))
+                .with_labels(vec![Label::primary(span.start..span.end)
+                    .with_message("no transition of the automaton reads this character")])
+                .with_notes(vec!["check the encoding of the input".to_string()]),
+            LexError::InvalidUtf8 { byte_offset, .. } => Diagnostic::error()
+                .with_message(format!("invalid UTF-8 sequence at byte {}", byte_offset))
+                .with_labels(vec![Label::primary(span.start..span.end)
+                    .with_message("these bytes are not valid UTF-8")]),
+            LexError::InvalidUtf16 { byte_offset, .. } => Diagnostic::error()
+                .with_message(format!("invalid UTF-16 sequence at byte {}", byte_offset))
+                .with_labels(vec![Label::primary(span.start..span.end)
+                    .with_message("these bytes are not valid UTF-16")]),
+            LexError::ZeroLengthToken { state, .. } => Diagnostic::error()
+                .with_message(format!("accept state {} matched an empty lexeme", state))
+                .with_labels(vec![Label::primary(span.start..span.end)
+                    .with_message("no token of length one or more starts here")])
+                .with_notes(vec!["the start state of the automaton is accepting".to_string()]),
+            LexError::EmptyLexeme { token_type, state, .. } => Diagnostic::error()
+                .with_message(format!("the '{}' token accepted in state {} has an empty lexeme", token_type, state))
+                .with_labels(vec![Label::primary(span.start..span.end).with_message("the token would start and end here")])
+                .with_notes(vec!["the automaton's transitions and accept labels disagree".to_string()]),
+            LexError::ReplacementChar { .. } => Diagnostic::error()
+                .with_message("replacement character U+FFFD in the input")
+                .with_labels(vec![Label::primary(span.start..span.end)
+                    .with_message("invalid bytes were probably decoded as this character")])
+                .with_notes(vec!["decode strictly to locate the bytes, or choose another `on_replacement_char` policy".to_string()]),
+            LexError::ControlCharacter { code, .. } => Diagnostic::error()
+                .with_message(format!("control character U+{:04X} in the input", code))
+                .with_labels(vec![Label::primary(span.start..span.end).with_message("this character is not printable")])
+                .with_notes(vec!["binary files are not source code; choose another `on_control_char` policy to accept it".to_string()]),
+            LexError::TokenMismatch { token_type, found, .. } => Diagnostic::error()
+                .with_message(match found {
+                    Some(found) => format!("the '{}' token lexes as '{}'", token_type, found),
+                    None => format!("the '{}' token is not a single token", token_type),
+                })
+                .with_labels(vec![Label::primary(span.start..span.end).with_message("this lexeme")])
+                .with_notes(vec!["the token stream does not come from this automaton".to_string()]),
+            LexError::AcceptingStart { state } => Diagnostic::error()
+                .with_message(format!("the start state {} is accepting", state))
+                .with_notes(vec!["every token would be allowed to match an empty lexeme".to_string()]),
+            LexError::InvalidConfig { reason } => Diagnostic::error()
+                .with_message(format!("invalid lexer configuration: {}", reason)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens::Span;
+
+    #[test]
+    fn test_to_diagnostic_labels_error_bytes() {
+        let error = LexError::UnexpectedChar { ch: 'é', line: 1, column: 5, span: Span::new(4, 6) };
+        let diagnostic = error.to_diagnostic();
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.labels[0].range, 4..6);
+        assert_eq!(diagnostic.labels[0].style, LabelStyle::Primary);
+    }
+
+    #[test]
+    fn test_render_points_at_column() {
+        let source = "var x;\nvar $y;\n";
+        let error = LexError::UnexpectedChar { ch: '$', line: 2, column: 5, span: Span::new(11, 12) };
+        let rendered = error.to_diagnostic().render("input.zig", source);
+        assert!(rendered.contains("  ┌─ input.zig:2:5"));
+        assert!(rendered.contains("2 │ var $y;"));
+        assert!(rendered.contains("  │     ^ no token starts with this character"));
+    }
+
+    #[test]
+    fn test_report_errors_renders_each_error() {
+        let source = "a @\nb #\n";
+        let errors = [
+            LexError::UnexpectedChar { ch: '@', line: 1, column: 3, span: Span::new(2, 3) },
+            LexError::UnexpectedChar { ch: '#', line: 2, column: 3, span: Span::new(6, 7) },
+        ];
+        let plain = report_errors("input.zig", source, &errors, false);
+        assert_eq!(plain.matches("error: unexpected character").count(), 2);
+        assert!(plain.contains("input.zig:1:3") && plain.contains("input.zig:2:3"));
+        assert!(!plain.contains('\x1b'));
+        assert_eq!(plain, errors.each_ref().map(|error| error.to_diagnostic().render("input.zig", source)).join("\n"));
+
+        let colored = report_errors("input.zig", source, &errors, true);
+        assert!(colored.contains("\x1b[1;31merror\x1b[0m: unexpected character '@'"));
+    }
+
+    #[test]
+    fn test_render_tolerates_ranges_outside_the_source() {
+        let source = "a é";
+        let past_end = LexError::UnexpectedChar { ch: '@', line: 1, column: 9, span: Span::new(8, 40) };
+        assert!(past_end.to_diagnostic().render("input.zig", source).contains("input.zig:1:4"));
+        let inside_char = LexError::UnexpectedChar { ch: 'é', line: 1, column: 3, span: Span::new(3, 4) };
+        assert!(inside_char.to_diagnostic().render("input.zig", source).contains("input.zig:1:3"));
+    }
+}
@@ -0,0 +1,299 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use core::fmt;
+use crate::tokens::Span;
+
+/// Errors produced while scanning an input with a DFA.
+/// Every variant carries both a human-facing line/column and the byte span it refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexError {
+    /// No token could be matched starting at this character.
+    UnexpectedChar {
+        ch: char,
+        line: usize,
+        column: usize,
+        span: Span,
+    },
+    /// The character is outside the alphabet declared for the automaton, e.g. with
+    /// `DFA::with_alphabet`, so no state could have a transition on it. Unlike `UnexpectedChar`,
+    /// this usually points at input in the wrong encoding or language rather than a typo.
+    CharacterNotInAlphabet {
+        ch: char,
+        line: usize,
+        column: usize,
+        span: Span,
+    },
+    /// The input bytes are not valid UTF-8; `line` and `column` are counted over the valid prefix.
+    InvalidUtf8 {
+        byte_offset: usize,
+        line: usize,
+        column: usize,
+        len: usize,
+    },
+    /// The input is not valid UTF-16: an unpaired surrogate or a trailing odd byte. `byte_offset`
+    /// counts bytes of the UTF-16 input, `line` and `column` characters of the valid prefix.
+    InvalidUtf16 {
+        byte_offset: usize,
+        line: usize,
+        column: usize,
+        len: usize,
+    },
+    /// The longest match was empty because the start state is accepting; the scanner could not advance.
+    ZeroLengthToken {
+        state: usize,
+        line: usize,
+        column: usize,
+        span: Span,
+    },
+    /// A token with an empty lexeme was about to be emitted, which a consistent automaton never
+    /// produces; `state` is the state that accepted it. The scanner stops instead, as such a token
+    /// would confuse a parser.
+    EmptyLexeme {
+        token_type: String,
+        state: usize,
+        line: usize,
+        column: usize,
+        span: Span,
+    },
+    /// The input holds U+FFFD REPLACEMENT CHARACTER, typically put there by lenient decoding in
+    /// place of invalid bytes, and `ScanOptions::on_replacement_char` asks for an error.
+    ReplacementChar {
+        line: usize,
+        column: usize,
+        span: Span,
+    },
+    /// The input holds a control character other than whitespace such as tab, line feed or
+    /// carriage return, e.g. a NUL byte of a binary file, and `ScanOptions::on_control_char` asks
+    /// for an error. `code` is the character's code point.
+    ControlCharacter {
+        code: u32,
+        line: usize,
+        column: usize,
+        span: Span,
+    },
+    /// A token checked by `verify_tokens` does not match its own type: its lexeme alone lexes as
+    /// the `found` label, or is not a single token of the automaton when `found` is `None`.
+    TokenMismatch {
+        token_type: String,
+        found: Option<String>,
+        line: usize,
+        column: usize,
+        span: Span,
+    },
+    /// The start state of an automaton is accepting, so every token match could end without
+    /// reading a character. Reported when the automaton is built, see `DFA::try_new`.
+    AcceptingStart {
+        state: usize,
+    },
+    /// A `LexerConfig` combines options that cannot work together. Like `AcceptingStart`, it
+    /// refers to no input, so its span is empty and its line and column are 0.
+    InvalidConfig {
+        reason: String,
+    },
+}
+
+impl LexError {
+    /// Byte range of the source the error points at.
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::UnexpectedChar { span, .. } | LexError::CharacterNotInAlphabet { span, .. } => *span,
+            LexError::InvalidUtf8 { byte_offset, len, .. } | LexError::InvalidUtf16 { byte_offset, len, .. } => {
+                Span::new(*byte_offset, byte_offset + len)
+            }
+            LexError::ZeroLengthToken { span, .. }
+            | LexError::EmptyLexeme { span, .. }
+            | LexError::ReplacementChar { span, .. }
+            | LexError::ControlCharacter { span, .. }
+            | LexError::TokenMismatch { span, .. } => *span,
+            LexError::AcceptingStart { .. } | LexError::InvalidConfig { .. } => Span::new(0, 0),
+        }
+    }
+
+    /// Line and column (both 1-based) where the error starts.
+    pub fn position(&self) -> (usize, usize) {
+        match self {
+            LexError::UnexpectedChar { line, column, .. } => (*line, *column),
+            LexError::CharacterNotInAlphabet { line, column, .. } => (*line, *column),
+            LexError::InvalidUtf8 { line, column, .. } => (*line, *column),
+            LexError::InvalidUtf16 { line, column, .. } => (*line, *column),
+            LexError::ZeroLengthToken { line, column, .. } => (*line, *column),
+            LexError::EmptyLexeme { line, column, .. } => (*line, *column),
+            LexError::ReplacementChar { line, column, .. } => (*line, *column),
+            LexError::ControlCharacter { line, column, .. } => (*line, *column),
+            LexError::TokenMismatch { line, column, .. } => (*line, *column),
+            LexError::AcceptingStart { .. } | LexError::InvalidConfig { .. } => (0, 0),
+        }
+    }
+}
+
+/// Template reproducing the default `Display` output.
+pub const DEFAULT_TEMPLATE: &str = "ZigZin compiler: Lexer error at line {line}, column {col}: {message}";
+/// GCC/Clang style, understood by most editors and build systems.
+pub const GNU_TEMPLATE: &str = "{file}:{line}:{col}: error: {message}";
+/// MSVC style, understood by Visual Studio.
+pub const MSVC_TEMPLATE: &str = "{file}({line},{col}): error: {message}";
+
+/// Built-in English text of every message key, used when a locale lacks a key.
+pub(crate) fn english(key: &str) -> Option<&'static str> {
+    match key {
+        "lexer_error" => Some(DEFAULT_TEMPLATE),
+        "unexpected_char" => Some("Unexpected token '{char}'"),
+        "character_not_in_alphabet" => Some("Character '{char}' is not in the alphabet of the automaton"),
+        "invalid_utf8" => Some("Invalid UTF-8 sequence at byte {byte}"),
+        "invalid_utf16" => Some("Invalid UTF-16 sequence at byte {byte}"),
+        "zero_length_token" => Some("Accept state {state} matched an empty lexeme"),
+        "empty_lexeme" => Some("The '{type}' token accepted in state {state} has an empty lexeme"),
+        "replacement_char" => Some("Replacement character U+FFFD in the input, which was probably not valid UTF-8"),
+        "control_character" => Some("Control character {code} in the input, which may not be a text file"),
+        "token_mismatch" => Some("The lexeme of the '{type}' token lexes as '{found}'"),
+        "token_unmatched" => Some("The lexeme of the '{type}' token is not a single token"),
+        "accepting_start" => Some("The start state {state} is accepting and would match an empty lexeme"),
+        "invalid_config" => Some("Invalid lexer configuration: {reason}"),
+        _ => None,
+    }
+}
+
+/// Looks up the text of a message key in the current locale.
+fn lookup(key: &str) -> String {
+    #[cfg(feature = "std")]
+    return crate::locale::lookup(key);
+    #[cfg(not(feature = "std"))]
+    return english(key).unwrap_or_default().to_string();
+}
+
+/// Replaces every `{name}` in `template` for which `resolve` returns a value and copies
+/// everything else, including unknown placeholders, as is.
+fn fill(template: &str, resolve: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(template.len() + 32);
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        rest = &rest[open..];
+        let Some(close) = rest.find('}') else { break };
+        match resolve(&rest[1..close]) {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[..=close]),
+        }
+        rest = &rest[close + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+impl LexError {
+    /// Key of the error's message in the message catalog.
+    pub fn message_key(&self) -> &'static str {
+        match self {
+            LexError::UnexpectedChar { .. } => "unexpected_char",
+            LexError::CharacterNotInAlphabet { .. } => "character_not_in_alphabet",
+            LexError::InvalidUtf8 { .. } => "invalid_utf8",
+            LexError::InvalidUtf16 { .. } => "invalid_utf16",
+            LexError::ZeroLengthToken { .. } => "zero_length_token",
+            LexError::EmptyLexeme { .. } => "empty_lexeme",
+            LexError::ReplacementChar { .. } => "replacement_char",
+            LexError::ControlCharacter { .. } => "control_character",
+            LexError::TokenMismatch { found: Some(_), .. } => "token_mismatch",
+            LexError::TokenMismatch { found: None, .. } => "token_unmatched",
+            LexError::AcceptingStart { .. } => "accepting_start",
+            LexError::InvalidConfig { .. } => "invalid_config",
+        }
+    }
+
+    /// The description of the error, without position information, in the current locale.
+    pub fn message(&self) -> String {
+        fill(&lookup(self.message_key()), |name| match (name, self) {
+            ("char", LexError::UnexpectedChar { ch, .. } | LexError::CharacterNotInAlphabet { ch, .. }) => {
+                Some(ch.to_string())
+            }
+            ("byte", LexError::InvalidUtf8 { byte_offset, .. } | LexError::InvalidUtf16 { byte_offset, .. }) => {
+                Some(byte_offset.to_string())
+            }
+            (
+                "state",
+                LexError::ZeroLengthToken { state, .. }
+                | LexError::EmptyLexeme { state, .. }
+                | LexError::AcceptingStart { state },
+            ) => Some(state.to_string()),
+            ("type", LexError::EmptyLexeme { token_type, .. } | LexError::TokenMismatch { token_type, .. }) => {
+                Some(token_type.clone())
+            }
+            ("found", LexError::TokenMismatch { found, .. }) => found.clone(),
+            ("code", LexError::ControlCharacter { code, .. }) => Some(format!("U+{:04X}", code)),
+            ("reason", LexError::InvalidConfig { reason }) => Some(reason.clone()),
+            _ => None,
+        })
+    }
+
+    /// The character the error refers to, if any.
+    pub fn character(&self) -> Option<char> {
+        match self {
+            LexError::UnexpectedChar { ch, .. } | LexError::CharacterNotInAlphabet { ch, .. } => Some(*ch),
+            LexError::ReplacementChar { .. } => Some(char::REPLACEMENT_CHARACTER),
+            LexError::ControlCharacter { code, .. } => char::from_u32(*code),
+            LexError::InvalidUtf8 { .. }
+            | LexError::InvalidUtf16 { .. }
+            | LexError::ZeroLengthToken { .. }
+            | LexError::EmptyLexeme { .. }
+            | LexError::TokenMismatch { .. }
+            | LexError::AcceptingStart { .. }
+            | LexError::InvalidConfig { .. } => None,
+        }
+    }
+
+    /// Renders the error through a template. The placeholders `{file}`, `{line}`, `{col}`,
+    /// `{char}` and `{message}` are replaced; `{char}` is empty when the error has no character.
+    /// Any other text, including unknown placeholders, is copied as is.
+    pub fn render(&self, template: &str, file: &str) -> String {
+        let (line, column) = self.position();
+        fill(template, |name| match name {
+            "file" => Some(file.to_string()),
+            "line" => Some(line.to_string()),
+            "col" => Some(column.to_string()),
+            "char" => Some(self.character().map(String::from).unwrap_or_default()),
+            "message" => Some(self.message()),
+            _ => None,
+        })
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // There is no position to report.
+            LexError::AcceptingStart { .. } | LexError::InvalidConfig { .. } => f.write_str(&self.message()),
+            _ => f.write_str(&self.render(&lookup("lexer_error"), "")),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LexError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unexpected() -> LexError {
+        LexError::UnexpectedChar { ch: '@', line: 3, column: 7, span: Span::new(20, 21) }
+    }
+
+    #[test]
+    fn test_display_matches_default_template() {
+        assert_eq!(
+            unexpected().to_string(),
+            "ZigZin compiler: Lexer error at line 3, column 7: Unexpected token '@'"
+        );
+    }
+
+    #[test]
+    fn test_render_built_in_templates() {
+        assert_eq!(unexpected().render(GNU_TEMPLATE, "main.zig"), "main.zig:3:7: error: Unexpected token '@'");
+        assert_eq!(unexpected().render(MSVC_TEMPLATE, "main.zig"), "main.zig(3,7): error: Unexpected token '@'");
+    }
+
+    #[test]
+    fn test_render_custom_template() {
+        let template = "[{file}] {line}/{col} got {char} {unknown} {";
+        assert_eq!(unexpected().render(template, "a.zig"), "[a.zig] 3/7 got @ {unknown} {");
+    }
+}
@@ -0,0 +1,212 @@
+//! Exporters that write a DFA in formats meant for people and other tools.
+use std::collections::BTreeMap;
+#[cfg(feature = "csv")]
+use std::error::Error;
+use std::fmt::Write;
+use crate::lexer::DFA;
+
+/// The single characters and the character ranges an edge is taken on.
+type EdgeLabel = (Vec<char>, Vec<(char, char)>);
+
+impl DFA {
+    /// Renders the automaton in Graphviz DOT format. States are shown by their name when a
+    /// state-name mapping is present, accept states are drawn as double circles with their label,
+    /// and parallel edges between the same pair of states are merged into one labelled edge, with
+    /// character ranges written as `[a-z]`. Negated classes are drawn as dashed `[^...]` edges.
+    pub fn to_dot(&self) -> String {
+        // Characters and character ranges of all edges between each pair of states.
+        let mut edges: BTreeMap<(usize, usize), EdgeLabel> = BTreeMap::new();
+        for (&(from, ch), &to) in &self.transitions {
+            edges.entry((from, to)).or_default().0.push(ch);
+        }
+        for (&from, intervals) in &self.intervals {
+            for &(low, high, to) in intervals {
+                edges.entry((from, to)).or_default().1.push((low, high));
+            }
+        }
+        let mut negated: Vec<(&usize, &(Vec<char>, usize))> = self.negated.iter().collect();
+        negated.sort();
+        let mut accept: Vec<(&usize, &String)> = self.accept.iter().collect();
+        accept.sort();
+
+        let mut dot = String::from("digraph DFA {\n    rankdir=LR;\n");
+        writeln!(dot, "    \"{}\" [shape=circle, style=bold];", escape(&self.state_name(self.start))).unwrap();
+        for (&state, label) in accept {
+            writeln!(
+                dot,
+                "    \"{}\" [shape=doublecircle, xlabel=\"{}\"];",
+                escape(&self.state_name(state)),
+                escape(label)
+            )
+            .unwrap();
+        }
+        for ((from, to), (mut chars, mut ranges)) in edges {
+            chars.sort();
+            ranges.sort();
+            let mut label: String = chars.into_iter().collect();
+            for (low, high) in ranges {
+                write!(label, "[{}-{}]", low, high).unwrap();
+            }
+            writeln!(
+                dot,
+                "    \"{}\" -> \"{}\" [label=\"{}\"];",
+                escape(&self.state_name(from)),
+                escape(&self.state_name(to)),
+                escape(&label)
+            )
+            .unwrap();
+        }
+        for (&from, (excluded, to)) in negated {
+            let excluded: String = excluded.iter().collect();
+            writeln!(
+                dot,
+                "    \"{}\" -> \"{}\" [label=\"[^{}]\", style=dashed];",
+                escape(&self.state_name(from)),
+                escape(&self.state_name(*to)),
+                escape(&excluded)
+            )
+            .unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(feature = "csv")]
+impl DFA {
+    /// Writes the transition table in the `From,Input,To` format read by
+    /// `read_transition_table_from_csv`, with ranges as `a-z` and negated classes as `[^...]`.
+    /// Rows are sorted by `(from, input, to)`, so exporting the same automaton always yields the
+    /// same bytes regardless of `HashMap` iteration order.
+    pub fn to_csv(&self) -> Result<String, Box<dyn Error>> {
+        let mut rows: Vec<(usize, String, usize)> = Vec::new();
+        for (from, ch, to) in self.iter_transitions() {
+            rows.push((from, ch.to_string(), to));
+        }
+        for (&from, intervals) in &self.intervals {
+            for &(low, high, to) in intervals {
+                rows.push((from, format!("{}-{}", low, high), to));
+            }
+        }
+        for (&from, (excluded, to)) in &self.negated {
+            let mut class = String::from("[^");
+            for &ch in excluded {
+                match ch {
+                    '\\' => class.push_str("\\\\"),
+                    '\n' => class.push_str("\\n"),
+                    '\r' => class.push_str("\\r"),
+                    '\t' => class.push_str("\\t"),
+                    _ => class.push(ch),
+                }
+            }
+            class.push(']');
+            rows.push((from, class, *to));
+        }
+        rows.sort();
+
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(["From", "Input", "To"])?;
+        for (from, input, to) in rows {
+            writer.write_record([from.to_string(), input, to.to_string()])?;
+        }
+        Ok(String::from_utf8(writer.into_inner()?)?)
+    }
+}
+
+/// Escapes a string for use inside a double-quoted DOT identifier.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\\\n"),
+            '\t' => escaped.push_str("\\\\t"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    #[test]
+    fn test_to_dot_uses_state_names() {
+        let mut transitions = HashMap::new();
+        transitions.insert((0, '"'), 7);
+        transitions.insert((7, 'a'), 7);
+        transitions.insert((7, 'b'), 7);
+        transitions.insert((7, '"'), 8);
+        let mut accept = HashMap::new();
+        accept.insert(8, "string".to_string());
+        let mut names = HashMap::new();
+        names.insert(0, "start".to_string());
+        names.insert(7, "in_string".to_string());
+        let dfa = DFA::new(transitions, 0, accept).with_state_names(names);
+
+        let dot = dfa.to_dot();
+        assert!(dot.contains("\"start\" -> \"in_string\" [label=\"\\\"\"];"));
+        assert!(dot.contains("\"in_string\" -> \"in_string\" [label=\"ab\"];"));
+        assert!(dot.contains("\"8\" [shape=doublecircle, xlabel=\"string\"];"));
+    }
+
+    /// Builds the same automaton from scratch, so its maps get fresh hash seeds.
+    fn sample_dfa() -> DFA {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        for (from, ch, to) in [(0, 'b', 2), (0, 'a', 1), (1, ',', 3), (2, '"', 3), (0, 'c', 1), (3, 'x', 0)] {
+            dfa.add_transition(from, ch, to);
+        }
+        dfa.add_interval(1, '0', '9', 1).unwrap().add_interval(1, 'A', 'F', 2).unwrap();
+        dfa.add_negated(2, &['\n', '\\', '"'], 2).unwrap();
+        dfa.add_accept(3, "c").add_accept(1, "a").add_accept(2, "b");
+        dfa
+    }
+
+    #[test]
+    fn test_exports_are_byte_identical() {
+        assert_eq!(sample_dfa().to_dot(), sample_dfa().to_dot());
+        assert_eq!(sample_dfa().compile_phf(), sample_dfa().compile_phf());
+        #[cfg(feature = "csv")]
+        assert_eq!(sample_dfa().to_csv().unwrap(), sample_dfa().to_csv().unwrap());
+    }
+
+    #[test]
+    fn test_derived_outputs_do_not_depend_on_hash_seeds() {
+        let (first, second) = (sample_dfa(), sample_dfa());
+        assert_eq!(first.iter_transitions().collect::<Vec<_>>(), second.iter_transitions().collect::<Vec<_>>());
+        assert_eq!(first.validate(), second.validate());
+        assert_eq!(first.possible_token_types(), second.possible_token_types());
+        assert_eq!(first.union(&first.complement()).to_dot(), second.union(&second.complement()).to_dot());
+        assert_eq!(first.reverse().to_dfa().to_dot(), second.reverse().to_dfa().to_dot());
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_to_csv_round_trips() {
+        use crate::lexer::read_transition_table_from_csv;
+
+        let dfa = sample_dfa();
+        let csv = dfa.to_csv().unwrap();
+        assert!(csv.starts_with("From,Input,To\n0,a,1\n0,b,2\n0,c,1\n1,\",\",3\n1,0-9,1\n1,A-F,2\n"));
+        let path = std::env::temp_dir().join(format!("zigzin-export-{}.csv", std::process::id()));
+        std::fs::write(&path, &csv).unwrap();
+        let table = read_transition_table_from_csv(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(table.transitions, dfa.transitions);
+        assert_eq!(table.intervals, dfa.intervals);
+        assert_eq!(table.negated, dfa.negated);
+    }
+
+    #[test]
+    fn test_to_dot_labels_intervals() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_transition(0, '_', 1).add_interval(0, 'a', 'z', 1).unwrap();
+        dfa.add_negated(1, &['"'], 2).unwrap();
+        let dot = dfa.to_dot();
+        assert!(dot.contains("\"0\" -> \"1\" [label=\"_[a-z]\"];"));
+        assert!(dot.contains("\"1\" -> \"2\" [label=\"[^\\\"]\", style=dashed];"));
+    }
+}
@@ -0,0 +1,54 @@
+//! Reading gzip (RFC 1952) compressed input through `flate2`, so compressed corpora can be
+//! tokenized without decompressing them first.
+use std::error::Error;
+use std::fmt;
+use std::io::Read;
+use flate2::read::MultiGzDecoder;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GzipError(String);
+
+impl fmt::Display for GzipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid gzip data: {}", self.0)
+    }
+}
+
+impl Error for GzipError {}
+
+/// Wraps a reader of gzip data into a reader of the decompressed bytes, concatenating the
+/// members if there are several, e.g. to tokenize a large file as it is decompressed.
+pub fn decoder<R: Read>(reader: R) -> MultiGzDecoder<R> {
+    MultiGzDecoder::new(reader)
+}
+
+/// Decompresses a complete gzip stream, concatenating the members if there are several.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, GzipError> {
+    let mut output = Vec::new();
+    decoder(data).read_to_end(&mut output).map_err(|err| GzipError(err.to_string()))?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decompress_fixture_matches_plain_file() {
+        let compressed = std::fs::read("tests/lexer_input_test.zig.gz").unwrap();
+        let plain = std::fs::read("tests/lexer_input_test.zig").unwrap();
+        assert_eq!(decompress(&compressed).unwrap(), plain);
+
+        // Members are concatenated.
+        let twice = [compressed.as_slice(), compressed.as_slice()].concat();
+        assert_eq!(decompress(&twice).unwrap(), [plain.as_slice(), plain.as_slice()].concat());
+    }
+
+    #[test]
+    fn test_decompress_rejects_corrupted_crc() {
+        let mut compressed = std::fs::read("tests/lexer_input_test.zig.gz").unwrap();
+        let crc_index = compressed.len() - 8;
+        compressed[crc_index] ^= 0xff;
+        assert!(decompress(&compressed).unwrap_err().to_string().starts_with("Invalid gzip data"));
+    }
+}
@@ -0,0 +1,105 @@
+//! A JSON5 front end for hand-edited tables: the JSON5 additions used in such files are rewritten
+//! into strict JSON, which `serde_json` then parses, so the feature adds no dependency.
+//!
+//! Supported: `//` and `/* */` comments, trailing commas, unquoted object keys and single-quoted
+//! strings. Other JSON5 extensions, such as hexadecimal numbers or `Infinity`, are passed through
+//! and rejected by the JSON parser.
+use std::error::Error;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Rewrites the JSON5 text `source` into equivalent strict JSON.
+pub fn to_json(source: &str) -> Result<String, Box<dyn Error>> {
+    let mut json = String::with_capacity(source.len());
+    let mut chars = source.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' | '\'' => copy_string(ch, &mut chars, &mut json)?,
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.next_if(|&next| next != '\n').is_some() {}
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if previous == '*' => break,
+                        Some(next) => previous = next,
+                        None => return Err("Unterminated block comment in JSON5 input".into()),
+                    }
+                }
+                // Keep the tokens on both sides of the comment apart.
+                json.push(' ');
+            }
+            ']' | '}' => {
+                let kept = json.trim_end().len();
+                if json[..kept].ends_with(',') {
+                    json.truncate(kept - 1);
+                }
+                json.push(ch);
+            }
+            ch if ch.is_alphabetic() || ch == '_' || ch == '$' => {
+                let mut word = String::from(ch);
+                while let Some(next) = chars.next_if(|&next| next.is_alphanumeric() || next == '_' || next == '$') {
+                    word.push(next);
+                }
+                let mut rest = chars.clone();
+                while rest.next_if(|next| next.is_whitespace()).is_some() {}
+                if rest.peek() == Some(&':') {
+                    json.push('"');
+                    json.push_str(&word);
+                    json.push('"');
+                } else {
+                    json.push_str(&word);
+                }
+            }
+            ch => json.push(ch),
+        }
+    }
+    Ok(json)
+}
+
+/// Copies a string opened by `quote` into `json` as a double-quoted string.
+fn copy_string(quote: char, chars: &mut Peekable<Chars>, json: &mut String) -> Result<(), Box<dyn Error>> {
+    json.push('"');
+    loop {
+        match chars.next() {
+            Some('\\') => match chars.next() {
+                // `\'` needs no escape once the string is double-quoted.
+                Some('\'') => json.push('\''),
+                Some(escaped) => {
+                    json.push('\\');
+                    json.push(escaped);
+                }
+                None => break,
+            },
+            Some('"') if quote == '\'' => json.push_str("\\\""),
+            Some(ch) if ch == quote => {
+                json.push('"');
+                return Ok(());
+            }
+            Some(ch) => json.push(ch),
+            None => break,
+        }
+    }
+    Err("Unterminated string in JSON5 input".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json_rewrites_json5_additions() {
+        let source = "{\n  // the label\n  label: 'it\\'s \"quoted\"', /* inline */ \"url\": \"http://x\",\n  list: [1, 2,],\n}";
+        let value: serde_json::Value = serde_json::from_str(&to_json(source).unwrap()).unwrap();
+        assert_eq!(value, serde_json::json!({ "label": "it's \"quoted\"", "url": "http://x", "list": [1, 2] }));
+        assert_eq!(to_json("[true, null, false,]").unwrap(), "[true, null, false]");
+    }
+
+    #[test]
+    fn test_to_json_reports_unterminated_input() {
+        assert!(to_json("[1, /* never closed").is_err());
+        assert!(to_json("['open]").is_err());
+    }
+}
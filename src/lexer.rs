@@ -1,49 +1,581 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::error::Error;
 use std::fs;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufRead, BufReader, Read};
+#[cfg(feature = "csv")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "csv")]
 use serde::Deserialize;
-use crate::tokens::Token;
+use crate::error::LexError;
+use crate::cursor::{LineIndex, Position, SourceMap, SourceSpan};
+use crate::passes;
+use crate::scanner::{
+    self, Action, ControlCharPolicy, LabelPolicy, LexStats, NewlineMode, ReplacementPolicy, ScanOptions, Skip, Transitions,
+    TriviaPolicy,
+};
+use crate::tokens::{Span, Token};
 
 /// Representation of a DFA.
 /// Transitions are stored in a HashMap where the key is a tuple of a DFA state and an input symbol.
+/// The maps are iterated in an unspecified order that changes from run to run, so every method
+/// producing output from them (exports, `validate`, `states`, the automaton operations) sorts
+/// first, and its result is the same for the same automaton.
 #[derive(Debug, Clone)]
 pub struct DFA {
     pub transitions: HashMap<(usize, char), usize>,
+    /// Transitions over character ranges: for each state, non-overlapping inclusive
+    /// `(char_start, char_end, to)` intervals sorted by `char_start`. They are consulted with a
+    /// binary search when `transitions` has no entry for a character.
+    pub intervals: HashMap<usize, Vec<(char, char, usize)>>,
+    /// Negated character classes: for each state, a sorted list of excluded characters and the
+    /// target taken on any other character. Consulted only when no exact or interval transition matches.
+    pub negated: HashMap<usize, (Vec<char>, usize)>,
     pub start: usize,
     /// Mapping from an accept state to its label.
     pub accept: HashMap<usize, String>,
+    /// Optional human-readable names of states, used only when presenting the automaton.
+    pub state_names: HashMap<usize, String>,
+    /// Optional broad category (e.g. "operator" or "literal") of an accept state's tokens,
+    /// copied onto `Token::category`.
+    pub categories: HashMap<usize, String>,
+    /// Every label of an accept state standing for several original accept states, e.g. after
+    /// `NFA::to_dfa` or `union`, in priority order. `accept` holds the label used by default,
+    /// normally the first; `ScanOptions::label_policy` can pick another one or all of them.
+    pub accept_labels: HashMap<usize, Vec<String>>,
+    /// Labels of the tokens a parser may ignore, such as whitespace or comments; their tokens
+    /// have `Token::trivia` set.
+    pub trivia: HashSet<String>,
+    /// Optional name of the action run on the lexemes of an accept state's tokens, e.g.
+    /// "strip_quotes"; see `LexerConfigBuilder::action`.
+    pub actions: HashMap<usize, String>,
+    /// The characters the input may hold, when declared with `with_alphabet`. Scanning any other
+    /// character fails with `LexError::CharacterNotInAlphabet` instead of `UnexpectedChar`.
+    pub declared_alphabet: Option<BTreeSet<char>>,
+    /// Priority and declaration order of the labels, which break ties between the labels of
+    /// one accept state; see `with_rule_ranks`.
+    pub rule_ranks: HashMap<String, RuleRank>,
 }
 
+/// Where a rule's label stands when several rules match the same longest lexeme: the higher
+/// `priority` wins, then the lower `declared` index, i.e. the rule defined first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RuleRank {
+    pub priority: i64,
+    pub declared: usize,
+}
+
+impl DFA {
+    /// Builds an automaton without checking it. An accepting start state is only reported once
+    /// scanning reaches an empty match, as `LexError::ZeroLengthToken`; prefer `try_new`, or call
+    /// `check_start` once an automaton built from scratch with `add_transition` and `add_accept`
+    /// is complete.
+    pub fn new(transitions: HashMap<(usize, char), usize>, start: usize, accept: HashMap<usize, String>) -> Self {
+        DFA {
+            transitions,
+            intervals: HashMap::new(),
+            negated: HashMap::new(),
+            start,
+            accept,
+            state_names: HashMap::new(),
+            categories: HashMap::new(),
+            accept_labels: HashMap::new(),
+            trivia: HashSet::new(),
+            actions: HashMap::new(),
+            declared_alphabet: None,
+            rule_ranks: HashMap::new(),
+        }
+    }
+
+    /// Like `new`, but rejects an automaton whose start state is accepting with
+    /// `LexError::AcceptingStart`. Such an automaton lets a token match the empty lexeme, which the
+    /// scanner can only report as `LexError::ZeroLengthToken` once it reaches a character no
+    /// token starts with; checking up front catches the mistake before any input is read.
+    pub fn try_new(
+        transitions: HashMap<(usize, char), usize>,
+        start: usize,
+        accept: HashMap<usize, String>,
+    ) -> Result<Self, LexError> {
+        let dfa = DFA::new(transitions, start, accept);
+        dfa.check_start()?;
+        Ok(dfa)
+    }
+
+    /// Fails with `LexError::AcceptingStart` when the start state is accepting, e.g. after
+    /// editing an automaton built with `new`.
+    pub fn check_start(&self) -> Result<(), LexError> {
+        match self.accept.contains_key(&self.start) {
+            true => Err(LexError::AcceptingStart { state: self.start }),
+            false => Ok(()),
+        }
+    }
+
+    /// Builds a DFA from a table read with `read_transition_table_from_csv`.
+    pub fn from_table(table: TransitionTable, start: usize, accept: HashMap<usize, String>) -> Self {
+        let mut dfa = DFA::new(table.transitions, start, accept);
+        dfa.intervals = table.intervals;
+        dfa.negated = table.negated;
+        dfa
+    }
+
+    /// Loads the automaton defined by a transitions CSV file (read with
+    /// `read_transition_table_from_csv`) and a final-states JSON file, starting in state 0 like
+    /// the command line does. Fails like `try_new` when the start state is accepting.
+    #[cfg(all(feature = "csv", feature = "json"))]
+    pub fn from_files(transitions_path: &str, accept_path: &str) -> Result<Self, Box<dyn Error>> {
+        let table = read_transition_table_from_csv(transitions_path)?;
+        let dfa = DFA::from_table(table, 0, read_accept_states_from_json(accept_path)?);
+        dfa.check_start()?;
+        Ok(dfa)
+    }
+
+    /// Attaches a state-name mapping, typically read with `read_state_names_from_json`.
+    pub fn with_state_names(mut self, state_names: HashMap<usize, String>) -> Self {
+        self.state_names = state_names;
+        self
+    }
+
+    /// Attaches token categories of accept states, typically read with
+    /// `read_accept_categories_from_json`.
+    pub fn with_categories(mut self, categories: HashMap<usize, String>) -> Self {
+        self.categories = categories;
+        self
+    }
+
+    /// Attaches the action names of accept states, typically read with
+    /// `read_accept_actions_from_csv`.
+    pub fn with_actions(mut self, actions: HashMap<usize, String>) -> Self {
+        self.actions = actions;
+        self
+    }
+
+    /// Declares the alphabet of the input, e.g. `"0123456789+-*/()".chars()`, so that characters
+    /// outside it are reported as `LexError::CharacterNotInAlphabet`.
+    pub fn with_alphabet(mut self, alphabet: impl IntoIterator<Item = char>) -> Self {
+        self.declared_alphabet = Some(alphabet.into_iter().collect());
+        self
+    }
+
+    /// Declares the characters of the transition table, see `alphabet`, as the alphabet of the
+    /// input. Characters only matched through a negated class are then rejected.
+    pub fn with_inferred_alphabet(self) -> Self {
+        let alphabet = self.alphabet();
+        self.with_alphabet(alphabet)
+    }
+
+    /// Attaches the ranks of the labels, typically read with `read_rule_ranks_from_csv` or
+    /// `read_rule_ranks_from_json`, and reorders every list of `accept_labels` by them: highest
+    /// priority first, then earliest declared. Labels without a rank come last, in their previous
+    /// order. Each state's first label becomes its `accept` label, so call this once the
+    /// automaton is complete, e.g. after `union`.
+    pub fn with_rule_ranks(mut self, ranks: HashMap<String, RuleRank>) -> Self {
+        self.rule_ranks = ranks;
+        for (&state, labels) in &mut self.accept_labels {
+            labels.sort_by_key(|label| {
+                let rank = self.rule_ranks.get(label);
+                (rank.is_none(), rank.map(|rank| (Reverse(rank.priority), rank.declared)))
+            });
+            if let Some(first) = labels.first() {
+                self.accept.insert(state, first.clone());
+            }
+        }
+        self
+    }
+
+    /// Marks the tokens labelled with one of `labels` as trivia, e.g. `["whitespace", "comment"]`
+    /// for an automaton that keeps them as tokens, or the labels read with
+    /// `read_trivia_labels_from_csv`.
+    pub fn with_trivia(mut self, labels: &[impl AsRef<str>]) -> Self {
+        self.trivia = labels.iter().map(|label| label.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Attaches the label lists of accept states, typically read with
+    /// `read_accept_label_lists_from_json`. Each state's first label also becomes its `accept` label.
+    pub fn with_accept_labels(mut self, accept_labels: HashMap<usize, Vec<String>>) -> Self {
+        for (&state, labels) in &accept_labels {
+            if let Some(first) = labels.first() {
+                self.accept.insert(state, first.clone());
+            }
+        }
+        self.accept_labels = accept_labels;
+        self
+    }
+
+    /// Adds a transition from `from` to `to` on `ch`, replacing any previous one.
+    pub fn add_transition(&mut self, from: usize, ch: char, to: usize) -> &mut Self {
+        self.transitions.insert((from, ch), to);
+        self
+    }
+
+    /// Adds a transition from `from` to `to` on every character in `start..=end`.
+    /// Fails if the range is empty or overlaps another interval of the same state.
+    pub fn add_interval(&mut self, from: usize, start: char, end: char, to: usize) -> Result<&mut Self, Box<dyn Error>> {
+        insert_interval(self.intervals.entry(from).or_default(), from, start, end, to)?;
+        Ok(self)
+    }
+
+    /// Looks up the interval transition of `state` covering `ch`.
+    pub fn interval_target(&self, state: usize, ch: char) -> Option<usize> {
+        let intervals = self.intervals.get(&state)?;
+        let index = intervals.partition_point(|&(start, _, _)| start <= ch);
+        match index.checked_sub(1).map(|index| intervals[index]) {
+            Some((_, end, to)) if ch <= end => Some(to),
+            _ => None,
+        }
+    }
+
+    /// Adds a transition from `from` to `to` on every character not in `excluded`, like `[^...]`.
+    /// Fails if `from` already has a negated class.
+    pub fn add_negated(&mut self, from: usize, excluded: &[char], to: usize) -> Result<&mut Self, Box<dyn Error>> {
+        insert_negated(&mut self.negated, from, excluded.to_vec(), to)?;
+        Ok(self)
+    }
+
+    /// Looks up the negated-class transition of `state` for `ch`.
+    pub fn negated_target(&self, state: usize, ch: char) -> Option<usize> {
+        let (excluded, to) = self.negated.get(&state)?;
+        excluded.binary_search(&ch).is_err().then_some(*to)
+    }
+
+    /// Marks `state` as accepting with the given label.
+    pub fn add_accept(&mut self, state: usize, label: &str) -> &mut Self {
+        self.accept.insert(state, label.to_string());
+        self
+    }
+
+    /// Marks `state` as accepting with several labels in priority order, see `accept_labels`.
+    /// A single label is the same as `add_accept`; an empty list changes nothing.
+    pub fn add_accept_labels(&mut self, state: usize, labels: &[&str]) -> &mut Self {
+        match labels {
+            [] => {}
+            [label] => {
+                self.add_accept(state, label);
+            }
+            [first, ..] => {
+                self.add_accept(state, first);
+                self.accept_labels.insert(state, labels.iter().map(|label| label.to_string()).collect());
+            }
+        }
+        self
+    }
+
+    /// The exact-character transitions as `(from, ch, to)` edges, sorted by `from` then `ch`, so
+    /// tools built on it produce reproducible output. Interval and negated-class transitions are
+    /// in `intervals` and `negated`.
+    pub fn iter_transitions(&self) -> impl Iterator<Item = (usize, char, usize)> + '_ {
+        let mut edges: Vec<(usize, char, usize)> =
+            self.transitions.iter().map(|(&(from, ch), &to)| (from, ch, to)).collect();
+        edges.sort_unstable();
+        edges.into_iter()
+    }
+
+    /// All states mentioned by the automaton: the start state, accept states and both ends of
+    /// every transition.
+    pub fn states(&self) -> BTreeSet<usize> {
+        let mut states = BTreeSet::from([self.start]);
+        states.extend(self.accept.keys());
+        for (&(from, _), &to) in &self.transitions {
+            states.extend([from, to]);
+        }
+        for (&from, intervals) in &self.intervals {
+            states.insert(from);
+            states.extend(intervals.iter().map(|&(_, _, to)| to));
+        }
+        for (&from, &(_, to)) in &self.negated {
+            states.extend([from, to]);
+        }
+        states
+    }
+
+    /// The characters named by the transition table: exact inputs, every character of each
+    /// interval and the characters excluded by negated classes. Characters only matched through
+    /// a negated class are not included, as there are unboundedly many of them.
+    pub fn alphabet(&self) -> BTreeSet<char> {
+        let mut alphabet: BTreeSet<char> = self.transitions.keys().map(|&(_, ch)| ch).collect();
+        for intervals in self.intervals.values() {
+            for &(start, end, _) in intervals {
+                alphabet.extend(start..=end);
+            }
+        }
+        for (excluded, _) in self.negated.values() {
+            alphabet.extend(excluded);
+        }
+        alphabet
+    }
+
+    /// Every `token_type` the automaton can give a token, sorted: the accept labels, including
+    /// those only listed in `accept_labels`, e.g. to declare the styles of a syntax highlighter.
+    /// The `NEWLINE` and `ERROR` types the scanner adds on request and the `|`-joined labels of
+    /// `LabelPolicy::All` are not included.
+    pub fn possible_token_types(&self) -> BTreeSet<String> {
+        let listed = self.accept_labels.values().flatten();
+        self.accept.values().chain(listed).cloned().collect()
+    }
+
+    /// Returns whether the whole of `input` is accepted, i.e. leads from the start state to an
+    /// accept state. Unlike scanning, no whitespace is skipped and no token is split off.
+    pub fn accepts(&self, input: &str) -> bool {
+        self.matches_whole(input).is_some()
+    }
+
+    /// The label of the token `input` is when it is exactly one token, e.g. to check that a
+    /// configuration value is a valid identifier. Every character must be consumed and the state
+    /// reached must accept; a token followed by anything, even a space, gives `None`.
+    pub fn matches_whole(&self, input: &str) -> Option<&String> {
+        self.accept.get(&self.run(input)?)
+    }
+
+    /// The state reached from the start state over all of `input`, if every character has a transition.
+    fn run(&self, input: &str) -> Option<usize> {
+        let mut state = self.start;
+        for ch in input.chars() {
+            state = self.next_state(state, ch)?;
+        }
+        Some(state)
+    }
+
+    /// Returns the name of a state, falling back to its numeric id.
+    pub fn state_name(&self, state: usize) -> String {
+        match self.state_names.get(&state) {
+            Some(name) => name.clone(),
+            None => state.to_string(),
+        }
+    }
+}
+
+/// Inserts an interval into a sorted interval list, rejecting empty and overlapping ranges.
+fn insert_interval(
+    intervals: &mut Vec<(char, char, usize)>,
+    from: usize,
+    start: char,
+    end: char,
+    to: usize,
+) -> Result<(), Box<dyn Error>> {
+    if start > end {
+        return Err(format!("Empty character range {}-{} from state {}", start, end, from).into());
+    }
+    let index = intervals.partition_point(|&(other_start, _, _)| other_start < start);
+    let overlaps_previous = index > 0 && intervals[index - 1].1 >= start;
+    let overlaps_next = index < intervals.len() && intervals[index].0 <= end;
+    if overlaps_previous || overlaps_next {
+        return Err(format!("Character range {}-{} from state {} overlaps another range", start, end, from).into());
+    }
+    intervals.insert(index, (start, end, to));
+    Ok(())
+}
+
+/// Records the negated class of a state, keeping the excluded characters sorted for lookups.
+fn insert_negated(
+    negated: &mut HashMap<usize, (Vec<char>, usize)>,
+    from: usize,
+    mut excluded: Vec<char>,
+    to: usize,
+) -> Result<(), Box<dyn Error>> {
+    if negated.contains_key(&from) {
+        return Err(format!("State {} has more than one negated character class", from).into());
+    }
+    excluded.sort_unstable();
+    excluded.dedup();
+    negated.insert(from, (excluded, to));
+    Ok(())
+}
+
+/// Transitions read from a CSV file, split into single characters, character ranges and
+/// negated character classes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransitionTable {
+    pub transitions: HashMap<(usize, char), usize>,
+    pub intervals: HashMap<usize, Vec<(char, char, usize)>>,
+    pub negated: HashMap<usize, (Vec<char>, usize)>,
+}
+
+/// The symbol a CSV transition row reads.
+#[cfg(feature = "csv")]
+enum TransitionInput {
+    Char(char),
+    /// An inclusive range written as `a-z`.
+    Interval(char, char),
+    /// Any character except the listed ones, written as `[^...]`.
+    Negated(Vec<char>),
+}
+
+#[cfg(feature = "csv")]
+fn parse_transition_input(input: &str) -> Result<TransitionInput, Box<dyn Error>> {
+    let chars: Vec<char> = input.chars().collect();
+    match chars.as_slice() {
+        [] => Err("Empty input field".into()),
+        [ch] => Ok(TransitionInput::Char(*ch)),
+        [start, '-', end] => Ok(TransitionInput::Interval(*start, *end)),
+        ['[', '^', class @ .., ']'] => Ok(TransitionInput::Negated(parse_class_chars(class)?)),
+        _ => Err("Expected a single character, a character range or a negated class for input field".into()),
+    }
+}
+
+/// Reads the characters listed inside a `[^...]` class. A backslash escapes the next character,
+/// with `\n`, `\r` and `\t` standing for newline, carriage return and tab.
+#[cfg(feature = "csv")]
+fn parse_class_chars(class: &[char]) -> Result<Vec<char>, Box<dyn Error>> {
+    let mut chars = Vec::with_capacity(class.len());
+    let mut iter = class.iter();
+    while let Some(&ch) = iter.next() {
+        if ch != '\\' {
+            chars.push(ch);
+            continue;
+        }
+        match iter.next() {
+            Some('n') => chars.push('\n'),
+            Some('r') => chars.push('\r'),
+            Some('t') => chars.push('\t'),
+            Some(&escaped) => chars.push(escaped),
+            None => return Err("Dangling escape in negated character class".into()),
+        }
+    }
+    Ok(chars)
+}
+
+#[cfg(feature = "csv")]
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
 struct DfaTransitionRecord {
-    From: usize,
-    Input: String,
-    To: usize,
+    from: usize,
+    input: String,
+    to: usize,
+}
+
+/// Prefix of a transition CSV line that pulls in the rows of another file.
+#[cfg(feature = "csv")]
+pub const INCLUDE_DIRECTIVE: &str = "#include ";
+
+/// Reads the transition rows of a CSV file, expanding `#include other.csv` lines in place with the
+/// rows of the named file, resolved relative to the including file's directory. `including`
+/// holds the files currently being read, to report include cycles.
+#[cfg(feature = "csv")]
+fn read_transition_records(path: &Path, including: &mut Vec<PathBuf>) -> Result<Vec<DfaTransitionRecord>, Box<dyn Error>> {
+    let canonical = fs::canonicalize(path).map_err(|err| format!("Cannot read {}: {}", path.display(), err))?;
+    if let Some(index) = including.iter().position(|file| *file == canonical) {
+        let cycle: Vec<String> = including[index..].iter().chain([&canonical]).map(|file| file.display().to_string()).collect();
+        return Err(format!("Cyclic include: {}", cycle.join(" -> ")).into());
+    }
+    including.push(canonical);
+
+    let reader = BufReader::new(File::open(path)?);
+    let mut csv_reader = csv::ReaderBuilder::new().flexible(true).from_reader(reader);
+    let headers = csv_reader.headers()?.clone();
+    let mut records = Vec::new();
+    for result in csv_reader.records() {
+        let row = result?;
+        match row.get(0).and_then(|field| field.strip_prefix(INCLUDE_DIRECTIVE)) {
+            Some(included) if row.len() == 1 => {
+                let included = path.parent().unwrap_or(Path::new("")).join(included.trim());
+                records.extend(read_transition_records(&included, including)?);
+            }
+            _ => records.push(row.deserialize(Some(&headers))?),
+        }
+    }
+    including.pop();
+    Ok(records)
+}
+
+/// Adds a single-character transition, rejecting a second transition on the same input that
+/// leads elsewhere. Repeating an identical transition, e.g. in two included files, is allowed.
+#[cfg(feature = "csv")]
+fn insert_transition(transitions: &mut HashMap<(usize, char), usize>, from: usize, ch: char, to: usize) -> Result<(), Box<dyn Error>> {
+    match transitions.insert((from, ch), to) {
+        Some(previous) if previous != to => {
+            Err(format!("Conflicting transitions from state {} on {:?}: to {} and to {}", from, ch, previous, to).into())
+        }
+        _ => Ok(()),
+    }
 }
 
 /// Reads a CSV file with DFA transitions and returns a HashMap of transitions.
 /// The CSV file is expected to have headers: "From,Input,To". The Input field should contain a single character.
+/// A line `#include other.csv` merges in the transitions of `other.csv`, relative to this file;
+/// include cycles and transitions that conflict with each other are reported as errors.
+#[cfg(feature = "csv")]
 pub fn read_transitions_from_csv(file_path: &str) -> Result<HashMap<(usize, char), usize>, Box<dyn Error>> {
-    let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
-    let mut csv_reader = csv::Reader::from_reader(reader);
     let mut transitions: HashMap<(usize, char), usize> = HashMap::new();
 
-    for result in csv_reader.deserialize() {
-        let record: DfaTransitionRecord = result?;
-        let mut chars = record.Input.chars();
+    for record in read_transition_records(Path::new(file_path), &mut Vec::new())? {
+        let mut chars = record.input.chars();
         let ch = chars.next().ok_or("Empty input field")?;
         if chars.next().is_some() {
             return Err("Expected a single character for input field".into());
         }
-        transitions.insert((record.From, ch), record.To);
+        insert_transition(&mut transitions, record.from, ch, record.to)?;
     }
     
     Ok(transitions)
 }
 
+/// Reads a CSV file with DFA transitions like `read_transitions_from_csv`, additionally accepting
+/// character ranges such as `a-z` or `0-9` and negated classes such as `[^"\n]` in the Input
+/// field. Ranges are kept as intervals instead of being expanded into one transition per character.
+#[cfg(feature = "csv")]
+pub fn read_transition_table_from_csv(file_path: &str) -> Result<TransitionTable, Box<dyn Error>> {
+    let mut table = TransitionTable::default();
+
+    for record in read_transition_records(Path::new(file_path), &mut Vec::new())? {
+        match parse_transition_input(&record.input)? {
+            TransitionInput::Char(ch) => {
+                insert_transition(&mut table.transitions, record.from, ch, record.to)?;
+            }
+            TransitionInput::Interval(start, end) => {
+                let intervals = table.intervals.entry(record.from).or_default();
+                insert_interval(intervals, record.from, start, end, record.to)?;
+            }
+            TransitionInput::Negated(excluded) => {
+                insert_negated(&mut table.negated, record.from, excluded, record.to)?;
+            }
+        }
+    }
+
+    Ok(table)
+}
+
+/// An entry of the final-states JSON file: a state and its label, optionally followed by the
+/// category of its tokens.
+#[cfg(feature = "json")]
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum AcceptRecord {
+    Labelled(usize, AcceptLabels),
+    Categorized(usize, AcceptLabels, String),
+}
+
+/// The label of a final-states entry, or the list of labels of a state standing for several.
+#[cfg(feature = "json")]
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum AcceptLabels {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[cfg(feature = "json")]
+impl AcceptRecord {
+    fn state_and_labels(self) -> (usize, Vec<String>) {
+        let (AcceptRecord::Labelled(state, labels) | AcceptRecord::Categorized(state, labels, _)) = self;
+        match labels {
+            AcceptLabels::One(label) => (state, vec![label]),
+            AcceptLabels::Many(labels) => (state, labels),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+fn read_accept_records(file_path: &str) -> Result<Vec<AcceptRecord>, Box<dyn Error>> {
+    let content = fs::read_to_string(file_path)?;
+    #[cfg(feature = "json5")]
+    if std::path::Path::new(file_path).extension().is_some_and(|ext| ext == "json5") {
+        return Ok(serde_json::from_str(&crate::json5::to_json(&content)?)?);
+    }
+    Ok(serde_json::from_str(&content)?)
+}
+
 /// Reads a JSON file containing DFA final states and returns a HashMap mapping
 /// accept states (usize) to their label (String).
 ///
@@ -53,111 +585,1721 @@ pub fn read_transitions_from_csv(file_path: &str) -> Result<HashMap<(usize, char
 ///   [1, "double quotes"],
 ///   ...
 /// ]
+/// An entry may carry a third element, the token category read by `read_accept_categories_from_json`.
+/// The label may also be a list, e.g. `[7, ["keyword", "identifier"]]`; the first one is returned
+/// here and the whole list by `read_accept_label_lists_from_json`.
+/// With the `json5` feature, files ending in `.json5` may also use comments and trailing commas.
+#[cfg(feature = "json")]
 pub fn read_accept_states_from_json(file_path: &str) -> Result<HashMap<usize, String>, Box<dyn Error>> {
-    let content = fs::read_to_string(file_path)?;
-    let records: Vec<(usize, String)> = serde_json::from_str(&content)?;
     let mut accept_states = HashMap::new();
-    for (state, label) in records {
-        accept_states.insert(state, label);
+    for (state, mut labels) in read_accept_label_records(file_path)? {
+        accept_states.insert(state, labels.swap_remove(0));
     }
     Ok(accept_states)
 }
 
-/// Helper function that computes the line and column number for a given index in the input.
-fn compute_line_and_column(input: &[char], index: usize) -> (usize, usize) {
-    let mut line: usize = 1;
-    let mut column: usize = 1;
-    for &ch in &input[0..index] {
-        if ch == '\n' {
-            line += 1;
-            column = 1;
-        } else {
-            column += 1;
+/// Reads the entries of a final-states JSON file whose label is a list of two or more labels,
+/// for `DFA::with_accept_labels`.
+#[cfg(feature = "json")]
+pub fn read_accept_label_lists_from_json(file_path: &str) -> Result<HashMap<usize, Vec<String>>, Box<dyn Error>> {
+    Ok(read_accept_label_records(file_path)?.into_iter().filter(|(_, labels)| labels.len() > 1).collect())
+}
+
+#[cfg(feature = "json")]
+fn read_accept_label_records(file_path: &str) -> Result<HashMap<usize, Vec<String>>, Box<dyn Error>> {
+    let mut records = HashMap::new();
+    for record in read_accept_records(file_path)? {
+        let (state, labels) = record.state_and_labels();
+        if labels.is_empty() {
+            return Err(format!("Accept state {} has an empty label list", state).into());
         }
+        records.insert(state, labels);
     }
-    (line, column)
+    Ok(records)
 }
 
-/// Processes the input string, scanning it using the provided DFA and returning tokens.
-/// If an invalid transition is encountered, the function panics with the line and column of the error.
-pub fn process_input(dfa: &DFA, input: &str) -> Vec<Token> {
-    let mut tokens: Vec<Token> = Vec::new();
+/// Reads the declaration order of the labels of a final-states JSON file, for
+/// `DFA::with_rule_ranks`: labels are numbered in the order they first appear, those of a list
+/// from left to right. The JSON layout has no priorities, so every priority is 0.
+#[cfg(feature = "json")]
+pub fn read_rule_ranks_from_json(file_path: &str) -> Result<HashMap<String, RuleRank>, Box<dyn Error>> {
+    let mut ranks = HashMap::new();
+    for record in read_accept_records(file_path)? {
+        for label in record.state_and_labels().1 {
+            let declared = ranks.len();
+            ranks.entry(label).or_insert(RuleRank { priority: 0, declared });
+        }
+    }
+    Ok(ranks)
+}
+
+/// Reads the token categories from a final-states JSON file whose entries have a third
+/// element, e.g. `[12, "sum", "operator"]`. Entries without a category are skipped.
+#[cfg(feature = "json")]
+pub fn read_accept_categories_from_json(file_path: &str) -> Result<HashMap<usize, String>, Box<dyn Error>> {
+    let mut categories = HashMap::new();
+    for record in read_accept_records(file_path)? {
+        if let AcceptRecord::Categorized(state, _, category) = record {
+            categories.insert(state, category);
+        }
+    }
+    Ok(categories)
+}
+
+#[cfg(feature = "csv")]
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AcceptStateRecord {
+    state: usize,
+    label: String,
+    #[serde(default)]
+    category: Option<String>,
+    #[serde(default)]
+    action: Option<String>,
+    #[serde(default)]
+    priority: Option<i64>,
+    #[serde(default)]
+    trivia: Option<bool>,
+}
+
+#[cfg(feature = "csv")]
+fn read_accept_state_records(file_path: &str) -> Result<Vec<AcceptStateRecord>, Box<dyn Error>> {
+    let mut csv_reader = csv::ReaderBuilder::new().flexible(true).from_reader(BufReader::new(File::open(file_path)?));
+    csv_reader.deserialize().map(|record| Ok(record?)).collect()
+}
+
+/// Reads the accept states from a CSV file with headers `State,Label`, the CSV counterpart of
+/// `read_accept_states_from_json`. Optional `Category`, `Action`, `Priority` and `Trivia` columns
+/// may follow; they are read by `read_accept_categories_from_csv`, `read_accept_actions_from_csv`,
+/// `read_rule_ranks_from_csv` and `read_trivia_labels_from_csv`, and other columns are ignored.
+#[cfg(feature = "csv")]
+pub fn read_accept_states_from_csv(file_path: &str) -> Result<HashMap<usize, String>, Box<dyn Error>> {
+    Ok(read_accept_state_records(file_path)?.into_iter().map(|record| (record.state, record.label)).collect())
+}
+
+/// Reads the `Category` column of an accept-states CSV file. Rows with an empty or missing
+/// category are skipped.
+#[cfg(feature = "csv")]
+pub fn read_accept_categories_from_csv(file_path: &str) -> Result<HashMap<usize, String>, Box<dyn Error>> {
+    let records = read_accept_state_records(file_path)?;
+    Ok(records.into_iter().filter_map(|record| Some((record.state, record.category?))).collect())
+}
+
+/// Reads the rank of every label of an accept-states CSV file, for `DFA::with_rule_ranks`: the
+/// `Priority` column of the first row carrying the label (0 when empty or missing), and the
+/// order in which the labels first appear.
+#[cfg(feature = "csv")]
+pub fn read_rule_ranks_from_csv(file_path: &str) -> Result<HashMap<String, RuleRank>, Box<dyn Error>> {
+    let mut ranks = HashMap::new();
+    for record in read_accept_state_records(file_path)? {
+        let declared = ranks.len();
+        ranks.entry(record.label).or_insert(RuleRank { priority: record.priority.unwrap_or(0), declared });
+    }
+    Ok(ranks)
+}
+
+/// Reads the `Action` column of an accept-states CSV file. Rows with an empty or missing action
+/// are skipped.
+#[cfg(feature = "csv")]
+pub fn read_accept_actions_from_csv(file_path: &str) -> Result<HashMap<usize, String>, Box<dyn Error>> {
+    let records = read_accept_state_records(file_path)?;
+    Ok(records.into_iter().filter_map(|record| Some((record.state, record.action?))).collect())
+}
+
+/// Reads the labels of the rows whose `Trivia` column is `true` in an accept-states CSV file, in
+/// the order they first appear, for `DFA::with_trivia`. Rows with an empty or missing value are
+/// not trivia.
+#[cfg(feature = "csv")]
+pub fn read_trivia_labels_from_csv(file_path: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut labels: Vec<String> = Vec::new();
+    for record in read_accept_state_records(file_path)? {
+        if record.trivia == Some(true) && !labels.contains(&record.label) {
+            labels.push(record.label);
+        }
+    }
+    Ok(labels)
+}
+
+/// Reads a JSON file mapping state ids to human-readable names, in the same
+/// array-of-arrays layout as the final-states file, e.g.:
+/// [
+///   [0, "start"],
+///   [7, "in_string"],
+///   ...
+/// ]
+#[cfg(feature = "json")]
+pub fn read_state_names_from_json(file_path: &str) -> Result<HashMap<usize, String>, Box<dyn Error>> {
+    read_accept_states_from_json(file_path)
+}
+
+impl Transitions for DFA {
+    fn start(&self) -> usize {
+        self.start
+    }
+
+    fn next_state(&self, state: usize, ch: char) -> Option<usize> {
+        match self.transitions.get(&(state, ch)) {
+            Some(&next) => Some(next),
+            None => self
+                .interval_target(state, ch)
+                .or_else(|| self.negated_target(state, ch)),
+        }
+    }
+
+    fn accept_label(&self, state: usize) -> Option<&str> {
+        self.accept.get(&state).map(String::as_str)
+    }
+
+    fn accept_category(&self, state: usize) -> Option<&str> {
+        self.categories.get(&state).map(String::as_str)
+    }
+
+    fn is_trivia(&self, label: &str) -> bool {
+        self.trivia.contains(label)
+    }
+
+    fn in_alphabet(&self, ch: char) -> bool {
+        self.declared_alphabet.as_ref().is_none_or(|alphabet| alphabet.contains(&ch))
+    }
+
+    fn accept_labels(&self, state: usize) -> Option<&[String]> {
+        self.accept_labels.get(&state).map(Vec::as_slice)
+    }
+}
+
+/// Everything `process_input_with_config` can be told, in one place. The default configuration
+/// behaves like `try_process_input`.
+#[derive(Debug, Clone, Default)]
+pub struct LexerConfig {
+    /// Whitespace, trivia and newline handling of the scanner.
+    pub scan: ScanOptions,
+    /// Contextual keywords recorded on matching tokens, see `passes::annotate_soft_keywords`.
+    pub soft_keywords: HashSet<String>,
+    /// The lexeme normalization of each token type that gets a normalized form, see
+    /// `passes::normalize_lexemes`.
+    pub normalizers: HashMap<String, fn(&str) -> String>,
+    /// User-registered actions by name, looked up before `passes::builtin_action`.
+    pub actions: HashMap<String, Action>,
+}
+
+impl LexerConfig {
+    pub fn builder() -> LexerConfigBuilder {
+        LexerConfigBuilder::default()
+    }
+}
+
+/// Builds a `LexerConfig` one option at a time and checks that the options fit together.
+#[derive(Debug, Clone, Default)]
+pub struct LexerConfigBuilder {
+    config: LexerConfig,
+}
+
+impl LexerConfigBuilder {
+    /// Columns between two tab stops; must be at least 1. Defaults to 1.
+    pub fn tab_width(&mut self, tab_width: usize) -> &mut Self {
+        self.config.scan.tab_width = tab_width;
+        self
+    }
+
+    /// Keeps skipped whitespace and comments on the tokens, see `process_input_with_trivia`.
+    pub fn keep_whitespace(&mut self, keep: bool) -> &mut Self {
+        self.config.scan.preserve_trivia = keep;
+        self
+    }
+
+    /// Chooses which token preserved trivia goes to, see `ScanOptions::trivia_policy`. Needs
+    /// `keep_whitespace`.
+    pub fn trivia_policy(&mut self, policy: TriviaPolicy) -> &mut Self {
+        self.config.scan.trivia_policy = policy;
+        self
+    }
+
+    pub fn newlines(&mut self, newlines: NewlineMode) -> &mut Self {
+        self.config.scan.newlines = newlines;
+        self
+    }
+
+    /// Skips comments from `open` to the end of the line.
+    pub fn line_comment(&mut self, open: &str) -> &mut Self {
+        self.config.scan.line_comment = Some(open.to_string());
+        self
+    }
+
+    /// Skips comments from `open` to `close`.
+    pub fn block_comment(&mut self, open: &str, close: &str) -> &mut Self {
+        self.config.scan.block_comment = Some((open.to_string(), close.to_string()));
+        self
+    }
+
+    /// Lets block comments nest; requires `block_comment`.
+    pub fn nested_block_comments(&mut self, nested: bool) -> &mut Self {
+        self.config.scan.nested_block_comments = nested;
+        self
+    }
+
+    /// Rejects tokens that run into the next word, see `ScanOptions::strict_boundaries`.
+    pub fn strict_boundaries(&mut self, strict: bool) -> &mut Self {
+        self.config.scan.strict_boundaries = strict;
+        self
+    }
+
+    /// Turns unmatchable input into `ERROR` tokens instead of failing, see
+    /// `ScanOptions::recover_errors`.
+    pub fn recover_errors(&mut self, recover: bool) -> &mut Self {
+        self.config.scan.recover_errors = recover;
+        self
+    }
+
+    /// Chooses the label of tokens whose accept state has several, see `ScanOptions::label_policy`.
+    pub fn label_policy(&mut self, policy: LabelPolicy) -> &mut Self {
+        self.config.scan.label_policy = policy;
+        self
+    }
+
+    /// Chooses how control characters in the input are treated, see `ScanOptions::on_control_char`.
+    pub fn on_control_char(&mut self, policy: ControlCharPolicy) -> &mut Self {
+        self.config.scan.on_control_char = policy;
+        self
+    }
+
+    /// Chooses how U+FFFD in the input is treated, see `ScanOptions::on_replacement_char`.
+    pub fn on_replacement_char(&mut self, policy: ReplacementPolicy) -> &mut Self {
+        self.config.scan.on_replacement_char = policy;
+        self
+    }
+
+    /// Replaces the whitespace skip between tokens, see `ScanOptions::skip`.
+    pub fn skip(&mut self, skip: impl Fn(&[char], usize) -> Option<usize> + Send + Sync + 'static) -> &mut Self {
+        self.config.scan.skip = Skip::new(skip);
+        self
+    }
+
+    /// Adds a contextual keyword, see `passes::annotate_soft_keywords`.
+    pub fn soft_keyword(&mut self, keyword: &str) -> &mut Self {
+        self.config.soft_keywords.insert(keyword.to_string());
+        self
+    }
+
+    /// Registers `action` under `name`, for the accept states that name it in `DFA::actions`. A
+    /// user-registered action hides the built-in action of the same name.
+    pub fn action(&mut self, name: &str, action: Action) -> &mut Self {
+        self.config.actions.insert(name.to_string(), action);
+        self
+    }
+
+    /// Normalizes the lexemes of the tokens of type `token_type` with `normalize`. Each type has
+    /// its own function; a later call for the same type replaces it.
+    pub fn normalize(&mut self, token_type: &str, normalize: fn(&str) -> String) -> &mut Self {
+        self.config.normalizers.insert(token_type.to_string(), normalize);
+        self
+    }
+
+    /// Returns the configuration, or `LexError::InvalidConfig` when a tab width is 0, a comment
+    /// delimiter is empty, the line comment opens like a block comment, nesting is asked for
+    /// without block comments, or a trivia policy without keeping whitespace.
+    pub fn build(&self) -> Result<LexerConfig, LexError> {
+        let scan = &self.config.scan;
+        let invalid = |reason: &str| Err(LexError::InvalidConfig { reason: reason.to_string() });
+        if scan.tab_width == 0 {
+            return invalid("the tab width must be at least 1");
+        }
+        let line_open = scan.line_comment.as_deref();
+        let block = scan.block_comment.as_ref();
+        if line_open == Some("") || block.is_some_and(|(open, close)| open.is_empty() || close.is_empty()) {
+            return invalid("comment delimiters cannot be empty");
+        }
+        if line_open.is_some_and(|open| block.is_some_and(|(block_open, _)| open == block_open)) {
+            return invalid("line and block comments cannot open with the same delimiter");
+        }
+        if scan.nested_block_comments && block.is_none() {
+            return invalid("nested block comments require a block comment syntax");
+        }
+        if scan.trivia_policy != TriviaPolicy::Leading && !scan.preserve_trivia {
+            return invalid("a trivia policy requires keep_whitespace");
+        }
+        Ok(self.config.clone())
+    }
+}
+
+/// Tokenizes `input` and runs the passes `config` asks for on the tokens.
+pub fn process_input_with_config(dfa: &DFA, input: &str, config: &LexerConfig) -> Result<Vec<Token>, LexError> {
+    process_input_with_stats(dfa, input, config).map(|(tokens, _)| tokens)
+}
+
+/// Like `process_input_with_config`, and also returns how many characters went into tokens and
+/// how many were skipped, so callers can check that `stats.chars_total()` covers the input.
+pub fn process_input_with_stats(
+    dfa: &DFA,
+    input: &str,
+    config: &LexerConfig,
+) -> Result<(Vec<Token>, LexStats), LexError> {
     let input_chars: Vec<char> = input.chars().collect();
-    let len = input_chars.len();
-    
-    let mut i = 0;
-    while i < len {
-        // Skip whitespace characters.
-        if input_chars[i].is_whitespace() {
-            i += 1;
-            continue;
+    let mut scan = Cow::Borrowed(&config.scan);
+    if !dfa.actions.is_empty() {
+        scan.to_mut().actions = resolve_actions(dfa, config)?;
+    }
+    let (mut tokens, stats) = scanner::scan_with_stats(dfa, &input_chars, &scan)?;
+    if !config.soft_keywords.is_empty() {
+        passes::annotate_soft_keywords(&mut tokens, &config.soft_keywords);
+    }
+    for token in tokens.iter_mut() {
+        if let Some(normalize) = config.normalizers.get(&token.token_type) {
+            token.normalized = Some(normalize(&token.lexeme));
         }
+    }
+    Ok((tokens, stats))
+}
+
+/// Looks up the action every accept state of `dfa` names, first among those registered in `config`.
+fn resolve_actions(dfa: &DFA, config: &LexerConfig) -> Result<BTreeMap<usize, Action>, LexError> {
+    let mut actions = BTreeMap::new();
+    for (&state, name) in &dfa.actions {
+        match config.actions.get(name).copied().or_else(|| passes::builtin_action(name)) {
+            Some(action) => actions.insert(state, action),
+            None => {
+                let reason = format!("accept state {} names the unknown action '{}'", state, name);
+                return Err(LexError::InvalidConfig { reason });
+            }
+        };
+    }
+    Ok(actions)
+}
+
+/// Like `try_process_input`, stopping once `limit` tokens have been produced, e.g. to preview the
+/// start of a large file. The input is scanned in windows of `PREVIEW_WINDOW` bytes, so scanning
+/// stops at the end of the window holding the last token, or of the token still open there.
+/// Returns the tokens and, if anything but whitespace is left, the position just past the last
+/// token, from which `resume_input` continues.
+pub fn process_input_limited(
+    dfa: &DFA,
+    input: &str,
+    limit: Option<usize>,
+) -> Result<(Vec<Token>, Option<Position>), LexError> {
+    resume_input(dfa, input, Position::start(), limit)
+}
 
-        // Start at the DFA's start state.
-        let mut current_state = dfa.start;
-        // Track the last encountered accepting state and its index.
-        let mut last_accept_state: Option<usize> = None;
-        let mut last_accept_index = i;
-        let mut j = i;
-        
-        while j < len {
-            let ch = input_chars[j];
-            if let Some(&next_state) = dfa.transitions.get(&(current_state, ch)) {
-                current_state = next_state;
-                // Record the last accepting state's index.
-                if dfa.accept.contains_key(&current_state) {
-                    last_accept_state = Some(current_state);
-                    last_accept_index = j + 1;
+/// Continues `process_input_limited` from the position `from` it returned, over the same `input`.
+/// Spans, lines and columns of the tokens are those of the whole input.
+pub fn resume_input(
+    dfa: &DFA,
+    input: &str,
+    from: Position,
+    limit: Option<usize>,
+) -> Result<(Vec<Token>, Option<Position>), LexError> {
+    resume_in_windows(dfa, input, from, limit, PREVIEW_WINDOW)
+}
+
+/// How many bytes `process_input_limited` and `resume_input` hand to the scanner at a time.
+pub const PREVIEW_WINDOW: usize = 1 << 12;
+
+fn resume_in_windows(
+    dfa: &DFA,
+    input: &str,
+    from: Position,
+    limit: Option<usize>,
+    window: usize,
+) -> Result<(Vec<Token>, Option<Position>), LexError> {
+    let mut state = IncrementalState { pending: String::new(), position: from };
+    let mut tokens = Vec::new();
+    let mut offset = from.offset;
+    while limit.is_none_or(|limit| tokens.len() < limit) {
+        let result = match offset < input.len() {
+            true => {
+                let mut end = (offset + window).min(input.len());
+                while !input.is_char_boundary(end) {
+                    end += 1;
                 }
-                j += 1;
-            } else {
-                break;
+                let chunk = &input[offset..end];
+                offset = end;
+                process_incremental(dfa, chunk, &mut state, &mut tokens)
             }
+            false => finish_incremental(dfa, &mut state, &mut tokens),
+        };
+        match result {
+            // An error after the limit belongs to a later call.
+            Err(_) if limit.is_some_and(|limit| tokens.len() >= limit) => break,
+            Err(err) => return Err(err),
+            Ok(()) if state.pending.is_empty() && offset == input.len() => break,
+            Ok(()) => {}
         }
-        
-        if let Some(state) = last_accept_state {
-            let lexeme: String = input_chars[i..last_accept_index].iter().collect();
-            let token_label = dfa.accept.get(&state).unwrap().clone();
-            tokens.push(Token::new(token_label, lexeme));
-            i = last_accept_index;
-        } else {
-            // When no valid transition exists, compute line and column and panic with an error message.
-            let (line, column) = compute_line_and_column(&input_chars, i);
-            panic!(
-                "ZigZin compiler: Lexer error at line {}, column {}: Unexpected token '{}'",
-                line, column, input_chars[i]
-            );
+    }
+    if let Some(limit) = limit {
+        tokens.truncate(limit);
+    }
+    let resume = match tokens.last() {
+        Some(last) => {
+            let mut end = Position { line: last.line, column: last.column, offset: last.span.start };
+            last.lexeme.chars().for_each(|ch| end.advance(ch));
+            end
         }
+        None => from,
+    };
+    match input[resume.offset..].chars().all(char::is_whitespace) {
+        true => Ok((tokens, None)),
+        false => Ok((tokens, Some(resume))),
     }
-    tokens
 }
 
-pub fn process_file_input(dfa: &DFA, file_path: &str) -> Result<Vec<Token>, Box<dyn Error>> {
-    let content = std::fs::read_to_string(file_path)?;
-    Ok(process_input(dfa, content.as_str()))
+/// What `process_incremental` keeps between chunks: the input not tokenized yet, which starts
+/// with a token the next chunk may still extend, and where that input starts in the stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncrementalState {
+    pub pending: String,
+    pub position: Position,
 }
 
-#[cfg(test)]
+impl Default for IncrementalState {
+    fn default() -> Self {
+        IncrementalState { pending: String::new(), position: Position::start() }
+    }
+}
+
+/// Tokenizes `chunk`, the next piece of a stream whose earlier pieces were passed with the same
+/// `state`, and appends the tokens known to be complete to `tokens`, including those before an
+/// error. A token is held back in `state` while the automaton can still run from its start to the
+/// end of the input received so far, since more input could make it longer or let it match at
+/// all; the same goes for input no token matches yet. Spans, lines and columns are those of the
+/// whole stream. Call `finish_incremental` once the stream ends.
+pub fn process_incremental(
+    dfa: &DFA,
+    chunk: &str,
+    state: &mut IncrementalState,
+    tokens: &mut impl Extend<Token>,
+) -> Result<(), LexError> {
+    state.pending.push_str(chunk);
+    let base = state.position.offset;
+    let text = &state.pending;
+    let runs_to_end = |offset: usize| {
+        let mut current = dfa.start;
+        text[offset - base..].chars().all(|ch| dfa.next_state(current, ch).map(|next| current = next).is_some())
+    };
+
+    let input_chars: Vec<char> = text.chars().collect();
+    let mut cut = None;
+    for item in scanner::Lexer::new(dfa, input_chars, ScanOptions::default()).starting_at(state.position) {
+        match item {
+            Ok(token) if runs_to_end(token.span.start) => {
+                cut = Some(Position { line: token.line, column: token.column, offset: token.span.start });
+                break;
+            }
+            Ok(token) => tokens.extend(Some(token)),
+            Err(err) if runs_to_end(err.span().start) => {
+                let (line, column) = err.position();
+                cut = Some(Position { line, column, offset: err.span().start });
+                break;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    let cut = cut.unwrap_or_else(|| {
+        let mut end = state.position;
+        text.chars().for_each(|ch| end.advance(ch));
+        end
+    });
+    state.pending.drain(..cut.offset - base);
+    state.position = cut;
+    Ok(())
+}
+
+/// Tokenizes what `process_incremental` held back once the stream has ended, leaving `state` empty,
+/// and appends the tokens to `tokens` as `process_incremental` does.
+pub fn finish_incremental(
+    dfa: &DFA,
+    state: &mut IncrementalState,
+    tokens: &mut impl Extend<Token>,
+) -> Result<(), LexError> {
+    let IncrementalState { pending, position } = std::mem::take(state);
+    let input_chars: Vec<char> = pending.chars().collect();
+    for item in scanner::Lexer::new(dfa, input_chars, ScanOptions::default()).starting_at(position) {
+        tokens.extend(Some(item?));
+    }
+    Ok(())
+}
+
+/// Tokenizes a `BufRead` source such as stdin or a large file as it is read, holding only the
+/// current buffer and any token that straddles its end, and yields each token with its location
+/// like `tokenize_with_positions`. Lines, columns and byte offsets run on across buffer refills,
+/// and a character split between two buffers is decoded once both halves are in. Invalid UTF-8 or
+/// a lexing error is yielded once, after every token before it, and ends the iteration, so the
+/// items are those of `scanner::Lexer` over the whole input.
+pub struct BufReadLexer<'a, R> {
+    dfa: &'a DFA,
+    reader: R,
+    state: IncrementalState,
+    /// Bytes read but not decoded yet: the start of a character the next buffer completes.
+    undecoded: Vec<u8>,
+    /// Position just past the decoded text.
+    decoded_end: Position,
+    ready: VecDeque<Token>,
+    error: Option<Box<dyn Error>>,
+    finished: bool,
+}
+
+impl<'a, R: BufRead> BufReadLexer<'a, R> {
+    pub fn new(dfa: &'a DFA, reader: R) -> Self {
+        BufReadLexer {
+            dfa,
+            reader,
+            state: IncrementalState::default(),
+            undecoded: Vec::new(),
+            decoded_end: Position::start(),
+            ready: VecDeque::new(),
+            error: None,
+            finished: false,
+        }
+    }
+
+    /// Reads and tokenizes the next buffer, or the rest held back at the end of the input.
+    fn refill(&mut self) -> Result<(), Box<dyn Error>> {
+        let buffer = self.reader.fill_buf()?;
+        if buffer.is_empty() {
+            self.finished = true;
+            finish_incremental(self.dfa, &mut self.state, &mut self.ready)?;
+            if !self.undecoded.is_empty() {
+                return Err(self.invalid_utf8(self.undecoded.len()).into());
+            }
+            return Ok(());
+        }
+        let len = buffer.len();
+        self.undecoded.extend_from_slice(buffer);
+        self.reader.consume(len);
+        let (valid, invalid_len) = match std::str::from_utf8(&self.undecoded) {
+            Ok(text) => (text.len(), None),
+            Err(err) => (err.valid_up_to(), err.error_len()),
+        };
+        let text = String::from_utf8(self.undecoded.drain(..valid).collect()).unwrap();
+        text.chars().for_each(|ch| self.decoded_end.advance(ch));
+        process_incremental(self.dfa, &text, &mut self.state, &mut self.ready)?;
+        if let Some(len) = invalid_len {
+            // The tokens held back end at the invalid bytes, so the stream ends there.
+            self.finished = true;
+            finish_incremental(self.dfa, &mut self.state, &mut self.ready)?;
+            return Err(self.invalid_utf8(len).into());
+        }
+        Ok(())
+    }
+
+    fn invalid_utf8(&self, len: usize) -> LexError {
+        let Position { line, column, offset } = self.decoded_end;
+        LexError::InvalidUtf8 { byte_offset: offset, line, column, len }
+    }
+}
+
+impl<R: BufRead> Iterator for BufReadLexer<'_, R> {
+    type Item = Result<(Token, SourceSpan), Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(token) = self.ready.pop_front() {
+                let mut end = Position { line: token.line, column: token.column, offset: token.span.start };
+                token.lexeme.chars().for_each(|ch| end.advance(ch));
+                let span = SourceSpan {
+                    start_line: token.line,
+                    start_col: token.column,
+                    end_line: end.line,
+                    end_col: end.column,
+                    byte_range: token.span.start..token.span.end,
+                };
+                return Some(Ok((token, span)));
+            }
+            if let Some(err) = self.error.take() {
+                return Some(Err(err));
+            }
+            if self.finished {
+                return None;
+            }
+            // The tokens `refill` completed before failing are yielded before its error.
+            if let Err(err) = self.refill() {
+                self.finished = true;
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+/// Like `try_process_input`, pairing every token with both ends of its location as lines and
+/// columns. The positions come from one `LineIndex` built over the input.
+pub fn tokenize_with_positions(dfa: &DFA, input: &str) -> Result<Vec<(Token, SourceSpan)>, LexError> {
+    let tokens = try_process_input(dfa, input)?;
+    let lines = LineIndex::new(input);
+    Ok(tokens.into_iter().map(|token| {
+        let span = lines.source_span(token.span);
+        (token, span)
+    }).collect())
+}
+
+/// Like `try_process_input`, also returning the `SourceMap` of the text the lexemes form once the
+/// whitespace between them is dropped, i.e. `passes::reconstruct` of the tokens, so an offset in
+/// that text can be traced back to the input. A stream filtered further, e.g. without comments,
+/// gets its own map from `SourceMap::from_tokens`.
+pub fn tokenize_with_source_map(dfa: &DFA, input: &str) -> Result<(Vec<Token>, SourceMap), LexError> {
+    let tokens = try_process_input(dfa, input)?;
+    let map = SourceMap::from_tokens(&tokens);
+    Ok((tokens, map))
+}
+
+/// Tokenizes `input` only to find its errors: scanning recovers as with
+/// `ScanOptions::recover_errors`, and each run of input no token matches is reported as one error
+/// at its first character, in order: the `UnexpectedChar`, `CharacterNotInAlphabet` or
+/// `ZeroLengthToken` that scanning without recovery would stop with there. An empty result means
+/// the input lexes cleanly. Errors recovery does not cover end the check and are reported last.
+pub fn check_input(dfa: &DFA, input: &str) -> Vec<LexError> {
+    let options = ScanOptions { recover_errors: true, ..ScanOptions::default() };
+    let input_chars: Vec<char> = input.chars().collect();
+    let mut errors = Vec::new();
+    for item in scanner::Lexer::new(dfa, input_chars, options) {
+        match item {
+            Ok(token) if token.token_type == scanner::ERROR_TOKEN => {
+                let ch = token.lexeme.chars().next().unwrap_or_default();
+                let (line, column) = (token.line, token.column);
+                let position = Position { line, column, offset: token.span.start };
+                // An accepting start state matches the empty lexeme before anything else fails.
+                errors.push(match dfa.accept.contains_key(&dfa.start) {
+                    true => LexError::ZeroLengthToken { state: dfa.start, line, column, span: Span::new(position.offset, position.offset) },
+                    false => scanner::no_match_error(dfa, ch, position),
+                });
+            }
+            Ok(_) => {}
+            Err(err) => errors.push(err),
+        }
+    }
+    errors
+}
+
+/// Checks that every token could have come from `dfa`: its lexeme on its own must be exactly one
+/// token, see `DFA::matches_whole`, whose label is the token's type or, when the accept state
+/// lists several, one of them or all of them joined by `|` as `LabelPolicy` may choose. Fails
+/// with `LexError::TokenMismatch` at the first token that does not, e.g. in a corrupted token
+/// cache. `NEWLINE` and `ERROR` tokens are not checked, and neither should passes that retype
+/// tokens, such as keyword reclassification, have run. Tokens whose lexeme an accept-state
+/// action rewrote do not lex as themselves either and fail the check.
+pub fn verify_tokens(dfa: &DFA, tokens: &[Token]) -> Result<(), LexError> {
+    let unchecked = [scanner::NEWLINE_TOKEN, scanner::ERROR_TOKEN];
+    for token in tokens.iter().filter(|token| !unchecked.contains(&token.token_type.as_str())) {
+        let state = dfa.run(&token.lexeme).filter(|state| dfa.accept.contains_key(state));
+        let matches = state.is_some_and(|state| {
+            let listed = dfa.accept_labels.get(&state).map(Vec::as_slice).unwrap_or_default();
+            dfa.accept[&state] == token.token_type || listed.contains(&token.token_type) || listed.join("|") == token.token_type
+        });
+        if !matches {
+            return Err(LexError::TokenMismatch {
+                token_type: token.token_type.clone(),
+                found: state.map(|state| dfa.accept[&state].clone()),
+                line: token.line,
+                column: token.column,
+                span: token.span,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Like `try_process_input`, with the scanner behaviour chosen by `options`, e.g. emitting
+/// line breaks as `NEWLINE` tokens for languages where they end statements.
+pub fn process_input_with_options(dfa: &DFA, input: &str, options: &ScanOptions) -> Result<Vec<Token>, LexError> {
+    let input_chars: Vec<char> = input.chars().collect();
+    scanner::scan_with_options(dfa, &input_chars, options)
+}
+
+/// Like `try_process_input`, over characters the caller has already collected, so one buffer can
+/// be reused across several passes over the same input.
+pub fn process_chars(dfa: &DFA, input_chars: &[char]) -> Result<Vec<Token>, LexError> {
+    scanner::scan(dfa, input_chars, false)
+}
+
+/// Processes the input string, scanning it using the provided DFA and returning tokens.
+/// If an invalid transition is encountered, a `LexError` with the position of the offending character is returned.
+///
+/// When several rules match at a position, the token is decided in three steps: the longest
+/// match wins; among rules matching the same longest lexeme, i.e. the labels of one accept state,
+/// the highest priority wins; among equal priorities, the rule declared first wins. Priorities and
+/// declaration order come from `DFA::with_rule_ranks`; without ranks, the labels keep the order
+/// of `accept_labels`. `ScanOptions::label_policy` can override the outcome of the last two steps.
+pub fn try_process_input(dfa: &DFA, input: &str) -> Result<Vec<Token>, LexError> {
+    process_input_with_config(dfa, input, &LexerConfig::default())
+}
+
+/// Like `try_process_input`, but keeps the skipped whitespace on the tokens: each token's
+/// `leading_trivia` holds the whitespace before it and the last token's `trailing_trivia`
+/// holds the whitespace up to the end of the input, so `passes::reconstruct` yields the
+/// original text. An input made only of whitespace produces no tokens and reconstructs as empty.
+pub fn process_input_with_trivia(dfa: &DFA, input: &str) -> Result<Vec<Token>, LexError> {
+    let scan = ScanOptions { preserve_trivia: true, ..ScanOptions::default() };
+    process_input_with_config(dfa, input, &LexerConfig { scan, ..LexerConfig::default() })
+}
+
+/// Processes the input string, scanning it using the provided DFA and returning tokens.
+/// If an invalid transition is encountered, the function panics with the line and column of the error.
+pub fn process_input(dfa: &DFA, input: &str) -> Vec<Token> {
+    match try_process_input(dfa, input) {
+        Ok(tokens) => tokens,
+        Err(err) => panic!("{}", err),
+    }
+}
+
+/// Decodes raw bytes as UTF-8. In strict mode the first invalid sequence is reported with its
+/// byte offset; in lenient mode invalid sequences are replaced with U+FFFD.
+pub fn decode_utf8(bytes: &[u8], lenient: bool) -> Result<Cow<'_, str>, LexError> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Ok(Cow::Borrowed(text)),
+        Err(_) if lenient => Ok(String::from_utf8_lossy(bytes)),
+        Err(err) => {
+            let byte_offset = err.valid_up_to();
+            // The prefix is valid, so it can be used to locate the error.
+            let valid = std::str::from_utf8(&bytes[..byte_offset]).unwrap();
+            let mut position = Position::start();
+            for ch in valid.chars() {
+                position.advance(ch);
+            }
+            Err(LexError::InvalidUtf8 {
+                byte_offset,
+                line: position.line,
+                column: position.column,
+                len: err.error_len().unwrap_or(bytes.len() - byte_offset),
+            })
+        }
+    }
+}
+
+/// Decodes UTF-16 bytes. A leading byte order mark selects little or big endian and is dropped;
+/// without one the input is read as little endian, the usual choice of Windows tools. Unpaired
+/// surrogates and a trailing odd byte are reported with their byte offset in the input and the
+/// line and column, in characters, they follow.
+#[cfg(feature = "utf16")]
+pub fn decode_utf16(bytes: &[u8]) -> Result<String, LexError> {
+    let (big_endian, body_start) = match bytes {
+        [0xFE, 0xFF, ..] => (true, 2),
+        [0xFF, 0xFE, ..] => (false, 2),
+        _ => (false, 0),
+    };
+    let units = bytes[body_start..].chunks_exact(2).map(|pair| {
+        let pair = [pair[0], pair[1]];
+        if big_endian { u16::from_be_bytes(pair) } else { u16::from_le_bytes(pair) }
+    });
+    let mut text = String::with_capacity(bytes.len() / 2);
+    let mut position = Position::start();
+    let mut byte_offset = body_start;
+    for decoded in char::decode_utf16(units) {
+        match decoded {
+            Ok(ch) => {
+                text.push(ch);
+                position.advance(ch);
+                byte_offset += 2 * ch.len_utf16();
+            }
+            Err(_) => {
+                return Err(LexError::InvalidUtf16 { byte_offset, line: position.line, column: position.column, len: 2 });
+            }
+        }
+    }
+    if (bytes.len() - body_start) % 2 == 1 {
+        return Err(LexError::InvalidUtf16 { byte_offset, line: position.line, column: position.column, len: 1 });
+    }
+    Ok(text)
+}
+
+/// Reads a UTF-16 file, decoding it with `decode_utf16`, and tokenizes it. Token spans are byte
+/// ranges of the decoded text and lines and columns count characters, as for UTF-8 input.
+#[cfg(feature = "utf16")]
+pub fn process_utf16_file(dfa: &DFA, file_path: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    let content = fs::read(file_path)?;
+    Ok(try_process_input(dfa, &decode_utf16(&content)?)?)
+}
+
+/// Tokenizes raw bytes, decoding them with `decode_utf8` first.
+pub fn process_bytes(dfa: &DFA, bytes: &[u8], lenient: bool) -> Result<Vec<Token>, LexError> {
+    let content = decode_utf8(bytes, lenient)?;
+    try_process_input(dfa, &content)
+}
+
+/// Reads and tokenizes a file. With the `gzip` feature, paths ending in `.gz` are decompressed first.
+pub fn process_file_input(dfa: &DFA, file_path: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    #[cfg(feature = "gzip")]
+    if file_path.ends_with(".gz") {
+        return process_gz_file(dfa, file_path);
+    }
+    let content = fs::read(file_path)?;
+    Ok(process_bytes(dfa, &content, false)?)
+}
+
+/// Like `process_file_input`, but streams the file through a `BufReadLexer` instead of reading it
+/// whole, and calls `on_progress(bytes_done, bytes_total)` about every `PROGRESS_INTERVAL` bytes
+/// read and once at the end of the file. Byte counts refer to the file on disk, so for a `.gz`
+/// path they count compressed bytes, and `bytes_total` is the file size from its metadata.
+pub fn process_file_with_progress(
+    dfa: &DFA,
+    file_path: &str,
+    on_progress: impl FnMut(usize, usize),
+) -> Result<Vec<Token>, Box<dyn Error>> {
+    let file = File::open(file_path)?;
+    let total = file.metadata()?.len() as usize;
+    let reader = ProgressReader::new(file, total, scanner::PROGRESS_INTERVAL, on_progress);
+    #[cfg(feature = "gzip")]
+    if file_path.ends_with(".gz") {
+        let decoder = BufReader::new(crate::gzip::decoder(reader));
+        return BufReadLexer::new(dfa, decoder).map(|item| item.map(|(token, _)| token)).collect();
+    }
+    BufReadLexer::new(dfa, BufReader::new(reader)).map(|item| item.map(|(token, _)| token)).collect()
+}
+
+/// A reader that counts the bytes read through it and reports them to `on_progress` whenever
+/// `interval` more bytes have been read, and when the inner reader runs out unless that count was
+/// just reported.
+struct ProgressReader<R, F> {
+    inner: R,
+    done: usize,
+    total: usize,
+    interval: usize,
+    /// The byte count of the last report.
+    reported: Option<usize>,
+    on_progress: F,
+}
+
+impl<R: Read, F: FnMut(usize, usize)> ProgressReader<R, F> {
+    fn new(inner: R, total: usize, interval: usize, on_progress: F) -> Self {
+        ProgressReader { inner, done: 0, total, interval, reported: None, on_progress }
+    }
+}
+
+impl<R: Read, F: FnMut(usize, usize)> Read for ProgressReader<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.done += len;
+        let due = match len {
+            0 => self.reported != Some(self.done),
+            _ => self.done - self.reported.unwrap_or(0) >= self.interval,
+        };
+        if due {
+            // A file that grew since its metadata was read must not report more than 100%.
+            (self.on_progress)(self.done, self.total.max(self.done));
+            self.reported = Some(self.done);
+        }
+        Ok(len)
+    }
+}
+
+/// Decompresses a gzip file and tokenizes its text. Positions refer to the decompressed source.
+#[cfg(feature = "gzip")]
+pub fn process_gz_file(dfa: &DFA, file_path: &str) -> Result<Vec<Token>, Box<dyn Error>> {
+    let compressed = fs::read(file_path)?;
+    let content = crate::gzip::decompress(&compressed)?;
+    Ok(process_bytes(dfa, &content, false)?)
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
 
+    fn single_char_dfa() -> DFA {
+        let mut transitions = HashMap::new();
+        transitions.insert((0, 'a'), 1);
+        let mut accept = HashMap::new();
+        accept.insert(1, "accepted".to_string());
+        DFA::new(transitions, 0, accept)
+    }
+
     #[test]
     fn test_process_input_accepted() {
-        // let mut transitions = HashMap::new();
-        // transitions.insert((0, 'a'), 1);
-        // let start = 0;
-        // let mut accept = HashMap::new();
-        // accept.insert(1, "accepted".to_string());
-    
-        // let dfa = DFA {
-        //     transitions,
-        //     start,
-        //     accept,
-        // };
-    
-        // let (result, state, label) = process_input(&dfa, "a".to_string());
-        // assert_eq!(result, true);
-        // assert_eq!(state, 1);
-        // assert_eq!(label, "accepted".to_string());
+        let dfa = single_char_dfa();
+        let tokens = process_input(&dfa, "a\n a");
+        let mut expected = Token::new("accepted".to_string(), "a".to_string());
+        expected.accept_state = Some(1);
+        assert_eq!(tokens, vec![
+            expected.clone().with_position(Span::new(0, 1), 1, 1),
+            expected.with_position(Span::new(3, 4), 2, 2),
+        ]);
+    }
+
+    #[test]
+    fn test_try_process_input_reports_byte_span() {
+        let dfa = single_char_dfa();
+        let err = try_process_input(&dfa, "a\né").unwrap_err();
+        assert_eq!(err, LexError::UnexpectedChar {
+            ch: 'é',
+            line: 2,
+            column: 1,
+            span: Span::new(2, 4),
+        });
+    }
+
+    #[test]
+    fn test_try_new_rejects_accepting_start() {
+        let accept = HashMap::from([(0, "empty".to_string()), (1, "a".to_string())]);
+        let err = DFA::try_new(HashMap::from([((0, 'a'), 1)]), 0, accept).unwrap_err();
+        assert_eq!(err, LexError::AcceptingStart { state: 0 });
+        assert_eq!(err.to_string(), "The start state 0 is accepting and would match an empty lexeme");
+        assert!(DFA::try_new(HashMap::from([((0, 'a'), 1)]), 0, HashMap::from([(1, "a".to_string())])).is_ok());
+
+        let mut dfa = single_char_dfa();
+        assert!(dfa.check_start().is_ok());
+        dfa.add_accept(0, "empty");
+        assert!(dfa.check_start().is_err());
+    }
+
+    #[test]
+    fn test_iter_transitions_is_sorted() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_transition(2, 'a', 0).add_transition(0, 'b', 1).add_transition(0, 'a', 2).add_transition(1, 'z', 1);
+        dfa.add_interval(0, '0', '9', 3).unwrap();
+        let edges: Vec<(usize, char, usize)> = dfa.iter_transitions().collect();
+        assert_eq!(edges, [(0, 'a', 2), (0, 'b', 1), (1, 'z', 1), (2, 'a', 0)]);
+    }
+
+    #[test]
+    fn test_dfa_builder() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_transition(0, '1', 1).add_transition(1, '1', 1).add_accept(1, "integer");
+        let tokens = try_process_input(&dfa, "11 1").unwrap();
+        let lexemes: Vec<&str> = tokens.iter().map(|token| token.lexeme.as_str()).collect();
+        assert_eq!(lexemes, ["11", "1"]);
+        assert!(tokens.iter().all(|token| token.token_type == "integer"));
+    }
+
+    #[test]
+    fn test_process_input_with_newline_tokens() {
+        let dfa = single_char_dfa();
+        let options = ScanOptions { newlines: NewlineMode::Emit, ..ScanOptions::default() };
+        let tokens = process_input_with_options(&dfa, "a\n\na", &options).unwrap();
+        let types: Vec<&str> = tokens.iter().map(|token| token.token_type.as_str()).collect();
+        assert_eq!(types, ["accepted", "NEWLINE", "NEWLINE", "accepted"]);
+        let options = ScanOptions { newlines: NewlineMode::Collapse, ..options };
+        assert_eq!(process_input_with_options(&dfa, "a\n\na", &options).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_process_input_with_config_runs_passes() {
+        let mut dfa = single_char_dfa();
+        dfa.add_transition(0, 'A', 1);
+        assert_eq!(process_input_with_config(&dfa, "a A", &LexerConfig::default()).unwrap(), process_input(&dfa, "a A"));
+
+        let config = LexerConfig {
+            soft_keywords: HashSet::from(["a".to_string()]),
+            normalizers: HashMap::from([("accepted".to_string(), str::to_lowercase as fn(&str) -> String)]),
+            ..LexerConfig::default()
+        };
+        let tokens = process_input_with_config(&dfa, "a A", &config).unwrap();
+        assert_eq!(tokens[0].soft_keyword.as_deref(), Some("a"));
+        assert_eq!(tokens[1].soft_keyword, None);
+        assert_eq!(tokens[1].normalized_lexeme(), "a");
+
+        // Every type keeps its own normalization.
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::from([(1, "id".to_string()), (2, "number".to_string())]));
+        dfa.add_transition(0, 'A', 1).add_transition(0, '7', 2);
+        let config = LexerConfig::builder()
+            .normalize("id", str::to_lowercase)
+            .normalize("number", |number| format!("{}.0", number))
+            .build()
+            .unwrap();
+        let tokens = process_input_with_config(&dfa, "A 7", &config).unwrap();
+        let normalized: Vec<&str> = tokens.iter().map(Token::normalized_lexeme).collect();
+        assert_eq!(normalized, ["a", "7.0"]);
+    }
+
+    #[test]
+    fn test_lexer_config_builder_validates_options() {
+        let config = LexerConfig::builder()
+            .tab_width(4)
+            .keep_whitespace(true)
+            .line_comment("//")
+            .block_comment("/*", "*/")
+            .nested_block_comments(true)
+            .build()
+            .unwrap();
+        let tokens = process_input_with_config(&single_char_dfa(), "\ta /* /* */ a */ // a\na", &config).unwrap();
+        assert_eq!(tokens.iter().map(|token| token.column).collect::<Vec<_>>(), [5, 1]);
+        assert_eq!(passes::reconstruct(&tokens), "\ta /* /* */ a */ // a\na");
+
+        let reason = |builder: &LexerConfigBuilder| match builder.build() {
+            Err(LexError::InvalidConfig { reason }) => reason,
+            other => panic!("expected an invalid configuration, got {:?}", other),
+        };
+        let (_, stats) = process_input_with_stats(&single_char_dfa(), "\ta /* /* */ a */ // a\na", &config).unwrap();
+        assert_eq!((stats.chars_in_tokens, stats.chars_skipped), (2, 21));
+        assert_eq!(stats.max_comment_depth, 2);
+
+        assert!(reason(LexerConfig::builder().nested_block_comments(true)).contains("nested"));
+        assert!(reason(LexerConfig::builder().tab_width(0)).contains("tab width"));
+        assert!(reason(LexerConfig::builder().trivia_policy(TriviaPolicy::SameLine)).contains("keep_whitespace"));
+        assert!(reason(LexerConfig::builder().line_comment("#").block_comment("#", "#")).contains("same delimiter"));
+        let err = LexerConfig::builder().line_comment("").build().unwrap_err();
+        assert_eq!(err.to_string(), "Invalid lexer configuration: comment delimiters cannot be empty");
+    }
+
+    #[test]
+    fn test_tokenize_with_positions_spans_lines() {
+        let mut dfa = single_char_dfa();
+        dfa.add_transition(1, '\n', 2).add_transition(2, 'a', 1).add_accept(2, "open");
+        let positions: Vec<(usize, usize, usize, usize)> = tokenize_with_positions(&dfa, "a\n  a\na")
+            .unwrap()
+            .into_iter()
+            .map(|(_, span)| (span.start_line, span.start_col, span.end_line, span.end_col))
+            .collect();
+        // `a\n` ends at the start of the next line and `a\na` spans two lines.
+        assert_eq!(positions, [(1, 1, 2, 1), (2, 3, 3, 2)]);
+        let (token, span) = &tokenize_with_positions(&dfa, "  a\na").unwrap()[0];
+        assert_eq!(span.byte_range, token.span.start..token.span.end);
+        assert_eq!((token.line, token.column), (span.start_line, span.start_col));
+    }
+
+    #[test]
+    fn test_check_input_reports_every_error_run() {
+        let dfa = single_char_dfa();
+        assert!(check_input(&dfa, "a a\na").is_empty());
+        let errors = check_input(&dfa, "a @$a\n#");
+        let found: Vec<(char, usize, usize)> = errors
+            .iter()
+            .map(|error| match error {
+                LexError::UnexpectedChar { ch, line, column, .. } => (*ch, *line, *column),
+                other => panic!("unexpected error {:?}", other),
+            })
+            .collect();
+        assert_eq!(found, [('@', 1, 3), ('#', 2, 1)]);
+        assert_eq!(errors[0].span(), Span::new(2, 3));
+
+        // The check reports what the scan would stop with, here a character outside the alphabet.
+        let declared = single_char_dfa().with_alphabet(['a', '@']);
+        let input = "a @ é";
+        let errors = check_input(&declared, input);
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], LexError::UnexpectedChar { ch: '@', .. }));
+        let expected = LexError::CharacterNotInAlphabet { ch: 'é', line: 1, column: 5, span: Span::new(4, 6) };
+        assert_eq!(errors[1], expected);
+        assert_eq!(try_process_input(&declared, "a a é").unwrap_err(), expected);
+    }
+
+    #[test]
+    fn test_process_input_limited_resumes_where_it_stopped() {
+        let dfa = single_char_dfa();
+        let input = "a a\n  a a\ta";
+        let all = try_process_input(&dfa, input).unwrap();
+        let (first, resume) = process_input_limited(&dfa, input, Some(2)).unwrap();
+        assert_eq!(first, all[..2]);
+        let resume = resume.unwrap();
+        assert_eq!((resume.line, resume.column, resume.offset), (1, 4, 3));
+
+        let mut tokens = first;
+        let mut from = resume;
+        loop {
+            let (chunk, next) = resume_input(&dfa, input, from, Some(2)).unwrap();
+            assert!(chunk.len() <= 2);
+            tokens.extend(chunk);
+            match next {
+                Some(next) => from = next,
+                None => break,
+            }
+        }
+        assert_eq!(tokens, all);
+        assert_eq!(process_input_limited(&dfa, input, Some(5)).unwrap(), (all.clone(), None));
+        assert_eq!(process_input_limited(&dfa, input, None).unwrap(), (all.clone(), None));
+        assert_eq!(process_input_limited(&dfa, input, Some(0)).unwrap(), (Vec::new(), Some(Position::start())));
+
+        for window in [1, 2, 5] {
+            assert_eq!(resume_in_windows(&dfa, input, Position::start(), None, window).unwrap(), (all.clone(), None));
+            assert_eq!(resume_in_windows(&dfa, input, resume, Some(2), window).unwrap().0, all[2..4]);
+        }
+        // An error past the limit is left for the call that reaches it.
+        let (first, next) = resume_in_windows(&dfa, "a a @", Position::start(), Some(2), 1).unwrap();
+        assert_eq!((first.len(), next.map(|next| next.offset)), (2, Some(3)));
+        assert!(resume_input(&dfa, "a a @", next.unwrap(), Some(2)).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_process_incremental_holds_back_tokens_across_chunks() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_interval(0, 'a', 'z', 1).unwrap().add_interval(1, 'a', 'z', 1).unwrap().add_accept(1, "id");
+        dfa.add_transition(0, '"', 2).add_interval(2, 'a', 'z', 2).unwrap().add_transition(2, ' ', 2);
+        dfa.add_transition(2, '"', 3).add_accept(3, "string");
+        let mut state = IncrementalState::default();
+        let mut tokens = Vec::new();
+        process_incremental(&dfa, "foo \"ab", &mut state, &mut tokens).unwrap();
+        assert_eq!(tokens.iter().map(|t| t.lexeme.as_str()).collect::<Vec<_>>(), ["foo"]);
+        assert_eq!(state.pending, "\"ab");
+        assert_eq!((state.position.column, state.position.offset), (5, 4));
+
+        process_incremental(&dfa, "c d\" bar", &mut state, &mut tokens).unwrap();
+        assert_eq!(state.pending, "bar");
+        finish_incremental(&dfa, &mut state, &mut tokens).unwrap();
+        assert_eq!(state, IncrementalState::default());
+        assert_eq!(tokens, try_process_input(&dfa, "foo \"abc d\" bar").unwrap());
+
+        // The tokens before an error are still handed out.
+        tokens.clear();
+        let err = process_incremental(&dfa, "foo ? b", &mut IncrementalState::default(), &mut tokens).unwrap_err();
+        assert_eq!(err.position(), (1, 5));
+        assert_eq!(tokens.iter().map(|t| t.lexeme.as_str()).collect::<Vec<_>>(), ["foo"]);
+    }
+
+    #[test]
+    fn test_buf_read_lexer_matches_in_memory_positions() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_interval(0, 'a', 'z', 1).unwrap().add_interval(1, 'a', 'z', 1).unwrap().add_accept(1, "id");
+        dfa.add_transition(0, 'é', 1).add_transition(1, 'é', 1).add_transition(1, 'ß', 1);
+        dfa.add_transition(0, '"', 2).add_interval(2, 'a', 'z', 2).unwrap().add_transition(2, ' ', 2);
+        dfa.add_transition(2, '"', 3).add_accept(3, "string");
+        let input = "café straße\n  \"a long string\" \n\téa z";
+        let expected = tokenize_with_positions(&dfa, input).unwrap();
+        for capacity in [1, 2, 3, 7, 64] {
+            let reader = std::io::BufReader::with_capacity(capacity, input.as_bytes());
+            let tokens: Vec<(Token, SourceSpan)> = BufReadLexer::new(&dfa, reader).map(Result::unwrap).collect();
+            assert_eq!(tokens, expected, "buffer of {} bytes", capacity);
+        }
+
+        let bytes = b"ab \xff cd";
+        let mut lexer = BufReadLexer::new(&dfa, std::io::BufReader::with_capacity(2, &bytes[..]));
+        assert_eq!(lexer.next().unwrap().unwrap().0.lexeme, "ab");
+        let err = lexer.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), decode_utf8(bytes, false).unwrap_err().to_string());
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn test_buf_read_lexer_yields_tokens_before_errors() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_interval(0, 'a', 'z', 1).unwrap().add_interval(1, 'a', 'z', 1).unwrap().add_accept(1, "id");
+        let lexemes = |bytes: &[u8], capacity: usize| -> Vec<Result<String, String>> {
+            BufReadLexer::new(&dfa, std::io::BufReader::with_capacity(capacity, bytes))
+                .map(|item| item.map(|(token, _)| token.lexeme).map_err(|err| err.to_string()))
+                .collect()
+        };
+
+        let input = "ab cd ? ef";
+        let chars: Vec<char> = input.chars().collect();
+        let expected: Vec<Result<String, String>> = scanner::Lexer::new(&dfa, chars, ScanOptions::default())
+            .map(|item| item.map(|token| token.lexeme).map_err(|err| err.to_string()))
+            .collect();
+        assert_eq!(expected.len(), 3);
+        for capacity in [1, 4, 64] {
+            assert_eq!(lexemes(input.as_bytes(), capacity), expected, "buffer of {} bytes", capacity);
+        }
+
+        // A bad byte in the middle of the stream ends it after the tokens before it.
+        let bytes = b"ab cd\xff ef";
+        let utf8_error = decode_utf8(bytes, false).unwrap_err().to_string();
+        for capacity in [1, 4, 64] {
+            let expected = vec![Ok("ab".to_string()), Ok("cd".to_string()), Err(utf8_error.clone())];
+            assert_eq!(lexemes(bytes, capacity), expected, "buffer of {} bytes", capacity);
+        }
+    }
+
+    #[test]
+    fn test_progress_reader_reports_at_intervals_and_at_the_end() {
+        let mut reports = Vec::new();
+        let data = [0u8; 11];
+        let mut reader = ProgressReader::new(&data[..], 11, 4, |done, total| reports.push((done, total)));
+        let mut buf = [0u8; 3];
+        while reader.read(&mut buf).unwrap() > 0 {}
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+        assert_eq!(reports, vec![(6, 11), (11, 11)]);
+
+        reports = Vec::new();
+        let mut reader = ProgressReader::new(&data[..8], 8, 4, |done, total| reports.push((done, total)));
+        while reader.read(&mut buf[..2]).unwrap() > 0 {}
+        assert_eq!(reports, vec![(4, 8), (8, 8)]);
+    }
+
+    #[test]
+    fn test_process_file_with_progress_streams_the_file() {
+        let dfa = DFA::new(
+            read_transitions_from_csv("automato/DFA-transitions.csv").unwrap(),
+            0,
+            read_accept_states_from_json("automato/DFA-final-states.json").unwrap(),
+        );
+        let path = "tests/lexer_input_test.zig";
+        let size = fs::metadata(path).unwrap().len() as usize;
+        let mut reports = Vec::new();
+        let tokens = process_file_with_progress(&dfa, path, |done, total| reports.push((done, total))).unwrap();
+        assert_eq!(tokens, process_file_input(&dfa, path).unwrap());
+        assert_eq!(reports.last(), Some(&(size, size)));
+        assert!(reports.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+
+        #[cfg(feature = "gzip")]
+        {
+            let compressed = "tests/lexer_input_test.zig.gz";
+            let size = fs::metadata(compressed).unwrap().len() as usize;
+            reports.clear();
+            let from_gz = process_file_with_progress(&dfa, compressed, |done, total| reports.push((done, total))).unwrap();
+            assert_eq!(from_gz, tokens);
+            assert_eq!(reports.last(), Some(&(size, size)));
+        }
+    }
+
+    #[test]
+    fn test_verify_tokens_rejects_tampered_types() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_interval(0, 'a', 'z', 1).unwrap().add_interval(1, 'a', 'z', 1).unwrap().add_accept(1, "identifier");
+        dfa.add_interval(0, '0', '9', 2).unwrap().add_interval(2, '0', '9', 2).unwrap().add_accept(2, "integer");
+        let mut tokens = try_process_input(&dfa, "x 42\nyz").unwrap();
+        assert_eq!(verify_tokens(&dfa, &tokens), Ok(()));
+
+        tokens[1].token_type = "identifier".to_string();
+        let err = verify_tokens(&dfa, &tokens).unwrap_err();
+        assert_eq!(err, LexError::TokenMismatch {
+            token_type: "identifier".to_string(),
+            found: Some("integer".to_string()),
+            line: 1,
+            column: 3,
+            span: Span::new(2, 4),
+        });
+        assert_eq!(err.message(), "The lexeme of the 'identifier' token lexes as 'integer'");
+
+        tokens[1].token_type = "integer".to_string();
+        tokens[2].lexeme = "y z".to_string();
+        assert_eq!(verify_tokens(&dfa, &tokens).unwrap_err().message(), "The lexeme of the 'identifier' token is not a single token");
+    }
+
+    #[test]
+    fn test_matches_whole_requires_one_complete_token() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_interval(0, 'a', 'z', 1).unwrap().add_interval(1, 'a', 'z', 1).unwrap().add_accept(1, "identifier");
+        dfa.add_interval(1, '0', '9', 2).unwrap();
+        assert_eq!(dfa.matches_whole("value").map(String::as_str), Some("identifier"));
+        assert_eq!(dfa.matches_whole("value "), None);
+        assert_eq!(dfa.matches_whole("value1"), None);
+        assert_eq!(dfa.matches_whole(""), None);
+        assert!(!dfa.accepts("a b"));
+    }
+
+    #[test]
+    fn test_process_chars_matches_process_input() {
+        let dfa = single_char_dfa();
+        let input: Vec<char> = "a a\na".chars().collect();
+        assert_eq!(process_chars(&dfa, &input).unwrap(), try_process_input(&dfa, "a a\na").unwrap());
+        assert_eq!(process_chars(&dfa, &input[2..]).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_alphabet_is_sorted_and_covers_all_inputs() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_transition(0, 'z', 1).add_transition(1, '_', 1).add_interval(0, '0', '2', 1).unwrap();
+        dfa.add_negated(1, &['"'], 2).unwrap();
+        let alphabet: String = dfa.alphabet().into_iter().collect();
+        assert_eq!(alphabet, "\"012_z");
+        assert_eq!(dfa.states().into_iter().collect::<Vec<_>>(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_possible_token_types_are_sorted_and_distinct() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_accept(3, "string").add_accept(1, "identifier").add_accept(2, "identifier");
+        dfa.add_accept_labels(4, &["keyword", "identifier"]);
+        assert_eq!(dfa.possible_token_types().into_iter().collect::<Vec<_>>(), ["identifier", "keyword", "string"]);
+        assert!(DFA::new(HashMap::new(), 0, HashMap::new()).possible_token_types().is_empty());
+    }
+
+    #[test]
+    fn test_interval_transitions() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_interval(0, 'a', 'z', 1).unwrap();
+        dfa.add_interval(1, 'a', 'z', 1).unwrap();
+        dfa.add_interval(1, '0', '9', 1).unwrap();
+        dfa.add_transition(0, '_', 1).add_accept(1, "id");
+
+        let tokens = try_process_input(&dfa, "abc _x9 q").unwrap();
+        let lexemes: Vec<&str> = tokens.iter().map(|token| token.lexeme.as_str()).collect();
+        assert_eq!(lexemes, ["abc", "_x9", "q"]);
+        assert!(try_process_input(&dfa, "A").is_err());
+        assert!(dfa.add_interval(1, '5', 'b', 2).is_err());
+        assert!(dfa.add_interval(1, 'z', 'a', 2).is_err());
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_read_transition_table_keeps_ranges() {
+        let path = "tests/interval_transitions.csv";
+        let table = read_transition_table_from_csv(path).unwrap();
+        assert_eq!(table.intervals[&0], vec![('a', 'z', 1)]);
+        assert_eq!(table.intervals[&1], vec![('0', '9', 1), ('a', 'z', 1)]);
+        assert_eq!(table.transitions[&(1, '-')], 2);
+        assert!(read_transitions_from_csv(path).is_err());
+    }
+
+    #[test]
+    fn test_declared_alphabet_separates_foreign_characters() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::from([(1, "number".to_string()), (2, "plus".to_string())]));
+        dfa.add_interval(0, '0', '9', 1).unwrap().add_interval(1, '0', '9', 1).unwrap().add_transition(0, '+', 2);
+        let declared = dfa.clone().with_alphabet("0123456789+*".chars());
+        let error = try_process_input(&declared, "1+*").unwrap_err();
+        assert_eq!(error, LexError::UnexpectedChar { ch: '*', line: 1, column: 3, span: Span::new(2, 3) });
+        let error = try_process_input(&declared, "1+é").unwrap_err();
+        assert_eq!(error, LexError::CharacterNotInAlphabet { ch: 'é', line: 1, column: 3, span: Span::new(2, 4) });
+
+        let inferred = dfa.clone().with_inferred_alphabet();
+        assert!(matches!(try_process_input(&inferred, "12*"), Err(LexError::CharacterNotInAlphabet { ch: '*', .. })));
+        assert!(matches!(try_process_input(&dfa, "12*"), Err(LexError::UnexpectedChar { ch: '*', .. })));
+        assert_eq!(try_process_input(&inferred, "12 + 3").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_source_map_traces_filtered_output_to_input() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_interval(0, 'a', 'z', 1).unwrap().add_interval(1, 'a', 'z', 1).unwrap().add_accept(1, "word");
+        dfa.add_transition(0, '=', 2).add_transition(0, '#', 3).add_accept(2, "equals").add_accept(3, "comment");
+        let input = "let x\n  = # y\n\tz";
+        let (tokens, map) = tokenize_with_source_map(&dfa, input).unwrap();
+        let output = passes::reconstruct(&tokens);
+        assert_eq!(output, "letx=#yz");
+        let lines = LineIndex::new(input);
+        let original = |map: &SourceMap, offset: usize| lines.line_col(map.original_offset(offset).unwrap());
+        assert_eq!(original(&map, output.find('=').unwrap()), (2, 3));
+        assert_eq!(original(&map, output.find('z').unwrap()), (3, 2));
+
+        let kept: Vec<Token> = tokens.into_iter().filter(|token| token.token_type != "comment").collect();
+        let map = SourceMap::from_tokens(&kept);
+        let output = passes::reconstruct(&kept);
+        assert_eq!(output, "letx=yz");
+        assert_eq!(original(&map, output.find('y').unwrap()), (2, 7));
+        assert_eq!(original(&map, output.len()), (3, 3));
+    }
+
+    #[test]
+    fn test_accept_actions_transform_lexemes() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::from([(1, "string".to_string()), (3, "id".to_string())]));
+        dfa.add_transition(0, '"', 2).add_interval(2, 'a', 'z', 2).unwrap().add_transition(2, '"', 1);
+        dfa.add_interval(0, 'A', 'Z', 3).unwrap().add_interval(3, 'A', 'Z', 3).unwrap();
+        let dfa = dfa.with_actions(HashMap::from([(1, "strip_quotes".to_string()), (3, "shout".to_string())]));
+
+        let error = process_input_with_config(&dfa, "\"hi\"", &LexerConfig::default()).unwrap_err();
+        assert!(matches!(error, LexError::InvalidConfig { reason } if reason.contains("'shout'")));
+
+        let config = LexerConfig::builder().action("shout", |lexeme| format!("{}!", lexeme)).build().unwrap();
+        let tokens = process_input_with_config(&dfa, "\"hi\" HEY", &config).unwrap();
+        let found: Vec<(&str, &str)> = tokens.iter().map(|t| (t.token_type.as_str(), t.lexeme.as_str())).collect();
+        assert_eq!(found, [("string", "hi"), ("id", "HEY!")]);
+        assert_eq!(tokens[0].span, Span::new(0, 4));
+
+        let (tokens, stats) = process_input_with_stats(&dfa, "\"hi\" HEY", &config).unwrap();
+        assert_eq!(stats.chars_total(), 8);
+        assert_eq!(tokens.len(), 2);
+        let error = process_input_with_config(&dfa, "HEY \"\"", &config).unwrap_err();
+        assert_eq!(error.message(), "The 'string' token accepted in state 1 has an empty lexeme");
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_accept_actions_from_csv() {
+        let actions = read_accept_actions_from_csv("tests/accept_actions.csv").unwrap();
+        assert_eq!(actions, HashMap::from([(1, "strip_quotes".to_string()), (2, "lowercase".to_string())]));
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_trivia_labels_from_csv() {
+        let path = "tests/trivia_final_states.csv";
+        let labels = read_trivia_labels_from_csv(path).unwrap();
+        assert_eq!(labels, ["comment", "whitespace"]);
+
+        let mut dfa = DFA::new(HashMap::new(), 0, read_accept_states_from_csv(path).unwrap()).with_trivia(&labels);
+        dfa.add_transition(0, '#', 1).add_negated(1, &['\n'], 1).unwrap();
+        dfa.add_interval(0, 'a', 'z', 3).unwrap().add_interval(3, 'a', 'z', 3).unwrap();
+        let tokens = try_process_input(&dfa, "x #note\ny").unwrap();
+        let trivia: Vec<(&str, bool)> = tokens.iter().map(|t| (t.token_type.as_str(), t.is_trivia())).collect();
+        assert_eq!(trivia, [("id", false), ("comment", true), ("id", false)]);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_categories_from_accept_json() {
+        let path = "tests/categorized_final_states.json";
+        let accept = read_accept_states_from_json(path).unwrap();
+        assert_eq!(accept.len(), 3);
+        let categories = read_accept_categories_from_json(path).unwrap();
+        assert_eq!(categories, HashMap::from([(1, "literal".to_string()), (2, "operator".to_string())]));
+
+        let mut dfa = DFA::new(HashMap::new(), 0, accept).with_categories(categories);
+        dfa.add_transition(0, '1', 1).add_transition(0, '+', 2).add_transition(0, 'x', 3);
+        let tokens = try_process_input(&dfa, "1 + x").unwrap();
+        let categories: Vec<Option<&str>> = tokens.iter().map(|token| token.category.as_deref()).collect();
+        assert_eq!(categories, [Some("literal"), Some("operator"), None]);
+    }
+
+    #[test]
+    fn test_overlapping_rules_break_ties_by_length_then_priority_then_declaration() {
+        // `if` is a keyword and an identifier; longer words are only identifiers.
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_transition(0, 'i', 1).add_transition(1, 'f', 2).add_interval(2, 'a', 'z', 3).unwrap();
+        dfa.add_accept(1, "identifier").add_accept_labels(2, &["identifier", "keyword"]).add_accept(3, "identifier");
+        let rank = |priority, declared| RuleRank { priority, declared };
+        let types = |dfa: &DFA, input: &str| -> Vec<String> {
+            try_process_input(dfa, input).unwrap().into_iter().map(|token| token.token_type).collect()
+        };
+
+        // Length: the keyword's priority does not beat a longer identifier.
+        let ranks = HashMap::from([("identifier".to_string(), rank(0, 0)), ("keyword".to_string(), rank(5, 1))]);
+        let ranked = dfa.clone().with_rule_ranks(ranks);
+        assert_eq!(types(&ranked, "ifs if"), ["identifier", "keyword"]);
+
+        // Priority: the higher one wins even though the identifier rule was declared first.
+        assert_eq!(ranked.accept_labels[&2], ["keyword", "identifier"]);
+        let ranks = HashMap::from([("identifier".to_string(), rank(1, 1)), ("keyword".to_string(), rank(0, 0))]);
+        assert_eq!(types(&dfa.clone().with_rule_ranks(ranks), "if"), ["identifier"]);
+
+        // Declaration: with equal priorities, the rule declared first wins over the list order.
+        let ranks = HashMap::from([("identifier".to_string(), rank(2, 1)), ("keyword".to_string(), rank(2, 0))]);
+        assert_eq!(types(&dfa.clone().with_rule_ranks(ranks), "if"), ["keyword"]);
+        assert_eq!(types(&dfa, "if"), ["identifier"]);
+
+        // Unranked labels come after ranked ones, even those of negative priority.
+        let ranks = HashMap::from([("keyword".to_string(), rank(-3, 0))]);
+        assert_eq!(types(&dfa.clone().with_rule_ranks(ranks), "if"), ["keyword"]);
+    }
+
+    #[cfg(all(feature = "csv", feature = "json"))]
+    #[test]
+    fn test_rule_ranks_from_accept_files() {
+        let ranks = read_rule_ranks_from_csv("tests/categorized_final_states.csv").unwrap();
+        let rank = |priority, declared| RuleRank { priority, declared };
+        assert_eq!(ranks["integer"], rank(1, 0));
+        assert_eq!(ranks["sum"], rank(2, 1));
+        assert_eq!(ranks["id"], rank(0, 2));
+
+        let ranks = read_rule_ranks_from_json("tests/multi_label_final_states.json").unwrap();
+        let mut labels: Vec<&String> = ranks.keys().collect();
+        labels.sort_by_key(|label| ranks[*label].declared);
+        assert_eq!(labels, ["integer", "keyword", "identifier"]);
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_accept_label_lists_from_json() {
+        let path = "tests/multi_label_final_states.json";
+        let accept = read_accept_states_from_json(path).unwrap();
+        assert_eq!(accept[&2], "keyword");
+        assert_eq!(accept[&3], "identifier");
+        let lists = read_accept_label_lists_from_json(path).unwrap();
+        assert_eq!(lists, HashMap::from([(2, vec!["keyword".to_string(), "identifier".to_string()])]));
+
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new()).with_accept_labels(lists);
+        dfa.add_transition(0, 'a', 2);
+        let mut config = LexerConfig::builder().label_policy(LabelPolicy::All).build().unwrap();
+        assert_eq!(process_input_with_config(&dfa, "a", &config).unwrap()[0].token_type, "keyword|identifier");
+        config.scan.label_policy = LabelPolicy::First;
+        assert_eq!(process_input_with_config(&dfa, "a", &config).unwrap()[0].token_type, "keyword");
+    }
+
+    #[cfg(all(feature = "csv", feature = "json"))]
+    #[test]
+    fn test_accept_states_from_csv_match_json() {
+        let csv = "tests/categorized_final_states.csv";
+        let json = "tests/categorized_final_states.json";
+        assert_eq!(read_accept_states_from_csv(csv).unwrap(), read_accept_states_from_json(json).unwrap());
+        assert_eq!(read_accept_categories_from_csv(csv).unwrap(), read_accept_categories_from_json(json).unwrap());
+    }
+
+    #[cfg(feature = "json5")]
+    #[test]
+    fn test_accept_states_from_json5() {
+        let path = "tests/categorized_final_states.json5";
+        let json = "tests/categorized_final_states.json";
+        assert_eq!(read_accept_states_from_json(path).unwrap(), read_accept_states_from_json(json).unwrap());
+        assert_eq!(read_accept_categories_from_json(path).unwrap(), read_accept_categories_from_json(json).unwrap());
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_csv_include_merges_files() {
+        let table = read_transition_table_from_csv("tests/include/identifiers.csv").unwrap();
+        let dfa = DFA::from_table(table, 0, HashMap::from([(1, "id".to_string())]));
+        let lexemes: Vec<String> = try_process_input(&dfa, "_a9 b_c").unwrap().into_iter().map(|token| token.lexeme).collect();
+        assert_eq!(lexemes, ["_a9", "b_c"]);
+        assert_eq!(read_transitions_from_csv("tests/include/cycle_b.csv").unwrap_err().to_string().matches("->").count(), 2);
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_csv_include_reports_cycles_and_conflicts() {
+        let cycle = read_transition_table_from_csv("tests/include/cycle_a.csv").unwrap_err().to_string();
+        assert!(cycle.starts_with("Cyclic include: "), "{}", cycle);
+        assert!(cycle.ends_with("cycle_a.csv"), "{}", cycle);
+        let conflict = read_transition_table_from_csv("tests/include/conflict.csv").unwrap_err().to_string();
+        assert_eq!(conflict, "Conflicting transitions from state 0 on '_': to 2 and to 1");
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_negated_class_string_literal() {
+        let table = read_transition_table_from_csv("tests/string_literal_transitions.csv").unwrap();
+        assert_eq!(table.negated[&1], (vec!['\n', '"'], 1));
+        let mut accept = HashMap::new();
+        accept.insert(2, "string".to_string());
+        let dfa = DFA::from_table(table, 0, accept);
+
+        let tokens = try_process_input(&dfa, "\"hi, there!\" \"\"").unwrap();
+        let lexemes: Vec<&str> = tokens.iter().map(|token| token.lexeme.as_str()).collect();
+        assert_eq!(lexemes, ["\"hi, there!\"", "\"\""]);
+        assert!(try_process_input(&dfa, "\"broken\nstring\"").is_err());
+    }
+
+    #[test]
+    fn test_exact_transitions_win_over_negated_class() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_negated(0, &[' '], 1).unwrap();
+        dfa.add_transition(0, 'x', 2).add_accept(1, "other").add_accept(2, "x");
+        let tokens = try_process_input(&dfa, "x y").unwrap();
+        assert_eq!(tokens[0].token_type, "x");
+        assert_eq!(tokens[1].token_type, "other");
+        assert!(dfa.add_negated(0, &['a'], 3).is_err());
+    }
+
+    /// Tests that run the ZigZin automaton shipped in `automato/`.
+    #[cfg(all(feature = "csv", feature = "json"))]
+    mod zigzin {
+        use super::*;
+
+        fn zigzin_dfa() -> DFA {
+            DFA::new(
+                read_transitions_from_csv("automato/DFA-transitions.csv").unwrap(),
+                0,
+                read_accept_states_from_json("automato/DFA-final-states.json").unwrap(),
+            )
+        }
+
+        #[test]
+        fn test_trivia_round_trips_sample_files() {
+            let dfa = zigzin_dfa();
+            for path in ["tests/lexer_input_test.zig", "tests/lexer_first_test.txt"] {
+                let source = fs::read_to_string(path).unwrap();
+                let tokens = process_input_with_trivia(&dfa, &source).unwrap();
+                assert_eq!(crate::passes::reconstruct(&tokens), source, "round trip of {}", path);
+            }
+        }
+
+        #[test]
+        fn test_trivia_round_trips_edge_whitespace() {
+            let dfa = zigzin_dfa();
+            for source in ["", "x", "  x", "x  ", "\n\tx\r\n y \n\n"] {
+                let tokens = process_input_with_trivia(&dfa, source).unwrap();
+                assert_eq!(crate::passes::reconstruct(&tokens), source);
+            }
+        }
+
+        #[test]
+        fn test_tokens_record_accept_state() {
+            let dfa = zigzin_dfa();
+            let tokens = try_process_input(&dfa, "if iffy").unwrap();
+            assert_eq!(tokens[0].accept_state, Some(40));
+            let state = tokens[1].accept_state.unwrap();
+            assert_eq!(dfa.accept[&state], "id");
+        }
+
+        #[cfg(feature = "gzip")]
+        #[test]
+        fn test_process_file_input_decompresses_gz() {
+            let dfa = zigzin_dfa();
+            let plain = process_file_input(&dfa, "tests/lexer_input_test.zig").unwrap();
+            let compressed = process_file_input(&dfa, "tests/lexer_input_test.zig.gz").unwrap();
+            assert_eq!(compressed, plain);
+        }
+
+        #[test]
+        fn test_invalid_utf8_reports_byte_offset() {
+            let dfa = zigzin_dfa();
+            let bytes = fs::read("tests/lexer_invalid_utf8_test.zig").unwrap();
+            let err = process_bytes(&dfa, &bytes, false).unwrap_err();
+            assert_eq!(err, LexError::InvalidUtf8 { byte_offset: 33, line: 2, column: 13, len: 2 });
+        }
+    }
+
+    #[cfg(feature = "utf16")]
+    #[test]
+    fn test_decode_utf16_respects_bom() {
+        let text = "a\n€𝄞b";
+        let little: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let mut big = vec![0xFE, 0xFF];
+        big.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        let mut little_bom = vec![0xFF, 0xFE];
+        little_bom.extend(&little);
+        for bytes in [&little, &big, &little_bom] {
+            assert_eq!(decode_utf16(bytes).unwrap(), text);
+        }
+    }
+
+    #[cfg(feature = "utf16")]
+    #[test]
+    fn test_decode_utf16_reports_unpaired_surrogate() {
+        // "ab\n" then a lone high surrogate, little endian with a BOM.
+        let bytes = [0xFF, 0xFE, b'a', 0, b'b', 0, b'\n', 0, 0x00, 0xD8, b'c', 0];
+        assert_eq!(
+            decode_utf16(&bytes),
+            Err(LexError::InvalidUtf16 { byte_offset: 8, line: 2, column: 1, len: 2 })
+        );
+        assert_eq!(
+            decode_utf16(&[b'a', 0, b'b']),
+            Err(LexError::InvalidUtf16 { byte_offset: 2, line: 1, column: 2, len: 1 })
+        );
+    }
+
+    #[test]
+    fn test_custom_skip_callback() {
+        // Skips whitespace and comments from a configured marker to the end of the line.
+        let marker = ';';
+        let skip_comments = move |chars: &[char], index: usize| match chars[index] {
+            ch if ch == marker => Some(chars[index..].iter().position(|&ch| ch == '\n').unwrap_or(chars.len() - index)),
+            ch => ch.is_whitespace().then_some(1),
+        };
+        let input = "a ; a comment\n  a;\na ;";
+        let config = LexerConfig::builder().skip(skip_comments).keep_whitespace(true).build().unwrap();
+        let (tokens, stats) = process_input_with_stats(&single_char_dfa(), input, &config).unwrap();
+        assert_eq!(tokens.iter().map(|token| token.column).collect::<Vec<_>>(), [1, 3, 1]);
+        assert_eq!(tokens[1].leading_trivia, " ; a comment\n  ");
+        assert_eq!(passes::reconstruct(&tokens), input);
+        assert_eq!(stats.chars_total(), input.chars().count());
+
+        // The default skip only passes over whitespace.
+        assert!(matches!(try_process_input(&single_char_dfa(), input), Err(LexError::UnexpectedChar { ch: ';', .. })));
+    }
+
+    #[test]
+    fn test_replacement_char_policies() {
+        let input = "a \u{FFFD} a";
+        let scan = |dfa: &DFA, input: &str, policy: ReplacementPolicy| {
+            let config = LexerConfig::builder().on_replacement_char(policy).build().unwrap();
+            process_input_with_config(dfa, input, &config)
+        };
+        let mut dfa = single_char_dfa();
+        assert_eq!(
+            scan(&dfa, input, ReplacementPolicy::Error),
+            Err(LexError::ReplacementChar { line: 1, column: 3, span: Span::new(2, 5) })
+        );
+        assert_eq!(scan(&dfa, input, ReplacementPolicy::Whitespace).unwrap().len(), 2);
+        assert!(matches!(scan(&dfa, input, ReplacementPolicy::Char), Err(LexError::UnexpectedChar { .. })));
+
+        dfa.add_transition(0, '\u{FFFD}', 1).add_transition(1, '\u{FFFD}', 1);
+        let lexemes: Vec<String> = scan(&dfa, input, ReplacementPolicy::Char).unwrap().into_iter().map(|t| t.lexeme).collect();
+        assert_eq!(lexemes, ["a", "\u{FFFD}", "a"]);
+        // Inside a lexeme, only the default policy notices the character.
+        assert_eq!(scan(&dfa, "a\u{FFFD}", ReplacementPolicy::Error).unwrap_err().position(), (1, 2));
+        assert_eq!(scan(&dfa, "a\u{FFFD}", ReplacementPolicy::Whitespace).unwrap()[0].lexeme, "a\u{FFFD}");
+    }
+
+    #[test]
+    fn test_control_char_policies() {
+        let path = "tests/embedded_nul.txt";
+        let err = process_file_input(&single_char_dfa(), path).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LexError>(),
+            Some(&LexError::ControlCharacter { code: 0, line: 2, column: 2, span: Span::new(5, 6) })
+        );
+        assert_eq!(err.to_string(), "ZigZin compiler: Lexer error at line 2, column 2: Control character U+0000 in the input, which may not be a text file");
+
+        let input = fs::read_to_string(path).unwrap();
+        let scan = |dfa: &DFA, input: &str, policy: ControlCharPolicy| {
+            let config = LexerConfig::builder().on_control_char(policy).build().unwrap();
+            process_input_with_config(dfa, input, &config)
+        };
+        let mut dfa = single_char_dfa();
+        assert_eq!(scan(&dfa, &input, ControlCharPolicy::Whitespace).unwrap().len(), 5);
+        assert!(matches!(scan(&dfa, &input, ControlCharPolicy::Char), Err(LexError::UnexpectedChar { ch: '\0', .. })));
+        // Tabs and carriage returns are whitespace, not stray control characters.
+        assert_eq!(scan(&dfa, "a\t\r\na", ControlCharPolicy::Error).unwrap().len(), 2);
+
+        dfa.add_transition(1, '\u{7}', 1);
+        assert_eq!(scan(&dfa, "a\u{7}", ControlCharPolicy::Error).unwrap_err().position(), (1, 2));
+        assert_eq!(scan(&dfa, "a\u{7}", ControlCharPolicy::Char).unwrap()[0].lexeme, "a\u{7}");
+    }
+
+    #[test]
+    fn test_invalid_utf8_lenient_mode_replaces_sequence() {
+        let bytes = fs::read("tests/lexer_invalid_utf8_test.zig").unwrap();
+        let text = decode_utf8(&bytes, true).unwrap();
+        assert!(text.contains("var x = \u{FFFD};"));
+    }
+}
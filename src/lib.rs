@@ -0,0 +1,41 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod compiled;
+pub mod cursor;
+pub mod error;
+pub mod lint;
+pub mod scanner;
+pub mod tokens;
+
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod export;
+#[cfg(feature = "std")]
+pub mod lexer;
+#[cfg(feature = "std")]
+pub mod locale;
+#[cfg(feature = "std")]
+pub mod nfa;
+#[cfg(feature = "std")]
+pub mod ops;
+#[cfg(feature = "std")]
+pub mod passes;
+#[cfg(feature = "std")]
+pub mod rules;
+#[cfg(feature = "std")]
+pub mod validate;
+
+#[cfg(feature = "diagnostic")]
+pub mod diagnostic;
+
+#[cfg(feature = "gzip")]
+pub mod gzip;
+
+#[cfg(feature = "json5")]
+pub mod json5;
+
+#[cfg(feature = "json")]
+pub mod persist;
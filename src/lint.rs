@@ -0,0 +1,109 @@
+//! Checks over the raw source text that run independently of scanning.
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::cursor::Position;
+
+/// A problem found in the leading whitespace of a line by `check_indentation`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndentWarning {
+    /// The indentation of `line` (1-based) mixes tabs and spaces; `column` (1-based) is where the
+    /// first character of the other kind appears.
+    MixedIndentation { line: usize, column: usize },
+}
+
+impl IndentWarning {
+    pub fn describe(&self) -> String {
+        match self {
+            IndentWarning::MixedIndentation { line, column } => {
+                format!("line {} mixes tabs and spaces in its indentation (column {})", line, column)
+            }
+        }
+    }
+}
+
+/// Reports every line whose run of leading tabs and spaces contains both.
+pub fn check_indentation(input: &str) -> Vec<IndentWarning> {
+    let mut warnings = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        let indentation = line.bytes().take_while(|&byte| byte == b' ' || byte == b'\t');
+        let mut first = None;
+        for (offset, byte) in indentation.enumerate() {
+            match first {
+                None => first = Some(byte),
+                Some(kind) if kind != byte => {
+                    warnings.push(IndentWarning::MixedIndentation { line: index + 1, column: offset + 1 });
+                    break;
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    warnings
+}
+
+/// A line reported by `check_line_length`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineWarning {
+    /// `line` (1-based) is `length` columns long, more than the `max` allowed.
+    TooLong { line: usize, length: usize, max: usize },
+}
+
+impl LineWarning {
+    pub fn describe(&self) -> String {
+        match self {
+            LineWarning::TooLong { line, length, max } => {
+                format!("line {} is {} columns long, more than {}", line, length, max)
+            }
+        }
+    }
+}
+
+/// Reports every line longer than `max` columns, not counting its line break. Columns are counted
+/// like `ScanOptions::tab_width` counts them, so the lengths agree with the columns tokens report:
+/// a tab reaches the next multiple of `tab_width`, and a width of 1 counts characters.
+pub fn check_line_length(input: &str, max: usize, tab_width: usize) -> Vec<LineWarning> {
+    let mut warnings = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        let mut end = Position::start();
+        line.chars().for_each(|ch| end.advance_with_tab_width(ch, tab_width));
+        let length = end.column - 1;
+        if length > max {
+            warnings.push(LineWarning::TooLong { line: index + 1, length, max });
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_indentation_reports_mixed_runs() {
+        let input = "fn main() {\n    a;\n\t\tb;\n  \tc; \t\n\t d;\n x\t;\n}\n";
+        assert_eq!(
+            check_indentation(input),
+            [
+                IndentWarning::MixedIndentation { line: 4, column: 3 },
+                IndentWarning::MixedIndentation { line: 5, column: 2 },
+            ]
+        );
+        assert_eq!(
+            check_indentation(input)[0].describe(),
+            "line 4 mixes tabs and spaces in its indentation (column 3)"
+        );
+    }
+
+    #[test]
+    fn test_check_line_length_reports_lines_above_max() {
+        let input = "12345678\n123456789\n1234567\n\t12345\r\n";
+        assert_eq!(check_line_length(input, 8, 1), [LineWarning::TooLong { line: 2, length: 9, max: 8 }]);
+        assert_eq!(
+            check_line_length(input, 8, 4),
+            [LineWarning::TooLong { line: 2, length: 9, max: 8 }, LineWarning::TooLong { line: 4, length: 9, max: 8 }]
+        );
+        assert!(check_line_length(input, 9, 4).is_empty());
+        assert_eq!(check_line_length(input, 8, 1)[0].describe(), "line 2 is 9 columns long, more than 8");
+    }
+}
@@ -0,0 +1,98 @@
+//! Message catalogs for translating lexer errors.
+//!
+//! Each locale maps message keys (see `LexError::message_key`, plus `lexer_error` for the text
+//! around the message) to format strings using the same `{name}` placeholders as
+//! `LexError::render`. Only the prose is translated; positions and characters are inserted as is.
+//! English is built in and used for any key a locale does not define.
+use std::collections::HashMap;
+use std::sync::{OnceLock, RwLock};
+use crate::error::english;
+
+struct Catalog {
+    current: String,
+    locales: HashMap<String, HashMap<String, String>>,
+}
+
+fn catalog() -> &'static RwLock<Catalog> {
+    static CATALOG: OnceLock<RwLock<Catalog>> = OnceLock::new();
+    CATALOG.get_or_init(|| {
+        let mut locales = HashMap::new();
+        locales.insert("pt-BR".to_string(), portuguese());
+        RwLock::new(Catalog { current: "en".to_string(), locales })
+    })
+}
+
+/// The built-in Brazilian Portuguese catalog.
+fn portuguese() -> HashMap<String, String> {
+    [
+        ("lexer_error", "Compilador ZigZin: Erro léxico na linha {line}, coluna {col}: {message}"),
+        ("unexpected_char", "Token inesperado '{char}'"),
+        ("character_not_in_alphabet", "Caractere '{char}' fora do alfabeto do autômato"),
+        ("invalid_utf8", "Sequência UTF-8 inválida no byte {byte}"),
+        ("invalid_utf16", "Sequência UTF-16 inválida no byte {byte}"),
+        ("zero_length_token", "O estado de aceitação {state} reconheceu um lexema vazio"),
+        ("empty_lexeme", "O token '{type}' aceito no estado {state} tem um lexema vazio"),
+        ("replacement_char", "Caractere de substituição U+FFFD na entrada, que provavelmente não era UTF-8 válido"),
+        ("control_character", "Caractere de controle {code} na entrada, que talvez não seja um arquivo de texto"),
+        ("token_mismatch", "O lexema do token '{type}' é reconhecido como '{found}'"),
+        ("token_unmatched", "O lexema do token '{type}' não é um único token"),
+        ("accepting_start", "O estado inicial {state} é de aceitação e reconheceria um lexema vazio"),
+        ("invalid_config", "Configuração do lexer inválida: {reason}"),
+    ]
+    .into_iter()
+    .map(|(key, text)| (key.to_string(), text.to_string()))
+    .collect()
+}
+
+/// Registers (or replaces) the messages of a locale. Keys it leaves out fall back to English.
+pub fn register_locale(locale: &str, messages: HashMap<String, String>) {
+    catalog().write().unwrap().locales.insert(locale.to_string(), messages);
+}
+
+/// Selects the locale used by `LexError`'s messages. Unknown locales behave like English.
+pub fn set_locale(locale: &str) {
+    catalog().write().unwrap().current = locale.to_string();
+}
+
+/// Returns the currently selected locale.
+pub fn current_locale() -> String {
+    catalog().read().unwrap().current.clone()
+}
+
+/// Returns the text of `key` in the current locale, falling back to English.
+pub(crate) fn lookup(key: &str) -> String {
+    lookup_in(&current_locale(), key)
+}
+
+/// Returns the text of `key` in `locale`, falling back to English.
+pub(crate) fn lookup_in(locale: &str, key: &str) -> String {
+    catalog()
+        .read()
+        .unwrap()
+        .locales
+        .get(locale)
+        .and_then(|messages| messages.get(key))
+        .cloned()
+        .unwrap_or_else(|| english(key).unwrap_or_default().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tests run in parallel and the selected locale is global, so these only read named locales.
+    #[test]
+    fn test_built_in_portuguese() {
+        assert_eq!(lookup_in("pt-BR", "unexpected_char"), "Token inesperado '{char}'");
+        assert_eq!(lookup_in("de", "unexpected_char"), "Unexpected token '{char}'");
+    }
+
+    #[test]
+    fn test_registered_locale_falls_back_to_english() {
+        let mut messages = HashMap::new();
+        messages.insert("unexpected_char".to_string(), "Jeton inattendu « {char} »".to_string());
+        register_locale("fr", messages);
+        assert_eq!(lookup_in("fr", "unexpected_char"), "Jeton inattendu « {char} »");
+        assert_eq!(lookup_in("fr", "lexer_error"), crate::error::DEFAULT_TEMPLATE);
+    }
+}
@@ -1,40 +1,376 @@
 use std::collections::HashMap;
 use std::env;
 use std::error::Error;
+use std::fs;
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
 
-pub mod lexer;
-use lexer::{
-    process_file_input, DFA, read_accept_states_from_json, read_transitions_from_csv
+use zigzin::batch::{lint_directory, DfaRegistry};
+use zigzin::scanner::LexStats;
+use zigzin::tokens::Token;
+use zigzin::error::{LexError, DEFAULT_TEMPLATE, GNU_TEMPLATE, MSVC_TEMPLATE};
+use zigzin::lexer::{
+    check_input, decode_utf8, process_input_with_stats, try_process_input, BufReadLexer, LexerConfig, DFA,
+    read_accept_states_from_json, read_transitions_from_csv
 };
-pub mod tokens;
+
+fn usage(program: &str) -> ! {
+    eprintln!("Usage: {} [--error-format default|gnu|msvc|<template>] [--profile] [--format debug|json|jsonl] [--color auto|always|never] [--check] [--summary] [--repl] [--dfa <ext>=<transitions.csv>,<final-states.json>]... <input file path>", program);
+    eprintln!("       {} --repl [--error-format ...] [--format ...] [--color ...]", program);
+    eprintln!("       {} lint [--error-format ...] [--ext <extension>] [--dfa ...]... <directory>", program);
+    std::process::exit(1);
+}
+
+/// How the tokens of a file are printed.
+#[derive(Clone, Copy)]
+enum OutputFormat {
+    /// The token vector's `Debug` output.
+    Debug,
+    /// One JSON array holding every token.
+    Json,
+    /// One JSON object per token and line, written as tokens are printed.
+    JsonLines,
+    /// One token per line with its lexeme highlighted by token type, after a legend.
+    Highlighted(ColorChoice),
+}
+
+#[derive(Clone, Copy)]
+enum ColorChoice {
+    /// Colors unless `NO_COLOR` is set or standard output is not a terminal.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Auto => env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// ANSI foreground colors tokens are highlighted with.
+const PALETTE: [&str; 12] = ["31", "32", "33", "34", "35", "36", "91", "92", "93", "94", "95", "96"];
+
+/// Wraps `text` in the ANSI color picked for `token_type`. The color only depends on the type's
+/// name, so a type keeps its color across runs and files.
+fn paint(text: &str, token_type: &str, enabled: bool) -> String {
+    if !enabled {
+        return text.to_string();
+    }
+    // FNV-1a over the name.
+    let hash = token_type.bytes().fold(0x811c_9dc5u32, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193));
+    format!("\x1b[{}m{}\x1b[0m", PALETTE[hash as usize % PALETTE.len()], text)
+}
+
+/// Durations of the phases of a run, collected with `--profile`.
+#[derive(Default)]
+struct Profile {
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Profile {
+    fn time<T>(&mut self, phase: &'static str, run: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = run();
+        self.phases.push((phase, started.elapsed()));
+        result
+    }
+
+    fn print(&self) {
+        let total: Duration = self.phases.iter().map(|(_, duration)| *duration).sum();
+        eprintln!("{:<12} {:>12}", "phase", "time");
+        for (phase, duration) in &self.phases {
+            eprintln!("{:<12} {:>12.3?}", phase, duration);
+        }
+        eprintln!("{:<12} {:>12.3?}", "total", total);
+    }
+}
+
+/// Prints the `--summary` of a scan made with `config` to standard error. The comment nesting is
+/// left out when `config` has no block comments to nest.
+fn print_summary(stats: &LexStats, config: &LexerConfig) {
+    eprintln!("{:<16} {}", "tokens", stats.tokens);
+    eprintln!("{:<16} {} ({} in tokens, {} skipped)", "characters", stats.chars_total(), stats.chars_in_tokens, stats.chars_skipped);
+    if let Some((len, token_type, span)) = &stats.longest_token {
+        eprintln!("{:<16} {} characters, {} at bytes {}..{}", "longest token", len, token_type, span.start, span.end);
+    }
+    if config.scan.block_comment.is_some() {
+        eprintln!("{:<16} {}", "comment nesting", stats.max_comment_depth);
+    }
+}
+
+/// Prints `tokens` in `format` to standard output.
+fn print_tokens(tokens: &[Token], format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    match format {
+        OutputFormat::Debug => println!("{:?}", tokens),
+        OutputFormat::Json => println!("{}", serde_json::to_string(&tokens)?),
+        OutputFormat::JsonLines => {
+            // Standard output is line buffered, so every token line is flushed once written.
+            let mut out = io::stdout().lock();
+            for token in tokens {
+                serde_json::to_writer(&mut out, token)?;
+                writeln!(out)?;
+            }
+        }
+        OutputFormat::Highlighted(choice) => {
+            let enabled = choice.enabled();
+            let types: BTreeSet<&str> = tokens.iter().map(|token| token.token_type.as_str()).collect();
+            let legend: Vec<String> = types.iter().map(|&token_type| paint(token_type, token_type, enabled)).collect();
+            println!("legend: {}", legend.join(" "));
+            for token in tokens {
+                println!(
+                    "{:>4}:{:<3} {} {}",
+                    token.line,
+                    token.column,
+                    paint(&token.lexeme, &token.token_type, enabled),
+                    token.token_type
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Tokenizes every line read from standard input on its own and prints its tokens, or its
+/// error, before reading the next one. Positions count from the start of each line.
+fn repl(dfa: &DFA, format: OutputFormat, error_template: &str) -> Result<(), Box<dyn Error>> {
+    let stdin = io::stdin();
+    let prompt = stdin.is_terminal();
+    let mut line = String::new();
+    loop {
+        if prompt {
+            print!("> ");
+            io::stdout().flush()?;
+        }
+        line.clear();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        match try_process_input(dfa, line.trim_end_matches(['\n', '\r'])) {
+            Ok(tokens) => print_tokens(&tokens, format)?,
+            Err(err) => eprintln!("{}", err.render(error_template, "<stdin>")),
+        }
+    }
+}
+
+/// Reads the input file, decompressing `.gz` files like `process_file_input` does.
+fn read_input(file_path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let content = fs::read(file_path)?;
+    #[cfg(feature = "gzip")]
+    if file_path.ends_with(".gz") {
+        return Ok(zigzin::gzip::decompress(&content)?);
+    }
+    Ok(content)
+}
+
+/// Opens the input file for reading as it is tokenized, decompressing `.gz` files on the fly.
+fn open_input(file_path: &str) -> Result<Box<dyn BufRead>, Box<dyn Error>> {
+    let file = fs::File::open(file_path)?;
+    #[cfg(feature = "gzip")]
+    if file_path.ends_with(".gz") {
+        return Ok(Box::new(io::BufReader::new(zigzin::gzip::decoder(file))));
+    }
+    Ok(Box::new(io::BufReader::new(file)))
+}
+
+/// Writes every token of the file as a JSON line as soon as it is scanned, reading the file
+/// through a `BufReadLexer`, so memory stays flat however large the file is.
+fn stream_json_lines(dfa: &DFA, file_path: &str) -> Result<(), Box<dyn Error>> {
+    let mut out = io::stdout().lock();
+    for item in BufReadLexer::new(dfa, open_input(file_path)?) {
+        let (token, _) = item?;
+        serde_json::to_writer(&mut out, &token)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args: Vec<String> = env::args().collect();
     
-    if args.len() < 2 {
-        eprintln!("Usage: {} <input file path>", args[0]);
-        std::process::exit(1);
+    // Lexer errors are printed through a template, see `LexError::render`.
+    let mut error_template = DEFAULT_TEMPLATE.to_string();
+    let mut file_path = None;
+    let mut rest = args[1..].iter().peekable();
+    // `lint` tokenizes every matching file under a directory and prints a summary.
+    let lint = rest.next_if(|arg| *arg == "lint").is_some();
+    let mut extension = "zig".to_string();
+    let mut profile = None;
+    // `--format` and `--color` each choose how tokens are printed, so only one may be given.
+    let mut format = None;
+    let mut color = None;
+    // `--check` only reports whether the file lexes: every error and the exit status, no tokens.
+    let mut check = false;
+    // `--summary` prints token counts and the longest token to standard error after the tokens.
+    let mut summary = false;
+    // `--repl` tokenizes the lines typed on standard input, one at a time, instead of a file.
+    let mut interactive = false;
+    // `--dfa` picks the automaton by file extension instead of using the one in `automato/`.
+    let mut registry = DfaRegistry::new();
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "--error-format" => {
+                error_template = match rest.next().map(String::as_str) {
+                    Some("default") => DEFAULT_TEMPLATE.to_string(),
+                    Some("gnu") => GNU_TEMPLATE.to_string(),
+                    Some("msvc") => MSVC_TEMPLATE.to_string(),
+                    Some(template) => template.to_string(),
+                    None => usage(&args[0]),
+                };
+            }
+            "--profile" => profile = Some(Profile::default()),
+            "--check" if !lint => check = true,
+            "--summary" if !lint => summary = true,
+            "--repl" if !lint => interactive = true,
+            "--dfa" => {
+                let Some((ext, files)) = rest.next().and_then(|arg| arg.split_once('=')) else { usage(&args[0]) };
+                let Some((transitions, accept)) = files.split_once(',') else { usage(&args[0]) };
+                registry.register(ext, transitions, accept);
+            }
+            "--format" => {
+                format = Some(match rest.next().map(String::as_str) {
+                    Some("debug") => OutputFormat::Debug,
+                    Some("json") => OutputFormat::Json,
+                    Some("jsonl") => OutputFormat::JsonLines,
+                    _ => usage(&args[0]),
+                });
+            }
+            "--color" => {
+                color = Some(match rest.next().map(String::as_str) {
+                    Some("auto") => ColorChoice::Auto,
+                    Some("always") => ColorChoice::Always,
+                    Some("never") => ColorChoice::Never,
+                    _ => usage(&args[0]),
+                });
+            }
+            "--ext" if lint => match rest.next() {
+                Some(ext) => extension = ext.trim_start_matches('.').to_string(),
+                None => usage(&args[0]),
+            },
+            _ if file_path.is_none() => file_path = Some(arg.clone()),
+            _ => usage(&args[0]),
+        }
+    }
+    if interactive && (file_path.is_some() || !registry.extensions().is_empty()) {
+        usage(&args[0]);
+    }
+    let format = match (format, color) {
+        (Some(_), Some(_)) => usage(&args[0]),
+        (Some(format), None) => format,
+        (None, Some(color)) => OutputFormat::Highlighted(color),
+        (None, None) => OutputFormat::Debug,
+    };
+    // JSON Lines are written while the file is scanned, so there are no totals to summarize.
+    let streaming = matches!(format, OutputFormat::JsonLines);
+    if streaming && summary {
+        usage(&args[0]);
     }
-    
-    // The file path is passed as the first command line argument.
-    let file_path = &args[1];
 
-    // Read NFA transitions from CSV file.
-    let transitions = read_transitions_from_csv("automato/DFA-transitions.csv")?;
+    let build_dfa = || -> Result<DFA, Box<dyn Error>> {
+        // Read NFA transitions from CSV file.
+        let transitions = read_transitions_from_csv("automato/DFA-transitions.csv")?;
 
-    // Create a HashSet of accept states [1,2,3,4,5,6,7,8,9,10,11].
-    let accept: HashMap<usize, String> = read_accept_states_from_json("automato/DFA-final-states.json")?;
+        // Create a HashSet of accept states [1,2,3,4,5,6,7,8,9,10,11].
+        let accept: HashMap<usize, String> = read_accept_states_from_json("automato/DFA-final-states.json")?;
 
-    // Define the NFA (assuming start state is 0).
-    let dfa = DFA {
-        transitions,
-        start: 0,
-        accept,
+        // Define the NFA (assuming start state is 0).
+        Ok(DFA::try_new(transitions, 0, accept)?)
+    };
+    let use_registry = !registry.extensions().is_empty();
+    let default_dfa = match profile.as_mut() {
+        _ if use_registry => None,
+        Some(profile) => Some(profile.time("build DFA", build_dfa)?),
+        None => Some(build_dfa()?),
+    };
+
+    if interactive {
+        return repl(default_dfa.as_ref().unwrap(), format, &error_template);
+    }
+    // The file path is the remaining command line argument.
+    let Some(file_path) = file_path else { usage(&args[0]) };
+
+    if lint {
+        let report = match &default_dfa {
+            Some(dfa) => lint_directory(dfa, Path::new(&file_path), &extension),
+            None => registry.lint_directory(Path::new(&file_path)),
+        };
+        for error in &report.errors {
+            println!("{}", error.render(&error_template));
+        }
+        println!("{} files checked, {} with errors", report.files, report.failed_files());
+        std::process::exit(if report.is_success() { 0 } else { 1 });
+    }
+
+    let dfa = match &default_dfa {
+        Some(dfa) => dfa,
+        None => {
+            let path = Path::new(&file_path);
+            let loaded = match profile.as_mut() {
+                Some(profile) => profile.time("build DFA", || registry.dfa_for(path)),
+                None => registry.dfa_for(path),
+            };
+            match loaded {
+                Ok(dfa) => dfa,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
     };
 
-    // Process input from a file using the DFA.
-    let tokens = process_file_input(&dfa, &file_path)?;
-    println!("{:?}",tokens);
+    if check {
+        let content = read_input(&file_path)?;
+        let errors = match decode_utf8(&content, false) {
+            Ok(text) => check_input(dfa, &text),
+            Err(err) => vec![err],
+        };
+        for error in &errors {
+            eprintln!("{}", error.render(&error_template, &file_path));
+        }
+        std::process::exit(if errors.is_empty() { 0 } else { 1 });
+    }
+
+    // Process input from a file using the DFA. Profiling times reading and scanning separately.
+    let config = LexerConfig::default();
+    let scan = |content: &[u8]| -> Result<(Vec<Token>, LexStats), LexError> {
+        let text = decode_utf8(content, false)?;
+        process_input_with_stats(dfa, &text, &config)
+    };
+    // JSON Lines are streamed instead, reading and scanning the file together.
+    let result = match profile.as_mut() {
+        Some(profile) if streaming => profile.time("scan", || stream_json_lines(dfa, &file_path)).map(|()| None),
+        None if streaming => stream_json_lines(dfa, &file_path).map(|()| None),
+        Some(profile) => match profile.time("read file", || read_input(&file_path)) {
+            Ok(content) => profile.time("scan", || scan(&content)).map(Some).map_err(Into::into),
+            Err(err) => Err(err),
+        },
+        None => read_input(&file_path).and_then(|content| Ok(Some(scan(&content)?))),
+    };
+    if let Some(profile) = &profile {
+        profile.print();
+    }
+    let (tokens, stats) = match result {
+        Ok(Some(scanned)) => scanned,
+        Ok(None) => return Ok(()),
+        Err(err) => match err.downcast_ref::<LexError>() {
+            Some(lex_error) => {
+                eprintln!("{}", lex_error.render(&error_template, &file_path));
+                std::process::exit(1);
+            }
+            None => return Err(err),
+        },
+    };
+    print_tokens(&tokens, format)?;
+    if summary {
+        print_summary(&stats, &config);
+    }
     
     Ok(())
 }
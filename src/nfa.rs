@@ -0,0 +1,179 @@
+//! Nondeterministic automata, produced by operations such as `DFA::reverse` and turned back
+//! into a `DFA` with the subset construction.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::lexer::DFA;
+use crate::scanner::Transitions;
+
+/// Representation of an NFA without epsilon transitions. A state may have several targets on the
+/// same character and there may be several start states.
+#[derive(Debug, Clone, Default)]
+pub struct NFA {
+    pub transitions: HashMap<(usize, char), BTreeSet<usize>>,
+    pub starts: BTreeSet<usize>,
+    /// Mapping from an accept state to its label.
+    pub accept: HashMap<usize, String>,
+}
+
+impl NFA {
+    pub fn new(starts: BTreeSet<usize>) -> Self {
+        NFA { starts, ..NFA::default() }
+    }
+
+    /// Adds a transition from `from` to `to` on `ch`, next to any existing ones.
+    pub fn add_transition(&mut self, from: usize, ch: char, to: usize) -> &mut Self {
+        self.transitions.entry((from, ch)).or_default().insert(to);
+        self
+    }
+
+    /// Marks `state` as accepting with the given label.
+    pub fn add_accept(&mut self, state: usize, label: &str) -> &mut Self {
+        self.accept.insert(state, label.to_string());
+        self
+    }
+
+    /// Returns whether some path spelling out `input` leads from a start state to an accept state.
+    pub fn accepts(&self, input: &str) -> bool {
+        let mut current = self.starts.clone();
+        for ch in input.chars() {
+            current = self.step(&current, ch);
+        }
+        current.iter().any(|state| self.accept.contains_key(state))
+    }
+
+    fn step(&self, states: &BTreeSet<usize>, ch: char) -> BTreeSet<usize> {
+        states
+            .iter()
+            .filter_map(|&state| self.transitions.get(&(state, ch)))
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    /// Determinizes the automaton with the subset construction. DFA states are numbered from 0
+    /// (the set of start states) in discovery order and named after the NFA states they stand
+    /// for, e.g. `{1,4}`. A set containing accept states takes the label of the lowest one; when
+    /// its accept states have different labels, all of them are kept in `DFA::accept_labels`,
+    /// ordered by the lowest state carrying each.
+    pub fn to_dfa(&self) -> DFA {
+        let mut alphabet: BTreeMap<usize, BTreeSet<char>> = BTreeMap::new();
+        for &(from, ch) in self.transitions.keys() {
+            alphabet.entry(from).or_default().insert(ch);
+        }
+
+        let mut ids: BTreeMap<BTreeSet<usize>, usize> = BTreeMap::from([(self.starts.clone(), 0)]);
+        let mut pending = vec![self.starts.clone()];
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        let mut names = HashMap::new();
+        while let Some(set) = pending.pop() {
+            let id = ids[&set];
+            let chars: BTreeSet<char> = set.iter().filter_map(|state| alphabet.get(state)).flatten().copied().collect();
+            for ch in chars {
+                let target = self.step(&set, ch);
+                let next_id = ids.len();
+                let target_id = *ids.entry(target.clone()).or_insert_with(|| {
+                    pending.push(target);
+                    next_id
+                });
+                dfa.add_transition(id, ch, target_id);
+            }
+            let mut labels: Vec<&str> = Vec::new();
+            for label in set.iter().filter_map(|state| self.accept.get(state)) {
+                if !labels.contains(&label.as_str()) {
+                    labels.push(label);
+                }
+            }
+            dfa.add_accept_labels(id, &labels);
+            let members: Vec<String> = set.iter().map(usize::to_string).collect();
+            names.insert(id, format!("{{{}}}", members.join(",")));
+        }
+        dfa.with_state_names(names)
+    }
+}
+
+impl DFA {
+    /// Builds the reversed automaton: every transition is flipped, the accept states become
+    /// the start states and the start state becomes the only accept state. It accepts exactly
+    /// the reversals of the strings this DFA accepts, over `DFA::alphabet`. The accept state is
+    /// labelled with the distinct accept labels of this DFA joined by `|`.
+    pub fn reverse(&self) -> NFA {
+        let mut nfa = NFA::new(self.accept.keys().copied().collect());
+        let alphabet = self.alphabet();
+        for state in self.states() {
+            for &ch in &alphabet {
+                if let Some(to) = self.next_state(state, ch) {
+                    nfa.add_transition(to, ch, state);
+                }
+            }
+        }
+        let labels: BTreeSet<&str> = self.accept.values().map(String::as_str).collect();
+        nfa.add_accept(self.start, &labels.into_iter().collect::<Vec<_>>().join("|"));
+        nfa
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Accepts `ab*c` and `ba`.
+    fn sample_dfa() -> DFA {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_transition(0, 'a', 1)
+            .add_transition(1, 'b', 1)
+            .add_transition(1, 'c', 2)
+            .add_transition(0, 'b', 3)
+            .add_transition(3, 'a', 4)
+            .add_accept(2, "abc")
+            .add_accept(4, "ba");
+        dfa
+    }
+
+    #[test]
+    fn test_reverse_then_determinize_accepts_reversals() {
+        let dfa = sample_dfa();
+        let reversed = dfa.reverse();
+        let determinized = reversed.to_dfa();
+        for word in ["ac", "abc", "abbbc", "ba", "", "a", "ab", "abca", "bac"] {
+            let reversed_word: String = word.chars().rev().collect();
+            assert_eq!(reversed.accepts(&reversed_word), dfa.accepts(word), "{}", word);
+            assert_eq!(determinized.accepts(&reversed_word), dfa.accepts(word), "{}", word);
+        }
+        assert_eq!(determinized.accept.values().next().map(String::as_str), Some("abc|ba"));
+    }
+
+    #[test]
+    fn test_to_dfa_merges_nondeterministic_targets() {
+        let mut nfa = NFA::new(BTreeSet::from([0]));
+        nfa.add_transition(0, 'a', 1).add_transition(0, 'a', 2).add_transition(2, 'b', 3).add_accept(1, "a").add_accept(3, "ab");
+        let dfa = nfa.to_dfa();
+        assert!(dfa.accepts("a") && dfa.accepts("ab") && !dfa.accepts("b"));
+        assert_eq!(dfa.state_name(dfa.transitions[&(0, 'a')]), "{1,2}");
+    }
+
+    #[test]
+    fn test_label_policy_resolves_merged_accept_states() {
+        use crate::scanner::{scan_with_options, LabelPolicy, ScanOptions};
+
+        // `if` is both a keyword and an identifier; `ix` only an identifier.
+        let mut nfa = NFA::new(BTreeSet::from([0, 10]));
+        nfa.add_transition(0, 'i', 1).add_transition(1, 'f', 2).add_accept(2, "keyword");
+        nfa.add_transition(10, 'i', 11)
+            .add_transition(11, 'f', 11)
+            .add_transition(11, 'x', 11)
+            .add_accept(11, "identifier");
+        let dfa = nfa.to_dfa();
+        let merged = dfa.transitions[&(dfa.transitions[&(0, 'i')], 'f')];
+        assert_eq!(dfa.accept_labels[&merged], ["keyword", "identifier"]);
+
+        let chars: Vec<char> = "if ix".chars().collect();
+        let types = |label_policy: LabelPolicy| -> Vec<String> {
+            let options = ScanOptions { label_policy, ..ScanOptions::default() };
+            scan_with_options(&dfa, &chars, &options).unwrap().into_iter().map(|token| token.token_type).collect()
+        };
+        assert_eq!(types(LabelPolicy::First), ["keyword", "identifier"]);
+        let priority = LabelPolicy::Priority(vec!["identifier".to_string(), "keyword".to_string()]);
+        assert_eq!(types(priority), ["identifier", "identifier"]);
+        assert_eq!(types(LabelPolicy::Priority(vec!["string".to_string()])), ["keyword", "identifier"]);
+        assert_eq!(types(LabelPolicy::All), ["keyword|identifier", "identifier"]);
+    }
+}
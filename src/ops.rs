@@ -0,0 +1,316 @@
+//! Language operations that combine or transform DFAs into new DFAs. The results only have
+//! exact-character transitions: intervals and negated classes are expanded over the alphabet
+//! the operation works on.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::lexer::DFA;
+use crate::scanner::Transitions;
+
+/// Label of the accept states of a complement.
+pub const COMPLEMENT_LABEL: &str = "complement";
+
+/// State name of the trap state added by `DFA::complete`.
+pub const TRAP_STATE_NAME: &str = "trap";
+
+impl DFA {
+    /// Complements the automaton over `DFA::alphabet`, see `complement_over`.
+    pub fn complement(&self) -> DFA {
+        self.complement_over(&self.alphabet())
+    }
+
+    /// Builds an automaton accepting exactly the strings over `alphabet` this one rejects. The
+    /// automaton is first made total with `complete`, then accepting and non-accepting states
+    /// swap roles; the new accept states are labelled `COMPLEMENT_LABEL`. Strings containing a
+    /// character outside `alphabet` are rejected by both automata.
+    pub fn complement_over(&self, alphabet: &BTreeSet<char>) -> DFA {
+        let alphabet: Vec<char> = alphabet.iter().copied().collect();
+        let total = self.complete(&alphabet);
+        let mut complement = DFA::new(HashMap::new(), self.start, HashMap::new());
+        for state in total.states() {
+            for &ch in &alphabet {
+                // `complete` guarantees a transition for every character of the alphabet.
+                complement.add_transition(state, ch, total.next_state(state, ch).unwrap());
+            }
+            if !self.accept.contains_key(&state) {
+                complement.add_accept(state, COMPLEMENT_LABEL);
+            }
+        }
+        complement.with_state_names(total.state_names)
+    }
+
+    /// Makes the transition function total over `alphabet`: a new trap state, numbered after the
+    /// highest existing state and named `TRAP_STATE_NAME`, receives every missing `(state, ch)`
+    /// transition and loops to itself. The accepted language is unchanged. The trap state is never
+    /// accepting and cannot be left, so it is a dead state by design, which `DFA::validate`
+    /// recognizes by its name.
+    pub fn complete(&self, alphabet: &[char]) -> DFA {
+        let states = self.states();
+        let trap = states.last().map_or(0, |&last| last + 1);
+        let mut total = self.clone();
+        for &state in states.iter().chain([&trap]) {
+            for &ch in alphabet {
+                if self.next_state(state, ch).is_none() || state == trap {
+                    total.add_transition(state, ch, trap);
+                }
+            }
+        }
+        total.state_names.insert(trap, TRAP_STATE_NAME.to_string());
+        total
+    }
+
+    /// Intersects two automata, see `intersect_with`. A product state is labelled with the common
+    /// label when both components agree and with `first&second` otherwise.
+    pub fn intersect(&self, other: &DFA) -> DFA {
+        self.intersect_with(other, |first, second| {
+            if first == second {
+                first.to_string()
+            } else {
+                format!("{}&{}", first, second)
+            }
+        })
+    }
+
+    /// Builds the product automaton accepting the strings both automata accept, over the union
+    /// of their alphabets. Only product states reachable from the pair of start states are
+    /// created; they are numbered from 0 in discovery order and named `(first,second)`. A
+    /// product state accepts when both components do, with the label `label(first, second)`.
+    pub fn intersect_with(&self, other: &DFA, label: impl Fn(&str, &str) -> String) -> DFA {
+        self.product(other, false, |first, second| Some(label(first?, second?)))
+    }
+
+    /// Merges two automata, see `union_with`. When both accept a string, the label of `self` wins.
+    /// Like every product, the result only reads the characters of the two alphabets: a negated
+    /// class such as `[^"]` no longer matches a character neither automaton mentions, e.g. `é`.
+    /// To add keywords to a lexer with such classes, use `with_keywords`, which keeps them.
+    pub fn union(&self, other: &DFA) -> DFA {
+        self.union_with(other, |first, _| first.to_string())
+    }
+
+    /// Builds the product automaton accepting the strings either automaton accepts, e.g. to merge
+    /// separately maintained token groups into one lexer. Product states are built like in
+    /// `intersect_with`, except that a component may have stopped (shown as `-` in the state
+    /// name) while the other goes on. A state accepted by one side keeps that side's label; when
+    /// both accept, the label is `resolve(first, second)` and, if the two differ, both are kept
+    /// in `accept_labels` after the resolved one, so `ScanOptions::label_policy` can pick another.
+    /// A state takes the category and action of the component whose label it has, and the rule
+    /// ranks of both automata are kept, those of `self` winning for a label both rank. Negated
+    /// classes are cut down to the union alphabet, see `union`.
+    pub fn union_with(&self, other: &DFA, resolve: impl Fn(&str, &str) -> String) -> DFA {
+        self.product(other, true, |first, second| match (first, second) {
+            (Some(first), Some(second)) => Some(resolve(first, second)),
+            (label, None) | (None, label) => label.map(str::to_string),
+        })
+    }
+
+    /// Runs both automata side by side from their start states over the union alphabet. With
+    /// `partial`, a pair keeps going while at least one component has a transition; otherwise
+    /// both need one. `label` decides the label of a pair from the components' accept labels.
+    fn product(
+        &self,
+        other: &DFA,
+        partial: bool,
+        label: impl Fn(Option<&str>, Option<&str>) -> Option<String>,
+    ) -> DFA {
+        type Pair = (Option<usize>, Option<usize>);
+        fn name(dfa: &DFA, state: Option<usize>) -> String {
+            state.map_or_else(|| "-".to_string(), |state| dfa.state_name(state))
+        }
+
+        let alphabet: BTreeSet<char> = self.alphabet().union(&other.alphabet()).copied().collect();
+        let start = (Some(self.start), Some(other.start));
+        let mut ids: BTreeMap<Pair, usize> = BTreeMap::from([(start, 0)]);
+        let mut pending = vec![start];
+        let mut product = DFA::new(HashMap::new(), 0, HashMap::new());
+        let mut names = HashMap::new();
+        while let Some((first, second)) = pending.pop() {
+            let id = ids[&(first, second)];
+            for &ch in &alphabet {
+                let first_to = first.and_then(|state| self.next_state(state, ch));
+                let second_to = second.and_then(|state| other.next_state(state, ch));
+                let keep = match (first_to, second_to) {
+                    (Some(_), Some(_)) => true,
+                    (None, None) => false,
+                    _ => partial,
+                };
+                if !keep {
+                    continue;
+                }
+                let next_id = ids.len();
+                let to = *ids.entry((first_to, second_to)).or_insert_with(|| {
+                    pending.push((first_to, second_to));
+                    next_id
+                });
+                product.add_transition(id, ch, to);
+            }
+            let first_label = first.and_then(|state| self.accept.get(&state)).map(String::as_str);
+            let second_label = second.and_then(|state| other.accept.get(&state)).map(String::as_str);
+            if let Some(label) = label(first_label, second_label) {
+                product.add_accept(id, &label);
+                let owner = match (first_label, second_label) {
+                    (Some(first_label), _) if first_label == label => first.map(|state| (self, state)),
+                    (_, Some(second_label)) if second_label == label => second.map(|state| (other, state)),
+                    _ => None,
+                };
+                if let Some((dfa, state)) = owner {
+                    if let Some(category) = dfa.categories.get(&state) {
+                        product.categories.insert(id, category.clone());
+                    }
+                    if let Some(action) = dfa.actions.get(&state) {
+                        product.actions.insert(id, action.clone());
+                    }
+                }
+                match (first_label, second_label) {
+                    (Some(first), Some(second)) if partial && first != second => {
+                        // The resolved label comes first, as `accept_labels` requires.
+                        let others = [first, second].into_iter().filter(|&other| other != label).map(str::to_string);
+                        let labels = std::iter::once(label.clone()).chain(others).collect();
+                        product.accept_labels.insert(id, labels);
+                    }
+                    _ => {}
+                }
+            }
+            names.insert(id, format!("({},{})", name(self, first), name(other, second)));
+        }
+        product.trivia = self.trivia.union(&other.trivia).cloned().collect();
+        product.rule_ranks = other.rule_ranks.clone();
+        product.rule_ranks.extend(self.rule_ranks.iter().map(|(label, &rank)| (label.clone(), rank)));
+        product.with_state_names(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Accepts `a` and `ab+`.
+    fn sample_dfa() -> DFA {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_transition(0, 'a', 1).add_transition(1, 'b', 2).add_transition(2, 'b', 2);
+        dfa.add_accept(1, "a").add_accept(2, "ab");
+        dfa
+    }
+
+    /// Every string over `alphabet` up to `max_len` characters.
+    fn words(alphabet: &[char], max_len: usize) -> Vec<String> {
+        let mut words = vec![String::new()];
+        let mut last = vec![String::new()];
+        for _ in 0..max_len {
+            last = last.iter().flat_map(|word| alphabet.iter().map(move |&ch| format!("{}{}", word, ch))).collect();
+            words.extend(last.iter().cloned());
+        }
+        words
+    }
+
+    #[test]
+    fn test_complement_accepts_exactly_the_rejected_strings() {
+        let dfa = sample_dfa();
+        let complement = dfa.complement();
+        for word in words(&['a', 'b'], 5) {
+            assert_ne!(complement.accepts(&word), dfa.accepts(&word), "{:?}", word);
+        }
+        assert!(!complement.accepts("ac"));
+    }
+
+    #[test]
+    fn test_complete_preserves_language() {
+        let dfa = sample_dfa();
+        let total = dfa.complete(&['a', 'b']);
+        let trap = total.transitions[&(0, 'b')];
+        assert_eq!(total.state_name(trap), TRAP_STATE_NAME);
+        assert!(!total.accept.contains_key(&trap));
+        for state in total.states() {
+            assert!(['a', 'b'].iter().all(|&ch| total.next_state(state, ch).is_some()));
+        }
+        for word in words(&['a', 'b'], 5) {
+            assert_eq!(total.accepts(&word), dfa.accepts(&word), "{:?}", word);
+        }
+    }
+
+    #[test]
+    fn test_intersect_accepts_strings_both_accept() {
+        // `a(a|b)*`, starting with `a`.
+        let mut starts_with_a = DFA::new(HashMap::new(), 0, HashMap::new());
+        starts_with_a.add_transition(0, 'a', 1).add_interval(1, 'a', 'b', 1).unwrap();
+        starts_with_a.add_accept(1, "id");
+        // `(a|b)*b`, ending with `b`.
+        let mut ends_with_b = DFA::new(HashMap::new(), 0, HashMap::new());
+        ends_with_b.add_transition(0, 'a', 0).add_transition(0, 'b', 1);
+        ends_with_b.add_transition(1, 'a', 0).add_transition(1, 'b', 1);
+        ends_with_b.add_accept(1, "suffix");
+
+        let product = starts_with_a.intersect(&ends_with_b);
+        for word in words(&['a', 'b'], 5) {
+            let expected = starts_with_a.accepts(&word) && ends_with_b.accepts(&word);
+            assert_eq!(product.accepts(&word), expected, "{:?}", word);
+        }
+        let accept_state = product.transitions[&(product.transitions[&(0, 'a')], 'b')];
+        assert_eq!(product.accept[&accept_state], "id&suffix");
+        assert_eq!(product.state_name(accept_state), "(1,1)");
+
+        let custom = starts_with_a.intersect_with(&ends_with_b, |_, second| second.to_string());
+        assert!(custom.accept.values().all(|label| label == "suffix"));
+    }
+
+    #[test]
+    fn test_union_tokenizes_both_languages() {
+        use crate::lexer::try_process_input;
+
+        let mut numbers = DFA::new(HashMap::new(), 0, HashMap::new());
+        numbers.add_interval(0, '0', '9', 1).unwrap().add_interval(1, '0', '9', 1).unwrap();
+        numbers.add_accept(1, "integer");
+        let mut words = DFA::new(HashMap::new(), 0, HashMap::new());
+        words.add_interval(0, 'a', 'z', 1).unwrap().add_interval(1, 'a', 'z', 1).unwrap();
+        words.add_transition(0, 'i', 2).add_transition(2, 'f', 3);
+        words.add_interval(2, 'a', 'z', 1).unwrap();
+        words.add_accept(1, "id").add_accept(2, "id").add_accept(3, "if");
+        let mut keywords = DFA::new(HashMap::new(), 0, HashMap::new());
+        keywords.add_transition(0, 'i', 1).add_transition(1, 'f', 2).add_accept(2, "keyword");
+
+        let merged = numbers.union(&words);
+        let tokens = try_process_input(&merged, "if 42 x7").unwrap();
+        let labels: Vec<(&str, &str)> = tokens.iter().map(|t| (t.token_type.as_str(), t.lexeme.as_str())).collect();
+        assert_eq!(labels, [("if", "if"), ("integer", "42"), ("id", "x"), ("integer", "7")]);
+
+        let preferred = words.union(&keywords);
+        assert_eq!(preferred.accept[&merged_state(&preferred, "if")], "if");
+        let resolved = words.union_with(&keywords, |_, second| second.to_string());
+        assert_eq!(resolved.accept[&merged_state(&resolved, "if")], "keyword");
+    }
+
+    #[test]
+    fn test_union_keeps_categories_actions_and_ranks() {
+        use crate::lexer::RuleRank;
+
+        let mut numbers = DFA::new(HashMap::new(), 0, HashMap::new());
+        numbers.add_transition(0, '1', 1).add_accept(1, "integer");
+        let numbers = numbers
+            .with_categories(HashMap::from([(1, "literal".to_string())]))
+            .with_rule_ranks(HashMap::from([("integer".to_string(), RuleRank { priority: 1, declared: 0 })]));
+        let mut strings = DFA::new(HashMap::new(), 0, HashMap::new());
+        strings.add_transition(0, '"', 1).add_transition(1, '"', 2).add_accept(2, "string");
+        let strings = strings
+            .with_categories(HashMap::from([(2, "text".to_string())]))
+            .with_actions(HashMap::from([(2, "strip_quotes".to_string())]));
+
+        let merged = numbers.union(&strings);
+        let integer = merged_state(&merged, "1");
+        let string = merged_state(&merged, "\"\"");
+        assert_eq!(merged.categories.get(&integer).map(String::as_str), Some("literal"));
+        assert_eq!(merged.categories.get(&string).map(String::as_str), Some("text"));
+        assert_eq!(merged.actions.get(&string).map(String::as_str), Some("strip_quotes"));
+        assert!(!merged.actions.contains_key(&integer));
+        assert_eq!(merged.rule_ranks["integer"].priority, 1);
+    }
+
+    /// The state reached from the start state on `input`.
+    fn merged_state(dfa: &DFA, input: &str) -> usize {
+        input.chars().fold(dfa.start, |state, ch| dfa.transitions[&(state, ch)])
+    }
+
+    #[test]
+    fn test_complement_over_wider_alphabet() {
+        let complement = sample_dfa().complement_over(&BTreeSet::from(['a', 'b', 'c']));
+        assert!(complement.accepts("ac") && complement.accepts("c"));
+        assert!(!complement.accepts("abb"));
+        assert_eq!(complement.accept.get(&0).map(String::as_str), Some(COMPLEMENT_LABEL));
+    }
+}
@@ -0,0 +1,444 @@
+//! Post-processing passes that operate on an already scanned token vector.
+use std::collections::{HashMap, HashSet};
+use crate::scanner::Action;
+use crate::tokens::Token;
+
+/// Collapses runs of back-to-back tokens whose type is in `types` into a single token; tokens
+/// separated by anything, even whitespace, stay apart. The merged token keeps the type, leading
+/// trivia and starting line/column of the first token of the run, concatenates the lexemes, takes
+/// the trailing trivia of the last token and its span covers the whole combined range, so the
+/// lexeme still matches the source under the span and `reconstruct` gives the same text.
+pub fn coalesce_tokens(tokens: Vec<Token>, types: &HashSet<String>) -> Vec<Token> {
+    let mut result: Vec<Token> = Vec::with_capacity(tokens.len());
+    for token in tokens {
+        if let Some(last) = result.last_mut() {
+            let touching = last.span.end == token.span.start
+                && last.trailing_trivia.is_empty()
+                && token.leading_trivia.is_empty();
+            if last.token_type == token.token_type && types.contains(&token.token_type) && touching {
+                last.lexeme.push_str(&token.lexeme);
+                last.span = last.span.merge(token.span);
+                last.trailing_trivia = token.trailing_trivia;
+                continue;
+            }
+        }
+        result.push(token);
+    }
+    result
+}
+
+/// Rebuilds the source text from tokens scanned with trivia preservation by concatenating
+/// each token's leading trivia and lexeme, followed by the trailing trivia of the last token.
+/// Tokens whose lexeme an accept-state action rewrote give back the rewritten text instead.
+pub fn reconstruct(tokens: &[Token]) -> String {
+    let mut source = String::new();
+    for token in tokens {
+        source.push_str(&token.leading_trivia);
+        source.push_str(&token.lexeme);
+        source.push_str(&token.trailing_trivia);
+    }
+    source
+}
+
+/// Marks tokens whose lexeme is one of the contextual `keywords` by recording it in
+/// `soft_keyword`. Unlike a keyword rewrite, `token_type` is kept as scanned.
+pub fn annotate_soft_keywords(tokens: &mut [Token], keywords: &HashSet<String>) {
+    for token in tokens.iter_mut() {
+        if keywords.contains(&token.lexeme) {
+            token.soft_keyword = Some(token.lexeme.clone());
+        }
+    }
+}
+
+/// Stores `normalize(lexeme)` in `normalized` for every token whose type is in `types`, e.g.
+/// lowercasing identifiers of a case-insensitive language. The raw `lexeme` is kept.
+pub fn normalize_lexemes(tokens: &mut [Token], types: &HashSet<String>, normalize: fn(&str) -> String) {
+    for token in tokens.iter_mut() {
+        if types.contains(&token.token_type) {
+            token.normalized = Some(normalize(&token.lexeme));
+        }
+    }
+}
+
+/// Stores `cook(lexeme)` in `display` for every token whose type is in `types`, e.g.
+/// `unescape_string` for string literals or removing the separators of numbers. Unlike
+/// `normalize_lexemes`, it leaves `normalized` alone, so both passes can run on the same tokens.
+pub fn display_lexemes(tokens: &mut [Token], types: &HashSet<String>, cook: fn(&str) -> String) {
+    for token in tokens.iter_mut() {
+        if types.contains(&token.token_type) {
+            token.display = Some(cook(&token.lexeme));
+        }
+    }
+}
+
+fn unquoted(lexeme: &str) -> &str {
+    let quoted = ['"', '\''].into_iter().any(|quote| lexeme.len() >= 2 && lexeme.starts_with(quote) && lexeme.ends_with(quote));
+    if quoted { &lexeme[1..lexeme.len() - 1] } else { lexeme }
+}
+
+/// Drops the `"` or `'` quotes around a lexeme, keeping escapes as written. Lexemes that are not
+/// quoted are returned unchanged.
+pub fn strip_quotes(lexeme: &str) -> String {
+    unquoted(lexeme).to_string()
+}
+
+/// The built-in action named `name`: `strip_quotes`, `unescape` (see `unescape_string`),
+/// `lowercase`, `uppercase` or `trim`.
+pub fn builtin_action(name: &str) -> Option<Action> {
+    let action: Action = match name {
+        "strip_quotes" => strip_quotes,
+        "unescape" => unescape_string,
+        "lowercase" => str::to_lowercase,
+        "uppercase" => str::to_uppercase,
+        "trim" => |lexeme| lexeme.trim().to_string(),
+        _ => return None,
+    };
+    Some(action)
+}
+
+/// The value of a string literal: the surrounding `"` or `'` quotes are dropped and the escapes
+/// `\n`, `\t`, `\r`, `\0`, `\\`, `\"`, `\'` and `\u{...}` are replaced by the characters they
+/// stand for. Other escapes are kept as written. Pass it to `display_lexemes` to keep the
+/// cooked value in `display` while `lexeme` stays as in the source.
+pub fn unescape_string(lexeme: &str) -> String {
+    let body = unquoted(lexeme);
+    let mut value = String::with_capacity(body.len());
+    let mut chars = body.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            value.push(ch);
+            continue;
+        }
+        let rest = chars.as_str();
+        let escaped = match chars.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some(ch @ ('\\' | '"' | '\'')) => ch,
+            Some('u') => {
+                let code = rest.strip_prefix("u{").and_then(|code| code.split_once('}'));
+                match code.and_then(|(hex, _)| u32::from_str_radix(hex, 16).ok()).and_then(char::from_u32) {
+                    Some(ch) => {
+                        chars = code.unwrap().1.chars();
+                        ch
+                    }
+                    None => {
+                        value.push_str("\\u");
+                        continue;
+                    }
+                }
+            }
+            Some(other) => {
+                value.push('\\');
+                other
+            }
+            None => '\\',
+        };
+        value.push(escaped);
+    }
+    value
+}
+
+/// Returns the tokens whose `category` is `category`, in order.
+pub fn tokens_in_category<'a>(tokens: &'a [Token], category: &'a str) -> impl Iterator<Item = &'a Token> + 'a {
+    tokens.iter().filter(move |token| token.category.as_deref() == Some(category))
+}
+
+/// A rule of `reclassify`: a token of type `target` whose preceding tokens have the types
+/// `before` and whose following tokens have the types `after`, both in source order, gets the
+/// type `replacement`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReclassifyRule {
+    pub before: Vec<String>,
+    pub target: String,
+    pub after: Vec<String>,
+    pub replacement: String,
+}
+
+impl ReclassifyRule {
+    pub fn new(before: &[&str], target: &str, after: &[&str], replacement: &str) -> Self {
+        ReclassifyRule {
+            before: before.iter().map(|t| t.to_string()).collect(),
+            target: target.to_string(),
+            after: after.iter().map(|t| t.to_string()).collect(),
+            replacement: replacement.to_string(),
+        }
+    }
+
+    fn matches(&self, types: &[&str], index: usize) -> bool {
+        let (before, after) = (self.before.len(), self.after.len());
+        index >= before
+            && index + after < types.len()
+            && types[index] == self.target
+            && self.before.iter().zip(&types[index - before..index]).all(|(rule, found)| rule == found)
+            && self.after.iter().zip(&types[index + 1..]).all(|(rule, found)| rule == found)
+    }
+}
+
+/// Rewrites token types from their neighbours, e.g. an `identifier` followed by `(` into a
+/// `function_name`. Tokens are visited left to right and the first rule that matches a token
+/// decides its type. Rules only see the types as they were before the pass, so a rewritten token
+/// does not trigger another rule; run the pass again to chain rules on purpose.
+pub fn reclassify(tokens: &mut [Token], rules: &[ReclassifyRule]) {
+    let original: Vec<String> = tokens.iter().map(|token| token.token_type.clone()).collect();
+    let types: Vec<&str> = original.iter().map(String::as_str).collect();
+    for (index, token) in tokens.iter_mut().enumerate() {
+        if let Some(rule) = rules.iter().find(|rule| rule.matches(&types, index)) {
+            token.token_type = rule.replacement.clone();
+        }
+    }
+}
+
+/// Items of a token stream an adapter can rewrite: plain tokens or the results a
+/// `scanner::Lexer` yields, whose errors pass through untouched.
+pub trait TokenItem {
+    fn token(&self) -> Option<&Token>;
+    fn token_mut(&mut self) -> Option<&mut Token>;
+}
+
+impl TokenItem for Token {
+    fn token(&self) -> Option<&Token> {
+        Some(self)
+    }
+
+    fn token_mut(&mut self) -> Option<&mut Token> {
+        Some(self)
+    }
+}
+
+impl<E> TokenItem for Result<Token, E> {
+    fn token(&self) -> Option<&Token> {
+        self.as_ref().ok()
+    }
+
+    fn token_mut(&mut self) -> Option<&mut Token> {
+        self.as_mut().ok()
+    }
+}
+
+/// Drops the tokens marked as trivia (see `DFA::with_trivia`) from a token stream, so a parser
+/// sees only meaningful tokens without listing whitespace and comment types itself. Errors pass through.
+pub fn skip_trivia<I: Iterator>(items: I) -> impl Iterator<Item = I::Item>
+where
+    I::Item: TokenItem,
+{
+    items.filter(|item| !item.token().is_some_and(Token::is_trivia))
+}
+
+/// Reclassifies keywords while tokens stream by: a token of type `identifier` whose lexeme is a
+/// key of `keywords` gets the mapped type. Nothing is buffered, so the adapter composes with
+/// `filter`, `peekable` and the like without collecting the stream first.
+pub struct Keywords<I> {
+    inner: I,
+    identifier: String,
+    keywords: HashMap<String, String>,
+}
+
+impl<I> Keywords<I> {
+    pub fn new(inner: I, identifier: &str, keywords: HashMap<String, String>) -> Self {
+        Keywords { inner, identifier: identifier.to_string(), keywords }
+    }
+}
+
+impl<I: Iterator> Iterator for Keywords<I>
+where
+    I::Item: TokenItem,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut item = self.inner.next()?;
+        if let Some(token) = item.token_mut().filter(|token| token.token_type == self.identifier) {
+            if let Some(keyword) = self.keywords.get(&token.lexeme) {
+                token.token_type = keyword.clone();
+            }
+        }
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens::Span;
+
+    fn token(token_type: &str, lexeme: &str, start: usize) -> Token {
+        Token::new(token_type.to_string(), lexeme.to_string())
+            .with_position(Span::new(start, start + lexeme.len()), 1, start + 1)
+    }
+
+    #[test]
+    fn test_coalesce_tokens_merges_runs_of_selected_types() {
+        let tokens = vec![
+            token("error", "@", 0),
+            token("error", "$", 1),
+            token("id", "a", 2),
+            token("id", "b", 4),
+            token("error", "#", 5),
+        ];
+        let types: HashSet<String> = ["error".to_string()].into_iter().collect();
+        let merged = coalesce_tokens(tokens, &types);
+        assert_eq!(merged, vec![
+            token("error", "@$", 0),
+            token("id", "a", 2),
+            token("id", "b", 4),
+            token("error", "#", 5),
+        ]);
+
+        // `a b` is not merged: the lexeme `ab` would not be the source under the span 0..3.
+        let ids: HashSet<String> = ["id".to_string()].into_iter().collect();
+        let apart = vec![token("id", "a", 0), token("id", "b", 2)];
+        assert_eq!(coalesce_tokens(apart.clone(), &ids), apart);
+    }
+
+    #[test]
+    fn test_coalesce_tokens_keeps_trivia() {
+        // ` @$ x\n` with its trivia: the run `@$` stays back-to-back with ` ` before and after it.
+        let mut first = token("error", "@", 1);
+        first.leading_trivia = " ".to_string();
+        let mut second = token("error", "$", 2);
+        second.trailing_trivia = " ".to_string();
+        let mut last = token("id", "x", 4);
+        last.trailing_trivia = "\n".to_string();
+        let tokens = vec![first, second, last];
+        let source = reconstruct(&tokens);
+        let types: HashSet<String> = ["error".to_string()].into_iter().collect();
+        let merged = coalesce_tokens(tokens, &types);
+        assert_eq!(merged.len(), 2);
+        assert_eq!((merged[0].lexeme.as_str(), merged[0].span), ("@$", Span::new(1, 3)));
+        assert_eq!(reconstruct(&merged), source);
+        assert_eq!(source, " @$ x\n");
+    }
+
+    #[test]
+    fn test_annotate_soft_keywords_keeps_identifier_type() {
+        let mut tokens = vec![token("id", "async", 0), token("id", "yield", 6), token("id", "value", 12)];
+        let keywords: HashSet<String> = ["async".to_string(), "yield".to_string()].into_iter().collect();
+        annotate_soft_keywords(&mut tokens, &keywords);
+        assert!(tokens.iter().all(|token| token.token_type == "id"));
+        assert_eq!(tokens[0].soft_keyword.as_deref(), Some("async"));
+        assert_eq!(tokens[1].soft_keyword.as_deref(), Some("yield"));
+        assert_eq!(tokens[2].soft_keyword, None);
+    }
+
+    /// Composes `e` or `a` followed by U+0301 COMBINING ACUTE ACCENT into the precomposed
+    /// letter, a tiny slice of Unicode NFC.
+    fn compose_acute(text: &str) -> String {
+        let mut out = String::new();
+        for ch in text.chars() {
+            match (out.chars().last(), ch) {
+                (Some(base @ ('e' | 'a')), '\u{301}') => {
+                    out.pop();
+                    out.push(if base == 'e' { 'é' } else { 'á' });
+                }
+                _ => out.push(ch),
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_normalize_lexemes_keeps_raw_form() {
+        let mut tokens = vec![token("id", "cafe\u{301}", 0), token("id", "café", 7), token("string", "e\u{301}", 13)];
+        let types: HashSet<String> = ["id".to_string()].into_iter().collect();
+        normalize_lexemes(&mut tokens, &types, compose_acute);
+        assert_eq!(tokens[0].lexeme, "cafe\u{301}");
+        assert_eq!(tokens[0].normalized_lexeme(), tokens[1].normalized_lexeme());
+        assert_eq!(tokens[2].normalized, None);
+        assert_eq!(tokens[2].normalized_lexeme(), "e\u{301}");
+
+        normalize_lexemes(&mut tokens, &types, str::to_lowercase);
+        assert_eq!(tokens[1].normalized.as_deref(), Some("café"));
+    }
+
+    #[test]
+    fn test_unescape_string_fills_display_lexeme_and_keeps_raw_lexeme() {
+        let raw = r#""tab\there \"q\" \u{E9} \x \u{zz}""#;
+        let mut tokens = vec![token("string", raw, 0), token("id", "a\\n", 30)];
+        let types: HashSet<String> = ["string".to_string()].into_iter().collect();
+        normalize_lexemes(&mut tokens, &types, str::to_lowercase);
+        display_lexemes(&mut tokens, &types, unescape_string);
+        assert_eq!(tokens[0].lexeme, raw);
+        assert_eq!(tokens[0].display_lexeme(), "tab\there \"q\" é \\x \\u{zz}");
+        assert_eq!(tokens[0].normalized_lexeme(), raw.to_lowercase());
+        assert_eq!(tokens[1].display_lexeme(), tokens[1].lexeme);
+        assert_eq!(unescape_string("'\\\\'"), "\\");
+        assert_eq!(unescape_string("\"ab\\"), "\"ab\\");
+    }
+
+    #[test]
+    fn test_keywords_reclassify_lexer_stream() {
+        use crate::lexer::DFA;
+        use crate::scanner::{Lexer, ScanOptions};
+
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_interval(0, 'a', 'z', 1).unwrap().add_interval(1, 'a', 'z', 1).unwrap().add_accept(1, "id");
+        let chars: Vec<char> = "if x else fi ?".chars().collect();
+        let keywords = HashMap::from([("if".to_string(), "if".to_string()), ("else".to_string(), "else".to_string())]);
+        let mut stream = Keywords::new(Lexer::new(&dfa, chars.as_slice(), ScanOptions::default()), "id", keywords.clone())
+            .filter(|item| item.as_ref().map_or(true, |token| token.lexeme != "x"))
+            .peekable();
+        assert_eq!(stream.peek().unwrap().as_ref().unwrap().token_type, "if");
+        let types: Vec<Result<String, _>> = stream.map(|item| item.map(|token| token.token_type)).collect();
+        assert_eq!(types[..3], [Ok("if".to_string()), Ok("else".to_string()), Ok("id".to_string())]);
+        assert!(types[3].is_err());
+
+        let plain = Keywords::new(vec![token("integer", "if", 0), token("id", "if", 3)].into_iter(), "id", keywords);
+        assert_eq!(plain.map(|token| token.token_type).collect::<Vec<_>>(), ["integer", "if"]);
+    }
+
+    #[test]
+    fn test_skip_trivia_drops_flagged_tokens() {
+        use crate::lexer::{try_process_input, DFA};
+        use crate::scanner::{Lexer, ScanOptions};
+
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_interval(0, 'a', 'z', 1).unwrap().add_interval(1, 'a', 'z', 1).unwrap().add_accept(1, "id");
+        dfa.add_transition(0, '#', 2).add_interval(2, 'a', 'z', 2).unwrap().add_accept(2, "comment");
+        let dfa = dfa.with_trivia(&["comment"]);
+        let tokens = try_process_input(&dfa, "a #note b").unwrap();
+        let flags: Vec<bool> = tokens.iter().map(Token::is_trivia).collect();
+        assert_eq!(flags, [false, true, false]);
+        let lexemes: Vec<String> = skip_trivia(tokens.into_iter()).map(|token| token.lexeme).collect();
+        assert_eq!(lexemes, ["a", "b"]);
+
+        let chars: Vec<char> = "#x a ?".chars().collect();
+        let items: Vec<_> = skip_trivia(Lexer::new(&dfa, chars.as_slice(), ScanOptions::default())).collect();
+        assert_eq!(items[0].as_ref().unwrap().lexeme, "a");
+        assert!(items[1].is_err());
+    }
+
+    #[test]
+    fn test_reclassify_rewrites_tokens_from_their_neighbours() {
+        let mut tokens = vec![
+            token("identifier", "f", 0),
+            token("(", "(", 1),
+            token("identifier", "x", 2),
+            token(")", ")", 3),
+            token("identifier", "g", 5),
+        ];
+        let rules = [
+            ReclassifyRule::new(&[], "identifier", &["("], "function_name"),
+            ReclassifyRule::new(&["function_name"], "(", &[], "call_open"),
+            ReclassifyRule::new(&["("], "identifier", &[")"], "argument"),
+        ];
+        reclassify(&mut tokens, &rules);
+        let types: Vec<&str> = tokens.iter().map(|token| token.token_type.as_str()).collect();
+        assert_eq!(types, ["function_name", "(", "argument", ")", "identifier"]);
+
+        reclassify(&mut tokens, &rules[1..2]);
+        assert_eq!(tokens[1].token_type, "call_open");
+    }
+
+    #[test]
+    fn test_tokens_in_category() {
+        let mut tokens = vec![token("integer", "1", 0), token("sum", "+", 2), token("integer", "2", 4)];
+        tokens[0].category = Some("literal".to_string());
+        tokens[1].category = Some("operator".to_string());
+        tokens[2].category = Some("literal".to_string());
+        let literals: Vec<&str> = tokens_in_category(&tokens, "literal").map(|token| token.lexeme.as_str()).collect();
+        assert_eq!(literals, ["1", "2"]);
+        assert_eq!(tokens_in_category(&tokens, "keyword").count(), 0);
+    }
+}
@@ -0,0 +1,330 @@
+//! Saving token streams to disk and loading them back, so lexing and parsing can run separately.
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, Read, Write};
+use crate::tokens::{Span, Token};
+
+/// On-disk layout of a token stream. `Json` and `JsonLines` match the CLI's `--format json` and
+/// `--format jsonl`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenFormat {
+    /// A single JSON array of tokens.
+    Json,
+    /// One JSON token object per line. Blank lines are ignored.
+    JsonLines,
+    /// The compact framing of `write_tokens_binary`.
+    Binary,
+}
+
+/// Writes `tokens` to `path` in `format`.
+pub fn save_tokens(path: &str, tokens: &[Token], format: TokenFormat) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        TokenFormat::Json => serde_json::to_writer(&mut writer, tokens)?,
+        TokenFormat::JsonLines => {
+            for token in tokens {
+                serde_json::to_writer(&mut writer, token)?;
+                writeln!(writer)?;
+            }
+        }
+        TokenFormat::Binary => write_tokens_binary(&mut writer, tokens)?,
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a token stream written by `save_tokens` or the CLI. Fields added to `Token` after the
+/// stream was written take their defaults. A malformed JSON Lines entry is reported with its line.
+pub fn load_tokens(path: &str, format: TokenFormat) -> Result<Vec<Token>, Box<dyn Error>> {
+    match format {
+        TokenFormat::Json => Ok(serde_json::from_str(&fs::read_to_string(path)?)?),
+        TokenFormat::JsonLines => {
+            let mut tokens = Vec::new();
+            for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let token = serde_json::from_str(&line)
+                    .map_err(|err| format!("{}:{}: invalid token: {}", path, index + 1, err))?;
+                tokens.push(token);
+            }
+            Ok(tokens)
+        }
+        TokenFormat::Binary => read_tokens_binary(File::open(path)?),
+    }
+}
+
+/// Start of every binary token stream: a tag and the version of the layout.
+const BINARY_MAGIC: &[u8; 4] = b"ZZT\x01";
+
+// Bits of the flags byte of a binary token frame.
+const TRIVIA: u8 = 1;
+const ACCEPT_STATE: u8 = 1 << 1;
+const SOFT_KEYWORD: u8 = 1 << 2;
+const NORMALIZED: u8 = 1 << 3;
+const CATEGORY: u8 = 1 << 4;
+const LEADING_TRIVIA: u8 = 1 << 5;
+const TRAILING_TRIVIA: u8 = 1 << 6;
+const DISPLAY: u8 = 1 << 7;
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &str) {
+    write_varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes.as_bytes());
+}
+
+/// Writes `tokens` in a compact binary framing meant for streaming between processes, e.g. a
+/// lexer piping into a parser. After a 4-byte header, every token is a frame prefixed by its
+/// length in bytes. A frame holds the type id, the lexeme, the span, line, column and file id,
+/// a byte of flags and the optional fields the flags announce. Type names are interned: a type
+/// gets the next id the first time it occurs, and only then is its name written after the id,
+/// so a token usually takes a few bytes more than its lexeme. Numbers are LEB128 varints and
+/// strings are prefixed by their length. Pass a `BufWriter` when writing to a file or pipe.
+pub fn write_tokens_binary<W: Write>(mut writer: W, tokens: &[Token]) -> Result<(), Box<dyn Error>> {
+    writer.write_all(BINARY_MAGIC)?;
+    let mut type_ids: HashMap<&str, u64> = HashMap::new();
+    let (mut frame, mut length) = (Vec::new(), Vec::new());
+    for token in tokens {
+        frame.clear();
+        let next_id = type_ids.len() as u64;
+        let id = *type_ids.entry(token.token_type.as_str()).or_insert(next_id);
+        write_varint(&mut frame, id);
+        if id == next_id {
+            write_bytes(&mut frame, &token.token_type);
+        }
+        write_bytes(&mut frame, &token.lexeme);
+        for value in [token.span.start, token.span.end, token.line, token.column] {
+            write_varint(&mut frame, value as u64);
+        }
+        write_varint(&mut frame, token.file_id.into());
+
+        let optional = [
+            (SOFT_KEYWORD, token.soft_keyword.as_deref()),
+            (NORMALIZED, token.normalized.as_deref()),
+            (CATEGORY, token.category.as_deref()),
+            (LEADING_TRIVIA, Some(token.leading_trivia.as_str()).filter(|trivia| !trivia.is_empty())),
+            (TRAILING_TRIVIA, Some(token.trailing_trivia.as_str()).filter(|trivia| !trivia.is_empty())),
+            (DISPLAY, token.display.as_deref()),
+        ];
+        let mut flags = if token.trivia { TRIVIA } else { 0 };
+        if token.accept_state.is_some() {
+            flags |= ACCEPT_STATE;
+        }
+        flags |= optional.iter().filter(|(_, value)| value.is_some()).fold(0, |flags, (flag, _)| flags | flag);
+        frame.push(flags);
+        if let Some(state) = token.accept_state {
+            write_varint(&mut frame, state as u64);
+        }
+        for value in optional.into_iter().filter_map(|(_, value)| value) {
+            write_bytes(&mut frame, value);
+        }
+
+        length.clear();
+        write_varint(&mut length, frame.len() as u64);
+        writer.write_all(&length)?;
+        writer.write_all(&frame)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// The bytes of one binary token frame being decoded.
+struct Frame<'a> {
+    bytes: &'a [u8],
+}
+
+impl Frame<'_> {
+    fn byte(&mut self) -> Result<u8, Box<dyn Error>> {
+        let (&first, rest) = self.bytes.split_first().ok_or("Truncated token frame")?;
+        self.bytes = rest;
+        Ok(first)
+    }
+
+    fn varint(&mut self) -> Result<u64, Box<dyn Error>> {
+        read_varint(|| self.byte().map(Some)).and_then(|value| value.ok_or_else(|| "Truncated token frame".into()))
+    }
+
+    fn usize(&mut self) -> Result<usize, Box<dyn Error>> {
+        Ok(usize::try_from(self.varint()?)?)
+    }
+
+    fn string(&mut self) -> Result<String, Box<dyn Error>> {
+        let length = self.usize()?;
+        if length > self.bytes.len() {
+            return Err("Truncated token frame".into());
+        }
+        let (bytes, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(String::from_utf8(bytes.to_vec())?)
+    }
+}
+
+/// Decodes a LEB128 varint from `next_byte`, or returns `None` when it has no byte to begin with.
+fn read_varint(mut next_byte: impl FnMut() -> Result<Option<u8>, Box<dyn Error>>) -> Result<Option<u64>, Box<dyn Error>> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let Some(byte) = next_byte()? else {
+            return match shift {
+                0 => Ok(None),
+                _ => Err("Truncated token frame length".into()),
+            };
+        };
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err("Varint longer than 64 bits in token stream".into())
+}
+
+/// Reads a token stream written by `write_tokens_binary`, reading no further than its end. Fails
+/// when the header is missing, a frame is cut short or does not decode, or a type id was never
+/// introduced.
+pub fn read_tokens_binary<R: Read>(reader: R) -> Result<Vec<Token>, Box<dyn Error>> {
+    let mut reader = BufReader::new(reader);
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic).map_err(|_| "Not a binary token stream")?;
+    if &magic != BINARY_MAGIC {
+        return Err("Not a binary token stream".into());
+    }
+    let mut types: Vec<String> = Vec::new();
+    let mut tokens = Vec::new();
+    let mut bytes = Vec::new();
+    let next_byte = |reader: &mut BufReader<R>| -> Result<Option<u8>, Box<dyn Error>> {
+        let mut byte = [0];
+        match reader.read_exact(&mut byte) {
+            Ok(()) => Ok(Some(byte[0])),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    };
+    while let Some(length) = read_varint(|| next_byte(&mut reader))? {
+        // The length is not trusted with an allocation: only the bytes that do arrive are kept.
+        bytes.clear();
+        let read = reader.by_ref().take(length).read_to_end(&mut bytes)?;
+        if (read as u64) < length {
+            return Err(format!("Truncated frame of token {}", tokens.len()).into());
+        }
+        let mut frame = Frame { bytes: &bytes };
+        let token = decode_token(&mut frame, &mut types).map_err(|err| format!("Token {}: {}", tokens.len(), err))?;
+        if !frame.bytes.is_empty() {
+            return Err(format!("Token {}: unexpected bytes at the end of its frame", tokens.len()).into());
+        }
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn decode_token(frame: &mut Frame, types: &mut Vec<String>) -> Result<Token, Box<dyn Error>> {
+    let id = frame.usize()?;
+    if id == types.len() {
+        types.push(frame.string()?);
+    }
+    let token_type = types.get(id).ok_or_else(|| format!("unknown type id {}", id))?.clone();
+    let lexeme = frame.string()?;
+    let span = Span::new(frame.usize()?, frame.usize()?);
+    let (line, column) = (frame.usize()?, frame.usize()?);
+    let mut token = Token::new(token_type, lexeme).with_position(span, line, column);
+    token.file_id = u32::try_from(frame.varint()?)?;
+    let flags = frame.byte()?;
+    token.trivia = flags & TRIVIA != 0;
+    if flags & ACCEPT_STATE != 0 {
+        token.accept_state = Some(frame.usize()?);
+    }
+    let mut optional = |flag: u8| match flags & flag {
+        0 => Ok(None),
+        _ => frame.string().map(Some),
+    };
+    token.soft_keyword = optional(SOFT_KEYWORD)?;
+    token.normalized = optional(NORMALIZED)?;
+    token.category = optional(CATEGORY)?;
+    token.leading_trivia = optional(LEADING_TRIVIA)?.unwrap_or_default();
+    token.trailing_trivia = optional(TRAILING_TRIVIA)?.unwrap_or_default();
+    token.display = optional(DISPLAY)?;
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokens::Span;
+
+    fn sample_tokens() -> Vec<Token> {
+        let mut first = Token::new("id".to_string(), "x".to_string()).with_position(Span::new(0, 1), 1, 1);
+        first.accept_state = Some(3);
+        let mut second = Token::new("string".to_string(), "\"a\nb\"".to_string()).with_position(Span::new(2, 7), 1, 3);
+        second.leading_trivia = " ".to_string();
+        second.file_id = 2;
+        vec![first, second]
+    }
+
+    #[test]
+    fn test_save_then_load_round_trips() {
+        let formats = [(TokenFormat::Json, "json"), (TokenFormat::JsonLines, "jsonl"), (TokenFormat::Binary, "bin")];
+        for (format, extension) in formats {
+            let path = std::env::temp_dir().join(format!("zigzin-tokens-{}.{}", std::process::id(), extension));
+            let path = path.to_str().unwrap();
+            save_tokens(path, &sample_tokens(), format).unwrap();
+            let loaded = load_tokens(path, format);
+            let wrong_format = load_tokens(path, if format == TokenFormat::Json { TokenFormat::JsonLines } else { TokenFormat::Json });
+            fs::remove_file(path).unwrap();
+            assert_eq!(loaded.unwrap(), sample_tokens());
+            assert!(wrong_format.is_err());
+        }
+    }
+
+    #[test]
+    fn test_binary_stream_round_trips_exactly() {
+        let mut tokens = sample_tokens();
+        let mut third = Token::new("id".to_string(), "ü".to_string()).with_position(Span::new(300, 302), 40, 1 << 20);
+        (third.soft_keyword, third.normalized, third.category) = (Some("u".into()), Some("u".into()), Some("name".into()));
+        third.trailing_trivia = "\n".to_string();
+        third.display = Some("you".to_string());
+        third.trivia = true;
+        tokens.push(third);
+        let mut bytes = Vec::new();
+        write_tokens_binary(&mut bytes, &tokens).unwrap();
+        let read = read_tokens_binary(bytes.as_slice()).unwrap();
+        assert_eq!(read, tokens);
+        let mut rewritten = Vec::new();
+        write_tokens_binary(&mut rewritten, &read).unwrap();
+        assert_eq!(rewritten, bytes);
+
+        // The second `x` only refers to the interned type by its id: the frame length, the id,
+        // the lexeme, four positions, the file id, the flags and the accept state take 11 bytes.
+        let mut repeated = Vec::new();
+        write_tokens_binary(&mut repeated, &[tokens[0].clone(), tokens[0].clone()]).unwrap();
+        assert_eq!(repeated.len(), BINARY_MAGIC.len() + 14 + 11);
+
+        assert!(read_tokens_binary(&bytes[..bytes.len() - 1]).is_err());
+        assert!(read_tokens_binary(&b"[]"[..]).is_err());
+        assert_eq!(read_tokens_binary(&BINARY_MAGIC[..]).unwrap(), []);
+
+        let oversized = [&BINARY_MAGIC[..], &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]].concat();
+        let err = read_tokens_binary(oversized.as_slice()).unwrap_err().to_string();
+        assert_eq!(err, "Truncated frame of token 0");
+    }
+
+    #[test]
+    fn test_load_fills_missing_fields_and_reports_bad_lines() {
+        let path = std::env::temp_dir().join(format!("zigzin-tokens-{}-old.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let line = r#"{"token_type":"id","lexeme":"x","span":{"start":0,"end":1},"line":1,"column":1,"leading_trivia":"","trailing_trivia":"","soft_keyword":null,"accept_state":null}"#;
+        fs::write(path, format!("{}\n\n{}\n{{\n", line, line)).unwrap();
+        let err = load_tokens(path, TokenFormat::JsonLines).unwrap_err().to_string();
+        fs::write(path, format!("{}\n", line)).unwrap();
+        let tokens = load_tokens(path, TokenFormat::JsonLines).unwrap();
+        fs::remove_file(path).unwrap();
+        assert!(err.starts_with(&format!("{}:4: invalid token", path)), "{}", err);
+        assert_eq!(tokens, [Token::new("id".to_string(), "x".to_string()).with_position(Span::new(0, 1), 1, 1)]);
+    }
+}
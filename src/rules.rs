@@ -0,0 +1,197 @@
+//! Generators of automata for common token shapes. Each returns a standalone `DFA` with
+//! exact-character transitions, to be merged into a lexer with `DFA::union`, except for
+//! `DFA::with_keywords`, which merges keywords into an identifier automaton itself.
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use crate::lexer::DFA;
+use crate::scanner::Transitions;
+
+/// An automaton accepting the numbers written in `radix` (2 to 36), labelled `label`. Digits past
+/// 9 are letters of either case, e.g. `DEADbeef` in base 16. With a `separator`, such as `_` in
+/// `1_000_000`, single separators may appear between two digits, but never first, last or twice
+/// in a row.
+pub fn number_rule(label: &str, radix: u32, separator: Option<char>) -> Result<DFA, Box<dyn Error>> {
+    prefixed_number_rule(label, "", radix, separator)
+}
+
+/// Like `number_rule`, for numbers that start with `prefix`, e.g. `0x` for base 16. A separator
+/// cannot follow the prefix directly.
+pub fn prefixed_number_rule(label: &str, prefix: &str, radix: u32, separator: Option<char>) -> Result<DFA, Box<dyn Error>> {
+    if !(2..=36).contains(&radix) {
+        return Err(format!("Radix {} is outside 2..=36", radix).into());
+    }
+    let digits: Vec<char> = (0..radix)
+        .flat_map(|value| {
+            let digit = char::from_digit(value, radix).unwrap();
+            [digit, digit.to_ascii_uppercase()]
+        })
+        .collect();
+    if let Some(separator) = separator.filter(|separator| digits.contains(separator)) {
+        return Err(format!("Separator '{}' is a digit in base {}", separator, radix).into());
+    }
+
+    let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+    // States 0..=prefix length spell out the prefix; then come the digit and separator states.
+    let mut state = 0;
+    for ch in prefix.chars() {
+        dfa.add_transition(state, ch, state + 1);
+        state += 1;
+    }
+    let (digit, after_separator) = (state + 1, state + 2);
+    for &ch in &digits {
+        dfa.add_transition(state, ch, digit).add_transition(digit, ch, digit);
+        if separator.is_some() {
+            dfa.add_transition(after_separator, ch, digit);
+        }
+    }
+    if let Some(separator) = separator {
+        dfa.add_transition(digit, separator, after_separator);
+    }
+    dfa.add_accept(digit, label);
+    Ok(dfa)
+}
+
+impl DFA {
+    /// Adds keyword recognition to an automaton that matches identifiers: every `(keyword, label)`
+    /// pair becomes an exact-match path from the start state, and where a keyword is also an
+    /// identifier its label wins over `identifier_label`, which stays second in `accept_labels`.
+    /// Longer identifiers such as `iffy` for the keyword `if` are unaffected, and so is every
+    /// other rule, including negated classes, categories, actions and rule ranks. Empty keywords
+    /// are ignored.
+    pub fn with_keywords(self, identifier_label: &str, keywords: &[(&str, &str)]) -> DFA {
+        let mut dfa = self;
+        let mut next_free = dfa.states().last().map_or(0, |&last| last + 1);
+        // States of keyword paths, which later keywords with the same prefix share.
+        let mut path_states = HashSet::new();
+        for &(keyword, label) in keywords.iter().filter(|(keyword, _)| !keyword.is_empty()) {
+            let mut state = dfa.start;
+            for ch in keyword.chars() {
+                if let Some(&next) = dfa.transitions.get(&(state, ch)).filter(|next| path_states.contains(*next)) {
+                    state = next;
+                    continue;
+                }
+                // A copy of the state the base automaton goes to, so only the keyword's path changes.
+                let copy = next_free;
+                next_free += 1;
+                if let Some(base) = dfa.next_state(state, ch) {
+                    dfa.copy_state(base, copy);
+                }
+                dfa.add_transition(state, ch, copy);
+                path_states.insert(copy);
+                state = copy;
+            }
+            match dfa.accept.get(&state).cloned() {
+                Some(base_label) if base_label != identifier_label => {}
+                base_label => {
+                    let mut labels = vec![label.to_string()];
+                    labels.extend(dfa.accept_labels.remove(&state).or(base_label.map(|base| vec![base])).into_iter().flatten());
+                    labels.dedup();
+                    // Categories and actions of the identifier rule do not describe the keyword.
+                    dfa.categories.remove(&state);
+                    dfa.actions.remove(&state);
+                    dfa.accept.insert(state, label.to_string());
+                    if labels.len() > 1 {
+                        dfa.accept_labels.insert(state, labels);
+                    }
+                }
+            }
+        }
+        dfa
+    }
+
+    /// Gives `copy` the transitions and accept metadata of `state`.
+    fn copy_state(&mut self, state: usize, copy: usize) {
+        let exact: Vec<(char, usize)> =
+            self.transitions.iter().filter(|(&(from, _), _)| from == state).map(|(&(_, ch), &to)| (ch, to)).collect();
+        for (ch, to) in exact {
+            self.transitions.insert((copy, ch), to);
+        }
+        if let Some(intervals) = self.intervals.get(&state).cloned() {
+            self.intervals.insert(copy, intervals);
+        }
+        if let Some(negated) = self.negated.get(&state).cloned() {
+            self.negated.insert(copy, negated);
+        }
+        for map in [&mut self.accept, &mut self.categories, &mut self.actions] {
+            if let Some(value) = map.get(&state).cloned() {
+                map.insert(copy, value);
+            }
+        }
+        if let Some(labels) = self.accept_labels.get(&state).cloned() {
+            self.accept_labels.insert(copy, labels);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::try_process_input;
+
+    #[test]
+    fn test_number_rule_decimal_with_separators() {
+        let dfa = number_rule("integer", 10, Some('_')).unwrap();
+        for number in ["0", "7", "1_000", "12_34_5"] {
+            assert!(dfa.accepts(number), "{}", number);
+        }
+        for malformed in ["", "_1", "1_", "1__0", "a", "1a"] {
+            assert!(!dfa.accepts(malformed), "{}", malformed);
+        }
+        assert!(number_rule("integer", 10, Some('5')).is_err());
+        assert!(number_rule("integer", 37, None).is_err());
+    }
+
+    #[test]
+    fn test_number_rule_hexadecimal_without_separators() {
+        let dfa = number_rule("hex", 16, None).unwrap();
+        assert!(dfa.accepts("DEADbeef") && dfa.accepts("09af"));
+        assert!(!dfa.accepts("f_f") && !dfa.accepts("g"));
+
+        let prefixed = prefixed_number_rule("hex", "0x", 16, Some('_')).unwrap();
+        assert!(prefixed.accepts("0xff_ff") && !prefixed.accepts("0x") && !prefixed.accepts("0x_ff"));
+        let lexer = number_rule("integer", 10, Some('_')).unwrap().union(&prefixed);
+        let tokens = try_process_input(&lexer, "1_000 0xFF_00 0").unwrap();
+        let found: Vec<(&str, &str)> = tokens.iter().map(|t| (t.token_type.as_str(), t.lexeme.as_str())).collect();
+        assert_eq!(found, [("integer", "1_000"), ("hex", "0xFF_00"), ("integer", "0")]);
+    }
+
+    #[test]
+    fn test_with_keywords_prefers_keywords_over_identifiers() {
+        let mut identifiers = DFA::new(HashMap::new(), 0, HashMap::new());
+        identifiers.add_interval(0, 'a', 'z', 1).unwrap().add_interval(1, 'a', 'z', 1).unwrap().add_accept(1, "identifier");
+        let dfa = DFA::with_keywords(identifiers, "identifier", &[("if", "if"), ("in", "in"), ("", "empty")]);
+        let tokens = try_process_input(&dfa, "if iffy in i f").unwrap();
+        let found: Vec<(&str, &str)> = tokens.iter().map(|t| (t.token_type.as_str(), t.lexeme.as_str())).collect();
+        assert_eq!(found, [("if", "if"), ("identifier", "iffy"), ("in", "in"), ("identifier", "i"), ("identifier", "f")]);
+        assert!(!dfa.accepts(""));
+
+        // The keyword stays the first label, so reordering by (no) ranks keeps it.
+        let ranked = dfa.with_rule_ranks(HashMap::new());
+        let types: Vec<String> = try_process_input(&ranked, "if iffy").unwrap().into_iter().map(|t| t.token_type).collect();
+        assert_eq!(types, ["if", "identifier"]);
+        assert!(ranked.accept_labels.values().all(|labels| labels[0] == "if" || labels[0] == "in"));
+    }
+
+    #[test]
+    fn test_with_keywords_keeps_negated_classes_and_metadata() {
+        let mut base = DFA::new(HashMap::new(), 0, HashMap::new());
+        base.add_interval(0, 'a', 'z', 1).unwrap().add_interval(1, 'a', 'z', 1).unwrap().add_accept(1, "id");
+        base.add_transition(0, '"', 2).add_negated(2, &['"'], 2).unwrap().add_transition(2, '"', 3).add_accept(3, "string");
+        let base = base.with_categories(HashMap::from([(3, "literal".to_string()), (1, "name".to_string())]));
+        let dfa = base.with_keywords("id", &[("if", "if"), ("in", "in")]);
+
+        let tokens = try_process_input(&dfa, "\"é\" if x \"ü\" in").unwrap();
+        let found: Vec<(&str, &str, Option<&str>)> =
+            tokens.iter().map(|t| (t.token_type.as_str(), t.lexeme.as_str(), t.category.as_deref())).collect();
+        assert_eq!(found, [
+            ("string", "\"é\"", Some("literal")),
+            ("if", "if", None),
+            ("id", "x", Some("name")),
+            ("string", "\"ü\"", Some("literal")),
+            ("in", "in", None),
+        ]);
+        // The prefix `i` is still an identifier.
+        let prefix = try_process_input(&dfa, "i").unwrap();
+        assert_eq!((prefix[0].token_type.as_str(), prefix[0].category.as_deref()), ("id", Some("name")));
+    }
+}
@@ -0,0 +1,1139 @@
+//! The core maximal-munch scanner. It only needs `alloc`, so it is available without the
+//! standard library; file and table-format I/O lives in `lexer` behind the `std` feature.
+use alloc::borrow::Cow;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use crate::cursor::{Position, SourceCursor};
+use crate::error::LexError;
+use crate::tokens::{Span, Token};
+
+/// Transition lookups the scanner needs from an automaton.
+pub trait Transitions {
+    /// The state every token match starts from.
+    fn start(&self) -> usize;
+    /// The state reached from `state` on `ch`, if there is a transition.
+    fn next_state(&self, state: usize, ch: char) -> Option<usize>;
+    /// The label of `state` when it is accepting.
+    fn accept_label(&self, state: usize) -> Option<&str>;
+    /// The category of the tokens accepted in `state`, if the automaton records one.
+    fn accept_category(&self, _state: usize) -> Option<&str> {
+        None
+    }
+    /// Every label of `state` when it stands for several original accept states, in priority
+    /// order; `accept_label` is normally the first of them.
+    fn accept_labels(&self, _state: usize) -> Option<&[String]> {
+        None
+    }
+    /// Whether tokens labelled `label` are trivia a parser may skip, e.g. comments.
+    fn is_trivia(&self, _label: &str) -> bool {
+        false
+    }
+    /// Whether `ch` belongs to the alphabet declared for the automaton. Characters outside it end
+    /// a match and are reported as `LexError::CharacterNotInAlphabet`; without a declared
+    /// alphabet every character belongs.
+    fn in_alphabet(&self, _ch: char) -> bool {
+        true
+    }
+}
+
+/// An automaton over borrowed tables, e.g. `static` arrays on targets without a file system.
+/// `transitions` must be sorted by `(from, input)` and `accept` by state id, as both are
+/// looked up with binary search.
+#[derive(Debug, Clone, Copy)]
+pub struct StaticDfa<'a> {
+    pub transitions: &'a [(usize, char, usize)],
+    pub start: usize,
+    pub accept: &'a [(usize, &'a str)],
+}
+
+impl Transitions for StaticDfa<'_> {
+    fn start(&self) -> usize {
+        self.start
+    }
+
+    fn next_state(&self, state: usize, ch: char) -> Option<usize> {
+        self.transitions
+            .binary_search_by(|&(from, input, _)| (from, input).cmp(&(state, ch)))
+            .ok()
+            .map(|index| self.transitions[index].2)
+    }
+
+    fn accept_label(&self, state: usize) -> Option<&str> {
+        self.accept
+            .binary_search_by(|&(accept_state, _)| accept_state.cmp(&state))
+            .ok()
+            .map(|index| self.accept[index].1)
+    }
+}
+
+/// Token type of the tokens emitted for line breaks with `NewlineMode::Emit` and `Collapse`.
+pub const NEWLINE_TOKEN: &str = "NEWLINE";
+
+/// Token type of the tokens covering input skipped by error recovery, see `ScanOptions::recover_errors`.
+pub const ERROR_TOKEN: &str = "ERROR";
+
+/// How the scanner treats `\n`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NewlineMode {
+    /// Line breaks are whitespace like any other.
+    #[default]
+    Skip,
+    /// Every line break becomes a `NEWLINE_TOKEN` token.
+    Emit,
+    /// A line break becomes a `NEWLINE_TOKEN` token unless the previous token already is one,
+    /// so blank lines collapse; the extra line breaks are skipped like other whitespace.
+    Collapse,
+}
+
+/// How the scanner treats U+FFFD REPLACEMENT CHARACTER, which lenient decoding puts in place of
+/// invalid bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReplacementPolicy {
+    /// Fail with `LexError::ReplacementChar`, wherever the character is outside a comment, so
+    /// corrupted input is never tokenized silently.
+    #[default]
+    Error,
+    /// Skip the character between tokens like whitespace. Inside a lexeme it is left to the automaton.
+    Whitespace,
+    /// Pass the character to the automaton like any other.
+    Char,
+}
+
+/// How the scanner treats control characters other than whitespace (tab, line feed, carriage
+/// return and the like), such as the NUL bytes of a binary file lexed by mistake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ControlCharPolicy {
+    /// Fail with `LexError::ControlCharacter` wherever the character is outside a comment.
+    #[default]
+    Error,
+    /// Skip the character between tokens like whitespace. Inside a lexeme it is left to the automaton.
+    Whitespace,
+    /// Pass the character to the automaton like any other.
+    Char,
+}
+
+/// Which token owns the whitespace and comments between two tokens when trivia is preserved.
+/// Either way every piece of trivia belongs to exactly one token, so `passes::reconstruct`
+/// rebuilds the source, and trivia after the last token is its `trailing_trivia`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TriviaPolicy {
+    /// All trivia between two tokens is the `leading_trivia` of the second one.
+    #[default]
+    Leading,
+    /// Trivia up to and including the first line break after a token is its `trailing_trivia`,
+    /// e.g. a comment closing the line; the rest leads the next token. Trivia between two tokens
+    /// on the same line trails the first one.
+    SameLine,
+}
+
+/// Which label a token gets when its accept state has several, see `Transitions::accept_labels`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum LabelPolicy {
+    /// The label `Transitions::accept_label` returns, normally the first one.
+    #[default]
+    First,
+    /// The label that appears earliest in the list; states with none of the listed labels keep
+    /// their accept label.
+    Priority(Vec<String>),
+    /// All labels joined by `|`, e.g. `keyword|identifier`.
+    All,
+}
+
+impl LabelPolicy {
+    /// The label replacing the accept label of a token accepted with the given `labels`, if any.
+    fn resolve(&self, labels: &[String]) -> Option<String> {
+        match self {
+            LabelPolicy::First => None,
+            LabelPolicy::Priority(order) => order.iter().find(|label| labels.contains(label)).cloned(),
+            LabelPolicy::All => Some(labels.join("|")),
+        }
+    }
+}
+
+/// A transform of the lexeme run before a token is handed out, like an action of a lex rule.
+pub type Action = fn(&str) -> String;
+
+/// Options of `scan_with_options`.
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    /// Store skipped whitespace and comments on the tokens instead of discarding them.
+    pub preserve_trivia: bool,
+    /// Which token preserved trivia between two tokens goes to.
+    pub trivia_policy: TriviaPolicy,
+    pub newlines: NewlineMode,
+    /// Columns between two tab stops when counting token columns; 1 counts a tab as one column.
+    pub tab_width: usize,
+    /// Opening of a comment running to the end of the line, e.g. `//`. The line break itself is
+    /// not part of the comment.
+    pub line_comment: Option<String>,
+    /// Opening and closing delimiters of block comments, e.g. `/*` and `*/`. A block comment
+    /// that is never closed runs to the end of the input.
+    pub block_comment: Option<(String, String)>,
+    /// Whether an opening delimiter inside a block comment starts a nested comment that needs its
+    /// own closing delimiter.
+    pub nested_block_comments: bool,
+    /// Reject a token that runs straight into a letter, digit or `_` when it ends with one, such
+    /// as `123abc`, instead of splitting it into `123` and `abc`. The character after the token
+    /// is reported as `LexError::UnexpectedChar`.
+    pub strict_boundaries: bool,
+    /// Instead of failing at a character no token can start with, skip ahead to where scanning
+    /// can resume and cover the skipped run with one `ERROR_TOKEN` token. The run ends before the
+    /// next whitespace character or the next character the start state has a transition on.
+    /// Adjacent runs, e.g. when matching fails again right after a resynchronization, are merged.
+    pub recover_errors: bool,
+    /// The label of tokens whose accept state has several.
+    pub label_policy: LabelPolicy,
+    pub on_replacement_char: ReplacementPolicy,
+    pub on_control_char: ControlCharPolicy,
+    /// Decides what lies between tokens: given the buffer and the current index, returns how many
+    /// characters to skip there, or `None` (or 0) when a token starts. Consulted after line
+    /// comments, block comments and emitted newlines; the default is `skip_whitespace`.
+    pub skip: Skip,
+    /// Actions run on the lexemes of the tokens of some accept states. A token whose action
+    /// leaves its lexeme empty fails with `LexError::EmptyLexeme`.
+    pub actions: BTreeMap<usize, Action>,
+}
+
+/// The callback of `ScanOptions::skip`, a shared closure so that the options stay cheap to clone
+/// and a skip can capture its configuration, e.g. a comment marker read at run time.
+#[derive(Clone)]
+pub struct Skip(Arc<SkipFn>);
+
+type SkipFn = dyn Fn(&[char], usize) -> Option<usize> + Send + Sync;
+
+impl Skip {
+    pub fn new(skip: impl Fn(&[char], usize) -> Option<usize> + Send + Sync + 'static) -> Self {
+        Skip(Arc::new(skip))
+    }
+
+    /// How many characters to skip at `index`, see `ScanOptions::skip`.
+    pub fn call(&self, chars: &[char], index: usize) -> Option<usize> {
+        (self.0)(chars, index)
+    }
+}
+
+impl Default for Skip {
+    fn default() -> Self {
+        Skip::new(skip_whitespace)
+    }
+}
+
+impl fmt::Debug for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Skip(..)")
+    }
+}
+
+/// The default `ScanOptions::skip`: one whitespace character at a time.
+pub fn skip_whitespace(chars: &[char], index: usize) -> Option<usize> {
+    chars[index].is_whitespace().then_some(1)
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            preserve_trivia: false,
+            trivia_policy: TriviaPolicy::Leading,
+            newlines: NewlineMode::Skip,
+            tab_width: 1,
+            line_comment: None,
+            block_comment: None,
+            nested_block_comments: false,
+            strict_boundaries: false,
+            recover_errors: false,
+            label_policy: LabelPolicy::First,
+            on_replacement_char: ReplacementPolicy::Error,
+            on_control_char: ControlCharPolicy::Error,
+            skip: Skip::default(),
+            actions: BTreeMap::new(),
+        }
+    }
+}
+
+impl ScanOptions {
+    /// Length in characters and deepest block-comment nesting of the comment at the start of
+    /// `rest`, if one starts there. Line comments have a depth of 0.
+    fn comment_len(&self, rest: &[char]) -> Option<(usize, usize)> {
+        let line_comment = self.line_comment.as_deref().filter(|open| !open.is_empty());
+        if line_comment.is_some_and(|open| starts_with(rest, open)) {
+            return Some((rest.iter().position(|&ch| ch == '\n').unwrap_or(rest.len()), 0));
+        }
+        let (open, close) = self
+            .block_comment
+            .as_ref()
+            .filter(|(open, close)| !open.is_empty() && !close.is_empty() && starts_with(rest, open))?;
+        let (open_len, close_len) = (open.chars().count(), close.chars().count());
+        let mut depth = 0;
+        let mut max_depth = 0;
+        let mut index = 0;
+        while index < rest.len() {
+            if (depth == 0 || self.nested_block_comments) && starts_with(&rest[index..], open) {
+                depth += 1;
+                max_depth = max_depth.max(depth);
+                index += open_len;
+            } else if starts_with(&rest[index..], close) {
+                depth -= 1;
+                index += close_len;
+                if depth == 0 {
+                    return Some((index, max_depth));
+                }
+            } else {
+                index += 1;
+            }
+        }
+        Some((rest.len(), max_depth))
+    }
+}
+
+fn starts_with(chars: &[char], prefix: &str) -> bool {
+    let mut chars = chars.iter();
+    prefix.chars().all(|expected| chars.next() == Some(&expected))
+}
+
+/// Scans the input characters with the automaton using maximal munch. When `preserve_trivia`
+/// is set, skipped whitespace is stored on the tokens instead of being discarded.
+///
+/// Maximal munch reads past the end of a token to look for a longer match, and without care
+/// the next token re-reads that lookahead: an automaton for `a` and `a+b` on `aaaa...a` takes
+/// quadratic time. To stay linear, every `(state, index)` pair visited after the last accept of
+/// a scan is remembered as failed, since no accept can be reached from it, and later scans stop
+/// as soon as they reach such a pair (Reps, "Maximal-munch tokenization in linear time", 1998).
+/// Each pair is visited at most once after its token is committed, so the whole scan takes
+/// O(n · states) transition lookups.
+pub fn scan<T: Transitions + ?Sized>(dfa: &T, input_chars: &[char], preserve_trivia: bool) -> Result<Vec<Token>, LexError> {
+    let options = ScanOptions { preserve_trivia, ..ScanOptions::default() };
+    scan_inner(dfa, input_chars, &options, None).map(|(tokens, _)| tokens)
+}
+
+/// Like `scan`, with the behaviour chosen by `options`.
+pub fn scan_with_options<T: Transitions + ?Sized>(
+    dfa: &T,
+    input_chars: &[char],
+    options: &ScanOptions,
+) -> Result<Vec<Token>, LexError> {
+    scan_inner(dfa, input_chars, options, None).map(|(tokens, _)| tokens)
+}
+
+/// Counts kept by the scanner, to check that every input character was either part of a token
+/// or skipped, and extremes of the input that help spot pathological files.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LexStats {
+    pub tokens: usize,
+    /// Characters in the lexemes of the tokens, `NEWLINE` tokens included.
+    pub chars_in_tokens: usize,
+    /// Whitespace and comment characters that belong to no token.
+    pub chars_skipped: usize,
+    /// Length in characters of the source text, type and span of the longest token; the first one
+    /// on ties. Like `chars_in_tokens`, the length is taken before any accept-state action.
+    pub longest_token: Option<(usize, String, Span)>,
+    /// Deepest block-comment nesting seen: 1 for a comment holding no nested one, 0 without block
+    /// comments.
+    pub max_comment_depth: usize,
+}
+
+impl LexStats {
+    /// Counts a token handed out by the scanner, whose source text is `len` characters long.
+    fn record_token(&mut self, token: &Token, len: usize) {
+        self.tokens += 1;
+        if self.longest_token.as_ref().is_none_or(|(longest, _, _)| len > *longest) {
+            self.longest_token = Some((len, token.token_type.clone(), token.span));
+        }
+    }
+
+    /// Characters accounted for, which equals the length of a fully scanned input.
+    pub fn chars_total(&self) -> usize {
+        self.chars_in_tokens + self.chars_skipped
+    }
+}
+
+/// Like `scan_with_options`, and also returns the scan's `LexStats`.
+pub fn scan_with_stats<T: Transitions + ?Sized>(
+    dfa: &T,
+    input_chars: &[char],
+    options: &ScanOptions,
+) -> Result<(Vec<Token>, LexStats), LexError> {
+    scan_inner(dfa, input_chars, options, None)
+}
+
+/// How many bytes are scanned between two calls of a progress callback.
+pub const PROGRESS_INTERVAL: usize = 1 << 16;
+
+/// Like `scan`, and calls `on_progress(bytes_done, bytes_total)` about every
+/// `PROGRESS_INTERVAL` bytes and once more when the scan succeeds.
+pub fn scan_with_progress<T: Transitions + ?Sized>(
+    dfa: &T,
+    input_chars: &[char],
+    preserve_trivia: bool,
+    mut on_progress: impl FnMut(usize, usize),
+) -> Result<Vec<Token>, LexError> {
+    let options = ScanOptions { preserve_trivia, ..ScanOptions::default() };
+    scan_inner(dfa, input_chars, &options, Some(&mut on_progress)).map(|(tokens, _)| tokens)
+}
+
+/// Matches the longest token starting exactly at `start` and returns it with the index just past
+/// it. Unlike `scan`, whitespace is not skipped first, so callers can apply their own whitespace
+/// policy or interleave tokenizing with parsing. The token's position is found by walking the
+/// characters before `start`, and the lookahead memo of `scan` does not carry over between
+/// calls, so tokenizing a whole input is best left to `scan`.
+///
+/// # Panics
+///
+/// Panics if `start` is not less than `input_chars.len()`.
+pub fn next_token<T: Transitions + ?Sized>(
+    dfa: &T,
+    input_chars: &[char],
+    start: usize,
+) -> Result<(Token, usize), LexError> {
+    assert!(start < input_chars.len(), "next_token called at the end of the input");
+    let mut cursor = SourceCursor::new(input_chars);
+    cursor.advance_by(start);
+    let token = Munch::default().next_token(dfa, &mut cursor)?;
+    Ok((token, cursor.index()))
+}
+
+/// The state maximal munch keeps between tokens to stay linear, see `scan`.
+#[derive(Default)]
+struct Munch {
+    /// `(state, index)` pairs from which no accept state can be reached, and their largest index.
+    failed: BTreeSet<(usize, usize)>,
+    failed_max_index: usize,
+    lookahead: Vec<(usize, usize)>,
+}
+
+impl Munch {
+    /// Matches the longest token at the cursor, which must not be at the end, and moves past it.
+    fn next_token<T: Transitions + ?Sized>(&mut self, dfa: &T, cursor: &mut SourceCursor) -> Result<Token, LexError> {
+        let i = cursor.index();
+        // Start at the DFA's start state.
+        let mut current_state = dfa.start();
+        // Track the last encountered accepting state and its index. An accepting start state
+        // matches the empty lexeme.
+        let mut last_accept_state: Option<usize> = dfa.accept_label(current_state).map(|_| current_state);
+        let mut last_accept_index = i;
+        let mut j = i;
+        self.lookahead.clear();
+        if self.failed_max_index < i {
+            // Every remembered pair lies behind the scan and can no longer be reached.
+            self.failed.clear();
+        }
+
+        while let Some(ch) = cursor.peek(j - i) {
+            if j <= self.failed_max_index && self.failed.contains(&(current_state, j)) {
+                break;
+            }
+            self.lookahead.push((current_state, j));
+            if let Some(next_state) = dfa.next_state(current_state, ch).filter(|_| dfa.in_alphabet(ch)) {
+                current_state = next_state;
+                // Record the last accepting state's index.
+                if dfa.accept_label(current_state).is_some() {
+                    last_accept_state = Some(current_state);
+                    last_accept_index = j + 1;
+                    self.lookahead.clear();
+                }
+                j += 1;
+            } else {
+                break;
+            }
+        }
+        // Nothing read after the last accept led to another one. The pair at the accept itself
+        // is skipped: later scans start there, so they can only reach it in the start state.
+        for &(state, index) in &self.lookahead {
+            if index > last_accept_index || state == dfa.start() {
+                self.failed.insert((state, index));
+                self.failed_max_index = self.failed_max_index.max(index);
+            }
+        }
+
+        let token_start = cursor.position();
+        let Some(state) = last_accept_state else {
+            return Err(no_match_error(dfa, cursor.current().unwrap(), token_start));
+        };
+        // Every token must advance the scan, otherwise the loop would never end.
+        if last_accept_index == i {
+            return Err(LexError::ZeroLengthToken {
+                state,
+                line: token_start.line,
+                column: token_start.column,
+                span: Span::new(token_start.offset, token_start.offset),
+            });
+        }
+        let lexeme: String = cursor.advance_by(last_accept_index - i).iter().collect();
+        let token_end = cursor.position().offset;
+        let token_label = String::from(dfa.accept_label(state).unwrap());
+        let mut token = Token::new(token_label, lexeme).with_position(
+            Span::new(token_start.offset, token_end),
+            token_start.line,
+            token_start.column,
+        );
+        token.accept_state = Some(state);
+        token.category = dfa.accept_category(state).map(String::from);
+        Ok(token)
+    }
+}
+
+/// The error for a character `ch` at `position` from which no token matches: its line, column and
+/// byte span, as `CharacterNotInAlphabet` when `dfa` declares an alphabet without it.
+pub(crate) fn no_match_error<T: Transitions + ?Sized>(dfa: &T, ch: char, position: Position) -> LexError {
+    let (line, column) = (position.line, position.column);
+    let span = Span::new(position.offset, position.offset + ch.len_utf8());
+    match dfa.in_alphabet(ch) {
+        true => LexError::UnexpectedChar { ch, line, column, span },
+        false => LexError::CharacterNotInAlphabet { ch, line, column, span },
+    }
+}
+
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Fails when `token`, which ends just before the cursor, and the next character are both part of
+/// a word.
+fn check_boundary(token: &Token, cursor: &SourceCursor) -> Result<(), LexError> {
+    match (token.lexeme.chars().next_back(), cursor.current()) {
+        (Some(last), Some(next)) if is_word_char(last) && is_word_char(next) => {
+            let position = cursor.position();
+            Err(LexError::UnexpectedChar {
+                ch: next,
+                line: position.line,
+                column: position.column,
+                span: Span::new(position.offset, position.offset + next.len_utf8()),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Turns a token with an empty lexeme into `LexError::EmptyLexeme`. `Munch` already refuses
+/// empty matches with `ZeroLengthToken`; this guards every other way a token can be made, such
+/// as an action emptying the lexeme.
+fn check_lexeme(token: Token, start: usize) -> Result<Token, LexError> {
+    match token {
+        token if token.lexeme.is_empty() => Err(LexError::EmptyLexeme {
+            state: token.accept_state.unwrap_or(start),
+            line: token.line,
+            column: token.column,
+            span: token.span,
+            token_type: token.token_type,
+        }),
+        token => Ok(token),
+    }
+}
+
+fn replacement_error(position: Position) -> LexError {
+    LexError::ReplacementChar {
+        line: position.line,
+        column: position.column,
+        span: Span::new(position.offset, position.offset + char::REPLACEMENT_CHARACTER.len_utf8()),
+    }
+}
+
+/// Whether `ch` is a control character `ScanOptions::on_control_char` applies to.
+fn is_stray_control(ch: char) -> bool {
+    ch.is_control() && !ch.is_whitespace()
+}
+
+fn control_error(ch: char, position: Position) -> LexError {
+    LexError::ControlCharacter {
+        code: ch as u32,
+        line: position.line,
+        column: position.column,
+        span: Span::new(position.offset, position.offset + ch.len_utf8()),
+    }
+}
+
+/// Fails at the first replacement or control character inside the lexeme of `token` whose
+/// policy in `options` is `Error`.
+fn check_lexeme_chars(token: &Token, options: &ScanOptions) -> Result<(), LexError> {
+    let replacement_fails = options.on_replacement_char == ReplacementPolicy::Error;
+    let control_fails = options.on_control_char == ControlCharPolicy::Error;
+    let mut position = Position { line: token.line, column: token.column, offset: token.span.start };
+    for ch in token.lexeme.chars() {
+        if ch == char::REPLACEMENT_CHARACTER && replacement_fails {
+            return Err(replacement_error(position));
+        }
+        if is_stray_control(ch) && control_fails {
+            return Err(control_error(ch, position));
+        }
+        position.advance_with_tab_width(ch, options.tab_width);
+    }
+    Ok(())
+}
+
+/// Scans lazily, one token per call of `next`, with the same rules as `scan_with_options`. After
+/// an error the iterator yields no more tokens. The characters may be borrowed or owned.
+pub struct Lexer<'a, T: Transitions + ?Sized> {
+    dfa: &'a T,
+    cursor: SourceCursor<'a>,
+    options: ScanOptions,
+    munch: Munch,
+    stats: LexStats,
+    /// Whitespace skipped since the last token, when trivia is preserved.
+    trivia: String,
+    /// The last token scanned, held back until it is known whether an error run merges into it and
+    /// whether it is the last token and gets the trailing trivia.
+    pending: Option<Token>,
+    /// Characters of the source text of `pending`, whose lexeme an action may have rewritten.
+    pending_chars: usize,
+    previous_is_newline: bool,
+    error: Option<LexError>,
+    finished: bool,
+}
+
+impl<'a, T: Transitions + ?Sized> Lexer<'a, T> {
+    pub fn new(dfa: &'a T, input_chars: impl Into<Cow<'a, [char]>>, options: ScanOptions) -> Self {
+        let cursor = SourceCursor::new(input_chars).with_tab_width(options.tab_width);
+        Lexer {
+            dfa,
+            cursor,
+            options,
+            munch: Munch::default(),
+            stats: LexStats::default(),
+            trivia: String::new(),
+            pending: None,
+            pending_chars: 0,
+            previous_is_newline: false,
+            error: None,
+            finished: false,
+        }
+    }
+
+    /// Continues an earlier scan: the characters are what follows `position` in the input, and
+    /// token spans, lines and columns are counted from it. Call before the first `next`.
+    pub fn starting_at(mut self, position: Position) -> Self {
+        self.cursor = self.cursor.starting_at(position);
+        self
+    }
+
+    /// Counts over the tokens yielded so far.
+    pub fn stats(&self) -> LexStats {
+        self.stats.clone()
+    }
+
+    /// Scans up to the end of the next token, or returns `None` at the end of the input. Unlike
+    /// `next`, error runs are not merged and the trailing trivia is left in `trivia`.
+    fn scan_token(&mut self) -> Result<Option<Token>, LexError> {
+        let (dfa, options) = (self.dfa, &self.options);
+        let cursor = &mut self.cursor;
+        while let Some(current) = cursor.current() {
+            if current == '\n' && options.newlines != NewlineMode::Skip {
+                let collapsed = options.newlines == NewlineMode::Collapse && self.previous_is_newline;
+                if !collapsed {
+                    let start = cursor.position();
+                    cursor.advance();
+                    let mut token = Token::new(String::from(NEWLINE_TOKEN), String::from("\n"))
+                        .with_position(Span::new(start.offset, cursor.position().offset), start.line, start.column);
+                    token.trivia = dfa.is_trivia(NEWLINE_TOKEN);
+                    return Ok(Some(token));
+                }
+            }
+            if let Some((len, depth)) = options.comment_len(&cursor.chars()[cursor.index()..]) {
+                let comment = cursor.advance_by(len);
+                self.stats.chars_skipped += comment.len();
+                self.stats.max_comment_depth = self.stats.max_comment_depth.max(depth);
+                if options.preserve_trivia {
+                    self.trivia.extend(comment);
+                }
+                continue;
+            }
+            let replacement = current == char::REPLACEMENT_CHARACTER;
+            if replacement && options.on_replacement_char == ReplacementPolicy::Error {
+                return Err(replacement_error(cursor.position()));
+            }
+            let control = is_stray_control(current);
+            if control && options.on_control_char == ControlCharPolicy::Error {
+                return Err(control_error(current, cursor.position()));
+            }
+            let skip = match options.skip.call(cursor.chars(), cursor.index()) {
+                Some(len) if len > 0 => len,
+                _ if replacement && options.on_replacement_char == ReplacementPolicy::Whitespace => 1,
+                _ if control && options.on_control_char == ControlCharPolicy::Whitespace => 1,
+                _ => 0,
+            };
+            if skip > 0 {
+                let skipped = cursor.advance_by(skip);
+                self.stats.chars_skipped += skipped.len();
+                if options.preserve_trivia {
+                    self.trivia.extend(skipped);
+                }
+                continue;
+            }
+
+            return match self.munch.next_token(dfa, cursor) {
+                Ok(mut token) => {
+                    let labels = token.accept_state.and_then(|state| dfa.accept_labels(state));
+                    if let Some(label) = labels.and_then(|labels| options.label_policy.resolve(labels)) {
+                        token.token_type = label;
+                    }
+                    token.trivia = dfa.is_trivia(&token.token_type);
+                    if options.strict_boundaries {
+                        check_boundary(&token, cursor)?;
+                    }
+                    check_lexeme_chars(&token, options)?;
+                    Ok(Some(token))
+                }
+                Err(
+                    LexError::UnexpectedChar { .. }
+                    | LexError::CharacterNotInAlphabet { .. }
+                    | LexError::ZeroLengthToken { .. },
+                ) if options.recover_errors => {
+                    let start = cursor.position();
+                    let mut run = String::new();
+                    run.extend(cursor.advance());
+                    let resumes = |ch: char| {
+                        ch.is_whitespace() || (dfa.in_alphabet(ch) && dfa.next_state(dfa.start(), ch).is_some())
+                    };
+                    while let Some(ch) = cursor.current().filter(|&ch| !resumes(ch)) {
+                        run.push(ch);
+                        cursor.advance();
+                    }
+                    let span = Span::new(start.offset, cursor.position().offset);
+                    Ok(Some(Token::new(String::from(ERROR_TOKEN), run).with_position(span, start.line, start.column)))
+                }
+                Err(err) => Err(err),
+            };
+        }
+        Ok(None)
+    }
+}
+
+impl<T: Transitions + ?Sized> Iterator for Lexer<'_, T> {
+    type Item = Result<Token, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished && self.error.is_none() {
+            let mut token = match self.scan_token() {
+                Ok(Some(token)) => token,
+                Ok(None) => {
+                    self.finished = true;
+                    break;
+                }
+                Err(err) => {
+                    self.error = Some(err);
+                    break;
+                }
+            };
+            // Counted before the action, so the counts still cover the source text.
+            let source_chars = token.lexeme.chars().count();
+            self.stats.chars_in_tokens += source_chars;
+            if let Some(action) = token.accept_state.and_then(|state| self.options.actions.get(&state)) {
+                token.lexeme = action(&token.lexeme);
+            }
+            let mut token = match check_lexeme(token, self.dfa.start()) {
+                Ok(token) => token,
+                Err(err) => {
+                    self.error = Some(err);
+                    break;
+                }
+            };
+            self.previous_is_newline = token.token_type == NEWLINE_TOKEN;
+            if let Some(last) = self.pending.as_mut() {
+                if token.token_type == ERROR_TOKEN && last.token_type == ERROR_TOKEN && last.span.end == token.span.start {
+                    last.lexeme.push_str(&token.lexeme);
+                    last.span = last.span.merge(token.span);
+                    self.pending_chars += source_chars;
+                    continue;
+                }
+            }
+            let mut trivia = core::mem::take(&mut self.trivia);
+            if let (TriviaPolicy::SameLine, Some(previous)) = (self.options.trivia_policy, self.pending.as_mut()) {
+                let line_end = trivia.find('\n').map_or(trivia.len(), |index| index + 1);
+                previous.trailing_trivia = trivia.drain(..line_end).collect();
+            }
+            token.leading_trivia = trivia;
+            let previous_chars = core::mem::replace(&mut self.pending_chars, source_chars);
+            if let Some(previous) = self.pending.replace(token) {
+                self.stats.record_token(&previous, previous_chars);
+                return Some(Ok(previous));
+            }
+        }
+        if let Some(mut last) = self.pending.take() {
+            if self.finished {
+                last.trailing_trivia = core::mem::take(&mut self.trivia);
+            }
+            self.stats.record_token(&last, self.pending_chars);
+            return Some(Ok(last));
+        }
+        self.finished = true;
+        self.error.take().map(Err)
+    }
+}
+
+fn scan_inner<T: Transitions + ?Sized>(
+    dfa: &T,
+    input_chars: &[char],
+    options: &ScanOptions,
+    mut on_progress: Option<&mut dyn FnMut(usize, usize)>,
+) -> Result<(Vec<Token>, LexStats), LexError> {
+    let total_bytes: usize = match on_progress {
+        Some(_) => input_chars.iter().map(|ch| ch.len_utf8()).sum(),
+        None => 0,
+    };
+    let mut next_report = PROGRESS_INTERVAL;
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut lexer = Lexer::new(dfa, input_chars, options.clone());
+    for token in &mut lexer {
+        let token = token?;
+        let token_end = token.span.end;
+        tokens.push(token);
+        if let Some(report) = on_progress.as_mut() {
+            if token_end >= next_report {
+                report(token_end, total_bytes);
+                next_report = token_end + PROGRESS_INTERVAL;
+            }
+        }
+    }
+    if let Some(report) = on_progress {
+        report(total_bytes, total_bytes);
+    }
+    Ok((tokens, lexer.stats()))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+    use super::*;
+
+    const TRANSITIONS: [(usize, char, usize); 4] = [(0, '1', 1), (0, 'a', 2), (1, '1', 1), (2, 'a', 2)];
+    const ACCEPT: [(usize, &str); 2] = [(1, "integer"), (2, "id")];
+
+    #[test]
+    fn test_scan_with_static_tables() {
+        let dfa = StaticDfa { transitions: &TRANSITIONS, start: 0, accept: &ACCEPT };
+        let input: Vec<char> = "11 aa1".chars().collect();
+        let tokens = scan(&dfa, &input, false).unwrap();
+        let labels: Vec<(&str, &str)> = tokens
+            .iter()
+            .map(|token| (token.token_type.as_str(), token.lexeme.as_str()))
+            .collect();
+        assert_eq!(labels, [("integer", "11"), ("id", "aa"), ("integer", "1")]);
+        assert_eq!(tokens[2].span, Span::new(5, 6));
+    }
+
+    #[test]
+    fn test_scan_reports_unexpected_char() {
+        let dfa = StaticDfa { transitions: &TRANSITIONS, start: 0, accept: &ACCEPT };
+        let input: Vec<char> = "a?".chars().collect();
+        let err = scan(&dfa, &input, false).unwrap_err();
+        assert_eq!(err.to_string(), "ZigZin compiler: Lexer error at line 1, column 2: Unexpected token '?'");
+    }
+
+    #[test]
+    fn test_scan_rejects_zero_length_token() {
+        // The start state accepts, so `b`, which has no transition, matches the empty lexeme.
+        let transitions = [(0, 'a', 1)];
+        let accept = [(0, "empty"), (1, "a")];
+        let dfa = StaticDfa { transitions: &transitions, start: 0, accept: &accept };
+        let input: Vec<char> = "a b".chars().collect();
+        let err = scan(&dfa, &input, false).unwrap_err();
+        assert_eq!(err, LexError::ZeroLengthToken { state: 0, line: 1, column: 3, span: Span::new(2, 2) });
+
+        let input: Vec<char> = "a a".chars().collect();
+        assert_eq!(scan(&dfa, &input, false).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_check_lexeme_rejects_empty_tokens() {
+        // `""` is a string literal; an action stripping its quotes leaves the lexeme empty.
+        let dfa = StaticDfa { transitions: &[(0, '"', 1), (1, '"', 2), (1, 'x', 1)], start: 0, accept: &[(2, "string")] };
+        let strip: Action = |lexeme| lexeme.trim_matches('"').into();
+        let options = ScanOptions { actions: BTreeMap::from([(2, strip)]), ..ScanOptions::default() };
+        let input: Vec<char> = "\"x\"\n  \"\"".chars().collect();
+        let err = scan_with_options(&dfa, &input, &options).unwrap_err();
+        assert_eq!(err, LexError::EmptyLexeme { token_type: "string".to_string(), state: 2, line: 2, column: 3, span: Span::new(6, 8) });
+        assert_eq!(err.message(), "The 'string' token accepted in state 2 has an empty lexeme");
+        let input: Vec<char> = "\"x\" \"xx\"".chars().collect();
+        let lexemes: Vec<String> = scan_with_options(&dfa, &input, &options).unwrap().into_iter().map(|token| token.lexeme).collect();
+        assert_eq!(lexemes, ["x", "xx"]);
+
+        // The stats measure the source text, not the stripped lexemes.
+        let (_, stats) = scan_with_stats(&dfa, &input, &options).unwrap();
+        assert_eq!(stats.chars_in_tokens, 7);
+        assert_eq!(stats.longest_token, Some((4, "string".to_string(), Span::new(4, 8))));
+    }
+
+    #[test]
+    fn test_next_token_matches_one_token() {
+        let dfa = StaticDfa { transitions: &TRANSITIONS, start: 0, accept: &ACCEPT };
+        let input: Vec<char> = "11\naa 1".chars().collect();
+        let (token, next) = next_token(&dfa, &input, 3).unwrap();
+        assert_eq!((token.token_type.as_str(), token.lexeme.as_str(), next), ("id", "aa", 5));
+        assert_eq!((token.line, token.column, token.span), (2, 1, Span::new(3, 5)));
+        assert_eq!(next_token(&dfa, &input, 0).unwrap().1, 2);
+
+        // Whitespace is left to the caller.
+        let err = next_token(&dfa, &input, 5).unwrap_err();
+        assert_eq!(err, LexError::UnexpectedChar { ch: ' ', line: 2, column: 3, span: Span::new(5, 6) });
+    }
+
+    #[test]
+    fn test_scan_skips_comments() {
+        let dfa = StaticDfa { transitions: &TRANSITIONS, start: 0, accept: &ACCEPT };
+        let input: Vec<char> = "a // 11\n1 /* a /* 1 */ aa */ 11 /* open".chars().collect();
+        let options = ScanOptions {
+            preserve_trivia: true,
+            line_comment: Some("//".to_string()),
+            block_comment: Some(("/*".to_string(), "*/".to_string())),
+            ..ScanOptions::default()
+        };
+        // Without nesting the first `*/` ends the comment, and the second is no token.
+        let err = scan_with_options(&dfa, &input, &options).unwrap_err();
+        assert_eq!(err.character(), Some('*'));
+        let nested = ScanOptions { nested_block_comments: true, ..options.clone() };
+        let tokens = scan_with_options(&dfa, &input, &nested).unwrap();
+        let lexemes: Vec<&str> = tokens.iter().map(|token| token.lexeme.as_str()).collect();
+        assert_eq!(lexemes, ["a", "1", "11"]);
+        assert_eq!(tokens[1].leading_trivia, " // 11\n");
+        assert_eq!(tokens[2].trailing_trivia, " /* open");
+    }
+
+    #[test]
+    fn test_trivia_policy_attributes_comments_to_one_token() {
+        let dfa = StaticDfa { transitions: &TRANSITIONS, start: 0, accept: &ACCEPT };
+        let input: Vec<char> = "a /* x */ 1 // end\n  /* next */ aa\n".chars().collect();
+        let options = ScanOptions {
+            preserve_trivia: true,
+            line_comment: Some("//".to_string()),
+            block_comment: Some(("/*".to_string(), "*/".to_string())),
+            ..ScanOptions::default()
+        };
+        let trivia = |policy| -> Vec<(String, String)> {
+            let options = ScanOptions { trivia_policy: policy, ..options.clone() };
+            let tokens = scan_with_options(&dfa, &input, &options).unwrap();
+            let rebuilt: String = tokens.iter().flat_map(|t| [&t.leading_trivia, &t.lexeme, &t.trailing_trivia]).map(String::as_str).collect();
+            assert_eq!(rebuilt, input.iter().collect::<String>());
+            tokens.into_iter().map(|t| (t.leading_trivia, t.trailing_trivia)).collect()
+        };
+        let pair = |leading: &str, trailing: &str| (leading.to_string(), trailing.to_string());
+        assert_eq!(trivia(TriviaPolicy::Leading), [pair("", ""), pair(" /* x */ ", ""), pair(" // end\n  /* next */ ", "\n")]);
+        assert_eq!(trivia(TriviaPolicy::SameLine), [pair("", " /* x */ "), pair("", " // end\n"), pair("  /* next */ ", "\n")]);
+    }
+
+    #[test]
+    fn test_strict_boundaries_reject_run_together_tokens() {
+        let dfa = StaticDfa { transitions: &TRANSITIONS, start: 0, accept: &ACCEPT };
+        let input: Vec<char> = "111aa".chars().collect();
+        let lexemes: Vec<String> = scan(&dfa, &input, false).unwrap().into_iter().map(|token| token.lexeme).collect();
+        assert_eq!(lexemes, ["111", "aa"]);
+
+        let options = ScanOptions { strict_boundaries: true, ..ScanOptions::default() };
+        let err = scan_with_options(&dfa, &input, &options).unwrap_err();
+        assert_eq!(err, LexError::UnexpectedChar { ch: 'a', line: 1, column: 4, span: Span::new(3, 4) });
+        let input: Vec<char> = "111 aa".chars().collect();
+        assert_eq!(scan_with_options(&dfa, &input, &options).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_scan_stats_account_for_every_character() {
+        let dfa = StaticDfa { transitions: &TRANSITIONS, start: 0, accept: &ACCEPT };
+        let input: Vec<char> = " aa\t11 # é\n a \n".chars().collect();
+        let options = ScanOptions {
+            newlines: NewlineMode::Emit,
+            line_comment: Some("#".to_string()),
+            ..ScanOptions::default()
+        };
+        let (tokens, stats) = scan_with_stats(&dfa, &input, &options).unwrap();
+        assert_eq!((stats.tokens, stats.chars_in_tokens, stats.chars_skipped), (5, 7, 8));
+        assert_eq!(stats.longest_token, Some((2, "id".to_string(), Span::new(1, 3))));
+        assert_eq!(stats.max_comment_depth, 0);
+        assert_eq!(stats.chars_total(), input.len());
+        assert_eq!(tokens, scan_with_options(&dfa, &input, &options).unwrap());
+    }
+
+    #[test]
+    fn test_recover_errors_resynchronizes_at_token_start() {
+        let dfa = StaticDfa { transitions: &TRANSITIONS, start: 0, accept: &ACCEPT };
+        let input: Vec<char> = "aa 11 ?%a1#! 1 ?".chars().collect();
+        assert!(scan(&dfa, &input, false).is_err());
+
+        let options = ScanOptions { recover_errors: true, preserve_trivia: true, ..ScanOptions::default() };
+        let (tokens, stats) = scan_with_stats(&dfa, &input, &options).unwrap();
+        let labels: Vec<(&str, &str)> = tokens.iter().map(|t| (t.token_type.as_str(), t.lexeme.as_str())).collect();
+        assert_eq!(labels, [
+            ("id", "aa"),
+            ("integer", "11"),
+            ("ERROR", "?%"),
+            ("id", "a"),
+            ("integer", "1"),
+            ("ERROR", "#!"),
+            ("integer", "1"),
+            ("ERROR", "?"),
+        ]);
+        assert_eq!((tokens[2].span, tokens[2].column), (Span::new(6, 8), 7));
+        assert_eq!(stats.chars_total(), input.len());
+        let rebuilt: String =
+            tokens.iter().flat_map(|t| [&t.leading_trivia, &t.lexeme, &t.trailing_trivia]).map(String::as_str).collect();
+        assert_eq!(rebuilt, input.iter().collect::<String>());
+    }
+
+    #[test]
+    fn test_recover_errors_merges_adjacent_runs() {
+        // `b` can start a token but never completes one, so matching fails again right after the resync.
+        let transitions = [(0, 'a', 1), (0, 'b', 2)];
+        let accept = [(1, "a")];
+        let dfa = StaticDfa { transitions: &transitions, start: 0, accept: &accept };
+        let input: Vec<char> = "a?b?b a".chars().collect();
+        let options = ScanOptions { recover_errors: true, ..ScanOptions::default() };
+        let lexemes: Vec<String> = scan_with_options(&dfa, &input, &options).unwrap().into_iter().map(|t| t.lexeme).collect();
+        assert_eq!(lexemes, ["a", "?b?b", "a"]);
+    }
+
+    #[test]
+    fn test_lexer_iterator_matches_scan() {
+        let dfa = StaticDfa { transitions: &TRANSITIONS, start: 0, accept: &ACCEPT };
+        let input: Vec<char> = " aa\n11  a ".chars().collect();
+        let options = ScanOptions { preserve_trivia: true, ..ScanOptions::default() };
+        let mut lexer = Lexer::new(&dfa, input.as_slice(), options.clone());
+        let tokens: Vec<Token> = lexer.by_ref().map(Result::unwrap).collect();
+        assert_eq!(tokens, scan_with_options(&dfa, &input, &options).unwrap());
+        assert_eq!(lexer.stats().chars_total(), input.len());
+
+        // The tokens before an error are yielded first, then the error, then nothing.
+        let owned: Vec<char> = "aa 1?1".chars().collect();
+        let mut lexer = Lexer::new(&dfa, owned, ScanOptions::default());
+        assert_eq!(lexer.next().unwrap().unwrap().lexeme, "aa");
+        assert_eq!(lexer.next().unwrap().unwrap().lexeme, "1");
+        assert_eq!(lexer.next().unwrap().unwrap_err().character(), Some('?'));
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn test_scan_counts_columns_with_tab_stops() {
+        let dfa = StaticDfa { transitions: &TRANSITIONS, start: 0, accept: &ACCEPT };
+        let input: Vec<char> = "\ta 1\t\t11\n\ta".chars().collect();
+        let options = ScanOptions { tab_width: 4, ..ScanOptions::default() };
+        let columns: Vec<usize> = scan_with_options(&dfa, &input, &options).unwrap().iter().map(|t| t.column).collect();
+        assert_eq!(columns, [5, 7, 13, 5]);
+        let columns: Vec<usize> = scan(&dfa, &input, false).unwrap().iter().map(|t| t.column).collect();
+        assert_eq!(columns, [2, 4, 7, 2]);
+    }
+
+    /// Counts lookups so tests can bound the work done by the scanner.
+    struct Counting<'a> {
+        dfa: StaticDfa<'a>,
+        lookups: core::cell::Cell<usize>,
+    }
+
+    impl Transitions for Counting<'_> {
+        fn start(&self) -> usize {
+            self.dfa.start()
+        }
+
+        fn next_state(&self, state: usize, ch: char) -> Option<usize> {
+            self.lookups.set(self.lookups.get() + 1);
+            self.dfa.next_state(state, ch)
+        }
+
+        fn accept_label(&self, state: usize) -> Option<&str> {
+            self.dfa.accept_label(state)
+        }
+    }
+
+    #[test]
+    fn test_scan_emits_newline_tokens() {
+        let dfa = StaticDfa { transitions: &TRANSITIONS, start: 0, accept: &ACCEPT };
+        let input: Vec<char> = "a\n\n  \n11 \na".chars().collect();
+        let types = |newlines| -> Vec<String> {
+            let options = ScanOptions { newlines, ..ScanOptions::default() };
+            scan_with_options(&dfa, &input, &options).unwrap().into_iter().map(|token| token.token_type).collect()
+        };
+        assert_eq!(types(NewlineMode::Skip), ["id", "integer", "id"]);
+        assert_eq!(types(NewlineMode::Emit), ["id", "NEWLINE", "NEWLINE", "NEWLINE", "integer", "NEWLINE", "id"]);
+        assert_eq!(types(NewlineMode::Collapse), ["id", "NEWLINE", "integer", "NEWLINE", "id"]);
+
+        let options = ScanOptions { preserve_trivia: true, newlines: NewlineMode::Collapse, ..ScanOptions::default() };
+        let tokens = scan_with_options(&dfa, &input, &options).unwrap();
+        assert_eq!(tokens[1].span, Span::new(1, 2));
+        assert_eq!((tokens[2].line, tokens[2].leading_trivia.as_str()), (4, "\n  \n"));
+    }
+
+    #[test]
+    fn test_scan_reports_progress() {
+        let dfa = StaticDfa { transitions: &TRANSITIONS, start: 0, accept: &ACCEPT };
+        let input: Vec<char> = "aaa 111 ".repeat(PROGRESS_INTERVAL / 2).chars().collect();
+        let mut reports = Vec::new();
+        let tokens = scan_with_progress(&dfa, &input, false, |done, total| reports.push((done, total))).unwrap();
+        assert_eq!(tokens, scan(&dfa, &input, false).unwrap());
+
+        let total = input.len();
+        assert_eq!(reports.len(), 4);
+        assert!(reports.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert!(reports.iter().all(|&(_, reported_total)| reported_total == total));
+        assert_eq!(reports.last(), Some(&(total, total)));
+    }
+
+    #[test]
+    fn test_scan_lookahead_is_linear() {
+        // Tokens `a` and `a+b`: on a run of `a`s every token would otherwise re-read the rest of the run.
+        let transitions = [(0, 'a', 1), (1, 'a', 2), (1, 'b', 3), (2, 'a', 2), (2, 'b', 3)];
+        let accept = [(1, "a"), (3, "ab")];
+        let dfa = Counting {
+            dfa: StaticDfa { transitions: &transitions, start: 0, accept: &accept },
+            lookups: core::cell::Cell::new(0),
+        };
+        let n = 5000;
+        let input: Vec<char> = core::iter::repeat_n('a', n).collect();
+        let tokens = scan(&dfa, &input, false).unwrap();
+        assert_eq!(tokens.len(), n);
+        assert!(dfa.lookups.get() <= 3 * n, "{} lookups for {} characters", dfa.lookups.get(), n);
+
+        let input: Vec<char> = "aaab a".chars().collect();
+        let lexemes: Vec<String> = scan(&dfa, &input, false).unwrap().into_iter().map(|token| token.lexeme).collect();
+        assert_eq!(lexemes, ["aaab", "a"]);
+    }
+
+    /// Small xorshift generator so the randomized test needs no dependencies and is reproducible.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, bound: u64) -> usize {
+            (self.next() % bound) as usize
+        }
+    }
+
+    #[test]
+    fn test_scan_random_automata_and_inputs() {
+        let alphabet = ['a', 'b', 'c', ' ', '\n', 'é'];
+        let mut rng = XorShift(0x2545_F491_4F6C_DD1D);
+        for _ in 0..2000 {
+            let mut transitions: Vec<(usize, char, usize)> = (0..rng.below(12))
+                .map(|_| (rng.below(5), alphabet[rng.below(3)], rng.below(5)))
+                .collect();
+            transitions.sort_unstable_by_key(|&(from, ch, _)| (from, ch));
+            transitions.dedup_by_key(|&mut (from, ch, _)| (from, ch));
+            let accept: Vec<(usize, &str)> = (0..5).filter(|_| rng.below(2) == 0).map(|state| (state, "t")).collect();
+            let dfa = StaticDfa { transitions: &transitions, start: 0, accept: &accept };
+            let input: Vec<char> = (0..rng.below(20)).map(|_| alphabet[rng.below(6)]).collect();
+            let text: String = input.iter().collect();
+
+            match scan(&dfa, &input, true) {
+                Ok(tokens) => {
+                    let mut rebuilt = String::new();
+                    for token in &tokens {
+                        assert!(!token.lexeme.is_empty());
+                        assert_eq!(&text[token.span.start..token.span.end], token.lexeme);
+                        rebuilt.push_str(&token.leading_trivia);
+                        rebuilt.push_str(&token.lexeme);
+                        rebuilt.push_str(&token.trailing_trivia);
+                    }
+                    if !tokens.is_empty() {
+                        assert_eq!(rebuilt, text);
+                    }
+                }
+                Err(err) => {
+                    let span = err.span();
+                    assert!(span.start <= span.end && span.end <= text.len());
+                }
+            }
+        }
+    }
+}
@@ -1,11 +1,211 @@
-#[derive(Debug, Clone, PartialEq, Eq)]
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+
+/// A scanned lexeme. Tokens are ordered by type, then lexeme, then position, which groups
+/// occurrences of the same lexeme together, e.g. for frequency reports; sort by `span` to get
+/// source order back.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Token {
     pub token_type: String,
     pub lexeme: String,
+    /// Byte range of the lexeme in the source.
+    pub span: Span,
+    /// Line (1-based) where the lexeme starts.
+    pub line: usize,
+    /// Column (1-based) where the lexeme starts.
+    pub column: usize,
+    /// Whitespace skipped between the previous token and this one, kept only when trivia is
+    /// preserved; `ScanOptions::trivia_policy` may give part of it to the previous token instead.
+    pub leading_trivia: String,
+    /// Whitespace after the last token of the input, kept only when trivia is preserved, or with
+    /// `TriviaPolicy::SameLine` the rest of the token's line.
+    pub trailing_trivia: String,
+    /// Contextual keyword this lexeme may stand for; the token type is left untouched so the
+    /// parser can decide from context.
+    pub soft_keyword: Option<String>,
+    /// DFA accept state that matched the lexeme; several states may share one label.
+    pub accept_state: Option<usize>,
+    /// Index of the source file in a caller-maintained table such as `batch::FileTable`;
+    /// 0 for tokens of a single input.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub file_id: u32,
+    /// Normalized form of `lexeme` for comparisons (e.g. lowercased), set by
+    /// `passes::normalize_lexemes`; `lexeme` keeps the source text unless an accept-state action
+    /// (see `DFA::actions`) rewrote it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub normalized: Option<String>,
+    /// Display form of `lexeme` when the logical value differs from the source text (e.g. a
+    /// string unescaped with `passes::unescape_string`), set by `passes::display_lexemes`. Read
+    /// it with `display_lexeme()`, which falls back to `lexeme`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub display: Option<String>,
+    /// Broad category of the token type, e.g. "operator", when the automaton defines one.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub category: Option<String>,
+    /// Whether the automaton marks the token type as trivia (whitespace, comments) that a
+    /// parser may skip; see `passes::skip_trivia`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub trivia: bool,
 }
 
 impl Token {
     pub fn new(token_type: String, lexeme: String) -> Self {
-        Token { token_type, lexeme }
+        Token {
+            token_type,
+            lexeme,
+            span: Span::default(),
+            line: 0,
+            column: 0,
+            leading_trivia: String::new(),
+            trailing_trivia: String::new(),
+            soft_keyword: None,
+            accept_state: None,
+            file_id: 0,
+            normalized: None,
+            display: None,
+            category: None,
+            trivia: false,
+        }
+    }
+
+    /// The normalized lexeme when there is one, else the lexeme itself; compare this for
+    /// case-insensitive or Unicode-equivalent matching.
+    pub fn normalized_lexeme(&self) -> &str {
+        self.normalized.as_deref().unwrap_or(&self.lexeme)
+    }
+
+    /// The display form when there is one, else the lexeme itself; the cooked value of e.g. an
+    /// escaped string literal.
+    pub fn display_lexeme(&self) -> &str {
+        self.display.as_deref().unwrap_or(&self.lexeme)
+    }
+
+    pub fn is_trivia(&self) -> bool {
+        self.trivia
+    }
+
+    /// The token as named text fields for templates: `type`, `lexeme`, `line`, `column`, `start`
+    /// and `end` (the byte span) and `file_id` are always present; `category`, `normalized`, `display`,
+    /// `soft_keyword` and `accept_state` only when set. Trivia is left out.
+    pub fn as_fields(&self) -> BTreeMap<&'static str, String> {
+        let mut fields = BTreeMap::from([
+            ("type", self.token_type.clone()),
+            ("lexeme", self.lexeme.clone()),
+            ("line", self.line.to_string()),
+            ("column", self.column.to_string()),
+            ("start", self.span.start.to_string()),
+            ("end", self.span.end.to_string()),
+            ("file_id", self.file_id.to_string()),
+        ]);
+        let optional = [
+            ("category", self.category.clone()),
+            ("normalized", self.normalized.clone()),
+            ("display", self.display.clone()),
+            ("soft_keyword", self.soft_keyword.clone()),
+            ("accept_state", self.accept_state.map(|state| state.to_string())),
+        ];
+        fields.extend(optional.into_iter().filter_map(|(name, value)| Some((name, value?))));
+        fields
+    }
+
+    /// Attaches the source position of the lexeme to the token.
+    pub fn with_position(mut self, span: Span, line: usize, column: usize) -> Self {
+        self.span = span;
+        self.line = line;
+        self.column = column;
+        self
+    }
+}
+
+/// A half-open range of byte offsets `[start, end)` into the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// The smallest span enclosing both spans, e.g. from the first to the last token of an
+    /// expression. Any gap between them is included.
+    pub fn merge(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
     }
-}
\ No newline at end of file
+
+    /// Whether the byte at `offset` lies in the span; `end` is excluded.
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+
+    /// Length in bytes.
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use alloc::vec;
+    use alloc::collections::BTreeSet;
+    use super::*;
+
+    fn token(token_type: &str, lexeme: &str, start: usize) -> Token {
+        Token::new(token_type.to_string(), lexeme.to_string()).with_position(Span::new(start, start + lexeme.len()), 1, start + 1)
+    }
+
+    #[test]
+    fn test_tokens_sort_by_type_then_lexeme() {
+        let mut tokens = vec![token("keyword", "a", 0), token("id", "b", 2), token("id", "a", 4), token("id", "b", 2)];
+        tokens.sort();
+        let order: Vec<(&str, &str, usize)> = tokens.iter().map(|t| (t.token_type.as_str(), t.lexeme.as_str(), t.span.start)).collect();
+        assert_eq!(order, [("id", "a", 4), ("id", "b", 2), ("id", "b", 2), ("keyword", "a", 0)]);
+        tokens.dedup();
+        assert_eq!(tokens.len(), 3);
+        let unique: BTreeSet<Token> = tokens.into_iter().collect();
+        assert_eq!(unique.len(), 3);
+    }
+
+    #[test]
+    fn test_as_fields_of_positioned_token() {
+        let mut token = token("id", "total", 12);
+        token.line = 3;
+        token.accept_state = Some(4);
+        let fields = token.as_fields();
+        let expected = [
+            ("accept_state", "4"),
+            ("column", "13"),
+            ("end", "17"),
+            ("file_id", "0"),
+            ("lexeme", "total"),
+            ("line", "3"),
+            ("start", "12"),
+            ("type", "id"),
+        ];
+        assert_eq!(fields, expected.map(|(name, value)| (name, value.to_string())).into_iter().collect());
+        token.category = Some("name".to_string());
+        assert_eq!(token.as_fields()["category"], "name");
+    }
+
+    #[test]
+    fn test_span_merge_adjacent_and_overlapping() {
+        assert_eq!(Span::new(0, 3).merge(Span::new(3, 5)), Span::new(0, 5));
+        assert_eq!(Span::new(2, 8).merge(Span::new(4, 6)), Span::new(2, 8));
+        assert_eq!(Span::new(4, 9).merge(Span::new(1, 6)), Span::new(1, 9));
+        assert_eq!(Span::new(6, 7).merge(Span::new(0, 2)), Span::new(0, 7));
+
+        let span = Span::new(3, 6);
+        assert_eq!(span.len(), 3);
+        assert!(span.contains(3) && span.contains(5) && !span.contains(6) && !span.contains(2));
+        assert!(Span::new(4, 4).is_empty() && !Span::new(4, 4).contains(4));
+    }
+}
@@ -0,0 +1,389 @@
+//! Static checks over a DFA that point at likely mistakes in the transition table, and queries
+//! about the language it accepts.
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use crate::lexer::DFA;
+use crate::ops::TRAP_STATE_NAME;
+
+/// A problem, or a possible improvement, found by `DFA::validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DfaWarning {
+    /// Two accept states share a label and have the same outgoing transitions, so they could be merged.
+    MergeCandidates {
+        first: usize,
+        second: usize,
+        label: String,
+    },
+    /// No path from the start state leads to this accept state, so its tokens can never match.
+    UnreachableAccept {
+        state: usize,
+        label: String,
+    },
+    /// A non-accepting state without outgoing transitions: a match entering it always falls back
+    /// to an earlier accept, typically because a transition row is missing. States named
+    /// `TRAP_STATE_NAME` are deliberate traps and are not reported.
+    NonAcceptingSink {
+        state: usize,
+    },
+    /// A state with outgoing transitions that is not the start state and that no other state has
+    /// a transition to, so its rows can never be used; often a mistyped `From` in the CSV.
+    /// Self-loops do not count as incoming transitions.
+    OrphanSourceState {
+        state: usize,
+    },
+}
+
+impl DfaWarning {
+    /// Describes the warning, naming states through the DFA's state-name mapping.
+    pub fn describe(&self, dfa: &DFA) -> String {
+        match self {
+            DfaWarning::MergeCandidates { first, second, label } => format!(
+                "accept states {} and {} both accept \"{}\" with identical transitions and could be merged",
+                dfa.state_name(*first),
+                dfa.state_name(*second),
+                label
+            ),
+            DfaWarning::UnreachableAccept { state, label } => format!(
+                "accept state {} for \"{}\" cannot be reached from the start state",
+                dfa.state_name(*state),
+                label
+            ),
+            DfaWarning::NonAcceptingSink { state } => format!(
+                "state {} is not accepting and has no outgoing transitions, so matches entering it always fail",
+                dfa.state_name(*state)
+            ),
+            DfaWarning::OrphanSourceState { state } => format!(
+                "state {} has outgoing transitions but no transition leads to it and it is not the start state",
+                dfa.state_name(*state)
+            ),
+        }
+    }
+}
+
+/// Facts about the strings a DFA accepts, from `DFA::language_info`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LanguageInfo {
+    /// Whether only finitely many strings are accepted, i.e. no cycle lies on a path from the
+    /// start state to an accept state.
+    pub finite: bool,
+    /// Length in characters of the shortest accepted string, or `None` when nothing is accepted.
+    pub shortest: Option<usize>,
+    /// Whether the empty string is accepted, i.e. the start state is accepting.
+    pub accepts_empty: bool,
+}
+
+/// What a state's transition reads: one character, an inclusive character range or a
+/// negated class with its sorted excluded characters.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Input {
+    Char(char),
+    Interval(char, char),
+    Negated(Vec<char>),
+}
+
+/// Where a state's transition on a character leads, with self-loops kept relative so that
+/// two states looping on themselves compare equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Target {
+    Itself,
+    State(usize),
+}
+
+/// The outgoing transitions of a state, sorted by input.
+type Signature = Vec<(Input, Target)>;
+
+impl DFA {
+    /// Runs the static checks and returns the warnings found, grouped by kind and ordered by
+    /// state id within a kind.
+    pub fn validate(&self) -> Vec<DfaWarning> {
+        let mut warnings = Vec::new();
+        self.check_merge_candidates(&mut warnings);
+        self.check_unreachable_accepts(&mut warnings);
+        self.check_sinks(&mut warnings);
+        self.check_orphan_sources(&mut warnings);
+        warnings
+    }
+
+    /// The states a breadth-first search from the start state reaches, the start state included.
+    pub fn reachable_states(&self) -> BTreeSet<usize> {
+        let mut reachable = BTreeSet::from([self.start]);
+        let mut queue = VecDeque::from([self.start]);
+        while let Some(state) = queue.pop_front() {
+            for (_, target) in self.outgoing(state) {
+                if let Target::State(to) = target {
+                    if reachable.insert(to) {
+                        queue.push_back(to);
+                    }
+                }
+            }
+        }
+        reachable
+    }
+
+    /// Whether the language is finite, the length of its shortest string and whether it holds the
+    /// empty string. Only states both reachable from the start state and leading to an accept
+    /// state are considered, so loops in dead ends do not make the language infinite.
+    pub fn language_info(&self) -> LanguageInfo {
+        let targets = |state: usize| -> Vec<usize> {
+            let edges = self.outgoing(state).into_iter();
+            edges.map(|(_, target)| if let Target::State(to) = target { to } else { state }).collect()
+        };
+        let reachable = self.reachable_states();
+        let mut shortest = None;
+        let mut distance = BTreeMap::from([(self.start, 0)]);
+        let mut queue = VecDeque::from([self.start]);
+        while let Some(state) = queue.pop_front() {
+            if shortest.is_none() && self.accept.contains_key(&state) {
+                shortest = Some(distance[&state]);
+            }
+            for to in targets(state) {
+                if !distance.contains_key(&to) {
+                    distance.insert(to, distance[&state] + 1);
+                    queue.push_back(to);
+                }
+            }
+        }
+
+        // The states leading to an accept state, found backwards from the accept states.
+        let mut incoming: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for &state in &reachable {
+            for to in targets(state) {
+                incoming.entry(to).or_default().push(state);
+            }
+        }
+        let mut useful: BTreeSet<usize> = self.accept.keys().copied().filter(|state| reachable.contains(state)).collect();
+        let mut pending: Vec<usize> = useful.iter().copied().collect();
+        while let Some(state) = pending.pop() {
+            for &from in incoming.get(&state).into_iter().flatten() {
+                if useful.insert(from) {
+                    pending.push(from);
+                }
+            }
+        }
+
+        // A depth-first search for a back edge among the useful states.
+        let mut finished = BTreeSet::new();
+        let mut on_path = BTreeSet::new();
+        let mut finite = true;
+        for &root in &useful {
+            if finished.contains(&root) || !finite {
+                continue;
+            }
+            let mut stack = vec![(root, targets(root).into_iter())];
+            on_path.insert(root);
+            while let Some((state, next)) = stack.last_mut() {
+                match next.find(|to| useful.contains(to)) {
+                    Some(to) if on_path.contains(&to) => {
+                        finite = false;
+                        break;
+                    }
+                    Some(to) if !finished.contains(&to) => {
+                        on_path.insert(to);
+                        stack.push((to, targets(to).into_iter()));
+                    }
+                    Some(_) => {}
+                    None => {
+                        on_path.remove(state);
+                        finished.insert(*state);
+                        stack.pop();
+                    }
+                }
+            }
+        }
+        LanguageInfo { finite, shortest, accepts_empty: self.accept.contains_key(&self.start) }
+    }
+
+    fn outgoing(&self, state: usize) -> Signature {
+        let target = |to: usize| if to == state { Target::Itself } else { Target::State(to) };
+        let mut edges: Signature = self
+            .transitions
+            .iter()
+            .filter(|(&(from, _), _)| from == state)
+            .map(|(&(_, ch), &to)| (Input::Char(ch), target(to)))
+            .collect();
+        for &(low, high, to) in self.intervals.get(&state).map(Vec::as_slice).unwrap_or_default() {
+            edges.push((Input::Interval(low, high), target(to)));
+        }
+        if let Some((excluded, to)) = self.negated.get(&state) {
+            edges.push((Input::Negated(excluded.clone()), target(*to)));
+        }
+        edges.sort();
+        edges
+    }
+
+    fn check_merge_candidates(&self, warnings: &mut Vec<DfaWarning>) {
+        let mut groups: BTreeMap<(&String, Signature), Vec<usize>> = BTreeMap::new();
+        let mut accept: Vec<(&usize, &String)> = self.accept.iter().collect();
+        accept.sort();
+        for (&state, label) in accept {
+            groups.entry((label, self.outgoing(state))).or_default().push(state);
+        }
+        let mut candidates = Vec::new();
+        for ((label, _), states) in groups {
+            for (index, &first) in states.iter().enumerate() {
+                for &second in &states[index + 1..] {
+                    candidates.push((first, second, label.clone()));
+                }
+            }
+        }
+        candidates.sort();
+        warnings.extend(candidates.into_iter().map(|(first, second, label)| DfaWarning::MergeCandidates { first, second, label }));
+    }
+
+    fn check_unreachable_accepts(&self, warnings: &mut Vec<DfaWarning>) {
+        let reachable = self.reachable_states();
+        let mut unreachable: Vec<(&usize, &String)> =
+            self.accept.iter().filter(|(state, _)| !reachable.contains(state)).collect();
+        unreachable.sort();
+        for (&state, label) in unreachable {
+            warnings.push(DfaWarning::UnreachableAccept { state, label: label.clone() });
+        }
+    }
+
+    fn check_sinks(&self, warnings: &mut Vec<DfaWarning>) {
+        for state in self.states() {
+            let marked_trap = self.state_names.get(&state).is_some_and(|name| name == TRAP_STATE_NAME);
+            if !self.accept.contains_key(&state) && !marked_trap && self.outgoing(state).is_empty() {
+                warnings.push(DfaWarning::NonAcceptingSink { state });
+            }
+        }
+    }
+
+    fn check_orphan_sources(&self, warnings: &mut Vec<DfaWarning>) {
+        let states = self.states();
+        let mut targeted = BTreeSet::from([self.start]);
+        for &state in &states {
+            targeted.extend(self.outgoing(state).into_iter().filter_map(|(_, target)| match target {
+                Target::State(to) => Some(to),
+                Target::Itself => None,
+            }));
+        }
+        for state in states {
+            if !targeted.contains(&state) && !self.outgoing(state).is_empty() {
+                warnings.push(DfaWarning::OrphanSourceState { state });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    #[test]
+    fn test_validate_reports_mergeable_accept_states() {
+        // "a" and "b" both lead to an "id" state that loops on letters.
+        let mut transitions = HashMap::new();
+        transitions.insert((0, 'a'), 1);
+        transitions.insert((0, 'b'), 2);
+        transitions.insert((0, '1'), 3);
+        for state in [1, 2] {
+            transitions.insert((state, 'a'), state);
+            transitions.insert((state, 'b'), state);
+        }
+        let mut accept = HashMap::new();
+        accept.insert(1, "id".to_string());
+        accept.insert(2, "id".to_string());
+        accept.insert(3, "integer".to_string());
+        let dfa = DFA::new(transitions, 0, accept);
+
+        assert_eq!(dfa.validate(), vec![DfaWarning::MergeCandidates {
+            first: 1,
+            second: 2,
+            label: "id".to_string(),
+        }]);
+    }
+
+    #[test]
+    fn test_validate_reports_unreachable_accept_states() {
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_transition(0, 'a', 1).add_interval(1, '0', '9', 2).unwrap();
+        // State 4 is only reachable from the disconnected state 3.
+        dfa.add_transition(3, 'x', 4);
+        dfa.add_accept(1, "a").add_accept(2, "a0").add_accept(4, "x").add_accept(5, "orphan");
+
+        assert_eq!(dfa.reachable_states(), BTreeSet::from([0, 1, 2]));
+        let warnings = dfa.validate();
+        assert_eq!(warnings, [
+            DfaWarning::UnreachableAccept { state: 4, label: "x".to_string() },
+            DfaWarning::UnreachableAccept { state: 5, label: "orphan".to_string() },
+            DfaWarning::OrphanSourceState { state: 3 },
+        ]);
+        assert_eq!(warnings[1].describe(&dfa), "accept state 5 for \"orphan\" cannot be reached from the start state");
+    }
+
+    #[test]
+    fn test_validate_reports_non_accepting_sinks() {
+        use crate::lexer::try_process_input;
+
+        // Meant to accept `a` and `abc`, but the row from state 2 on `c` is missing.
+        let mut dfa = DFA::new(HashMap::new(), 0, HashMap::new());
+        dfa.add_transition(0, 'a', 1).add_transition(1, 'b', 2);
+        dfa.add_accept(1, "a");
+        let err = try_process_input(&dfa, "abc").unwrap_err();
+        assert_eq!(err.position(), (1, 2));
+        assert_eq!(try_process_input(&dfa, "ab").unwrap_err().character(), Some('b'));
+        assert_eq!(dfa.validate(), [DfaWarning::NonAcceptingSink { state: 2 }]);
+
+        let named = dfa.clone().with_state_names(HashMap::from([(2, TRAP_STATE_NAME.to_string())]));
+        assert!(named.validate().is_empty());
+        assert!(dfa.complete(&['a', 'b', 'c']).validate().is_empty());
+    }
+
+    #[test]
+    fn test_language_info_of_finite_and_starred_languages() {
+        // `if` and `in`, plus a dead end looping on `x` that must not count as a cycle.
+        let mut keywords = DFA::new(HashMap::new(), 0, HashMap::new());
+        keywords.add_transition(0, 'i', 1).add_transition(1, 'f', 2).add_transition(1, 'n', 3);
+        keywords.add_transition(0, 'x', 4).add_transition(4, 'x', 4);
+        keywords.add_accept(2, "if").add_accept(3, "in");
+        assert_eq!(keywords.language_info(), LanguageInfo { finite: true, shortest: Some(2), accepts_empty: false });
+
+        // `ab*` and `(ab)*` over two states.
+        let mut starred = DFA::new(HashMap::new(), 0, HashMap::new());
+        starred.add_transition(0, 'a', 1).add_transition(1, 'b', 1).add_accept(1, "ab*");
+        assert_eq!(starred.language_info(), LanguageInfo { finite: false, shortest: Some(1), accepts_empty: false });
+        let mut pairs = DFA::new(HashMap::new(), 0, HashMap::new());
+        pairs.add_transition(0, 'a', 1).add_transition(1, 'b', 0).add_accept(0, "(ab)*");
+        assert_eq!(pairs.language_info(), LanguageInfo { finite: false, shortest: Some(0), accepts_empty: true });
+
+        let empty = DFA::new(HashMap::new(), 0, HashMap::new());
+        assert_eq!(empty.language_info(), LanguageInfo { finite: true, shortest: None, accepts_empty: false });
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_validate_reports_orphan_source_states() {
+        use crate::lexer::read_transitions_from_csv;
+
+        // Row `7,c,3` was meant as `2,c,3`; state 7 only loops on itself besides.
+        let transitions = read_transitions_from_csv("tests/orphan_source_transitions.csv").unwrap();
+        let accept = HashMap::from([(1, "a".to_string()), (3, "abc".to_string())]);
+        let dfa = DFA::new(transitions, 0, accept);
+        let warnings = dfa.validate();
+        // The typo shows up three ways: `abc` is unreachable, 2 is a dead end and 7 an orphan source.
+        assert_eq!(warnings, [
+            DfaWarning::UnreachableAccept { state: 3, label: "abc".to_string() },
+            DfaWarning::NonAcceptingSink { state: 2 },
+            DfaWarning::OrphanSourceState { state: 7 },
+        ]);
+        assert_eq!(
+            warnings[2].describe(&dfa),
+            "state 7 has outgoing transitions but no transition leads to it and it is not the start state"
+        );
+    }
+
+    #[test]
+    fn test_validate_keeps_states_with_different_transitions() {
+        let mut transitions = HashMap::new();
+        transitions.insert((0, 'a'), 1);
+        transitions.insert((0, 'b'), 2);
+        transitions.insert((1, 'a'), 1);
+        let mut accept = HashMap::new();
+        accept.insert(1, "id".to_string());
+        accept.insert(2, "id".to_string());
+        let dfa = DFA::new(transitions, 0, accept);
+
+        assert!(dfa.validate().is_empty());
+    }
+}
@@ -0,0 +1,3 @@
+pub fn main() void {
+    var x = ;
+}